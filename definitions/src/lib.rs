@@ -1,10 +1,18 @@
 //! Definitions -- A tiny interface for HLA-typing problem.
 //! Roughly speaking, we incorporate with other programs, pass messages, or interact with other CLI via JSON object format. Specifically, the message is encoded only one, possibly large, structure named [DataSet](DataSet)
+//! The DataSet can also be serialized into a compact binary format(bincode) with a header [BINARY_HEADER](BINARY_HEADER). See [DataSet::to_binary](DataSet::to_binary) and [DataSet::from_binary](DataSet::from_binary).
+//! Serialized DataSets written by older versions are upgraded when loaded. See [migration](migration).
 
+pub mod migration;
+pub use migration::SCHEMA_VERSION;
+pub mod validate;
 use serde::{Deserialize, Serialize};
+pub use validate::Violation;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DataSet {
+    /// The version of the schema. It should be the first field, as the binary format relies on it.
+    pub schema_version: u64,
     /// The path to the input file.
     pub input_file: String,
     /// If Some(x), it is the estimated coverage per haplotype.
@@ -28,8 +36,14 @@ pub struct DataSet {
     pub assignments: Vec<Assignment>,
     /// The type of the reads.
     pub read_type: ReadType,
+    /// The stages applied to this dataset, in order.
+    pub provenance: Vec<Provenance>,
 }
 
+/// The header of the binary serialization of a DataSet.
+/// As a JSON object never starts with this sequence, we can distinguish these two formats by peeking the first few bytes.
+pub const BINARY_HEADER: &[u8] = b"JTK\x00BIN\x01";
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Copy)]
 pub enum ReadType {
     CCS,
@@ -47,6 +61,7 @@ impl DataSet {
             _ => ReadType::None,
         };
         Self {
+            schema_version: SCHEMA_VERSION,
             input_file: input_file.to_string(),
             coverage: None,
             raw_reads,
@@ -56,6 +71,7 @@ impl DataSet {
             hic_edges: vec![],
             assignments: vec![],
             read_type,
+            provenance: vec![],
         }
     }
     #[allow(clippy::too_many_arguments)]
@@ -71,6 +87,7 @@ impl DataSet {
         read_type: ReadType,
     ) -> Self {
         Self {
+            schema_version: SCHEMA_VERSION,
             input_file,
             coverage,
             raw_reads,
@@ -80,7 +97,62 @@ impl DataSet {
             hic_edges,
             assignments,
             read_type,
+            provenance: vec![],
+        }
+    }
+    /// Serialize this dataset into the compact binary format, starting with [BINARY_HEADER](BINARY_HEADER).
+    pub fn to_binary<W: std::io::Write>(&self, mut wtr: W) -> std::io::Result<()> {
+        wtr.write_all(BINARY_HEADER)?;
+        bincode::serialize_into(&mut wtr, self).map_err(std::io::Error::other)?;
+        wtr.flush()
+    }
+    /// Deserialize a dataset from the binary format. The reader should start with [BINARY_HEADER](BINARY_HEADER).
+    /// As the binary format is not self-describing, only the current schema version can be loaded.
+    pub fn from_binary<R: std::io::Read>(mut rdr: R) -> std::io::Result<Self> {
+        use std::io::{Error, ErrorKind, Read};
+        let mut header = vec![0; BINARY_HEADER.len()];
+        rdr.read_exact(&mut header)?;
+        if header != BINARY_HEADER {
+            let msg = "The input does not start with the header of binary DataSet.";
+            return Err(Error::new(ErrorKind::InvalidData, msg));
+        }
+        // The schema version is the first field, encoded in 8-byte little endian.
+        let mut version = [0; 8];
+        rdr.read_exact(&mut version)?;
+        let schema_version = u64::from_le_bytes(version);
+        migration::check_version(schema_version)?;
+        if schema_version < SCHEMA_VERSION {
+            let msg = format!(
+                "The binary DataSet has schema version {}, which can not be upgraded. Please convert it into JSON by the jtk that wrote it.",
+                schema_version
+            );
+            return Err(Error::new(ErrorKind::InvalidData, msg));
+        }
+        let rdr = std::io::Cursor::new(version).chain(rdr);
+        bincode::deserialize_from(rdr).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+    /// Deserialize a dataset from JSON. If the dataset was written by an older version, it is upgraded.
+    /// If the dataset is newer than this program, return an error.
+    pub fn from_json<R: std::io::Read>(mut rdr: R) -> std::io::Result<Self> {
+        use std::io::{BufReader, Error, ErrorKind, Read};
+        // The datasets of the current version are deserialized from the stream directly.
+        // Only older ones are loaded as JSON objects to be upgraded.
+        let mut prefix = vec![];
+        (&mut rdr)
+            .take(migration::PEEK_LEN as u64)
+            .read_to_end(&mut prefix)?;
+        let version = migration::peek_version(&prefix);
+        let rdr = BufReader::new(std::io::Cursor::new(prefix).chain(rdr));
+        if let Some(version) = version {
+            migration::check_version(version)?;
         }
+        if version == Some(SCHEMA_VERSION) {
+            return serde_json::from_reader(rdr).map_err(|e| Error::new(ErrorKind::InvalidData, e));
+        }
+        let mut dataset: serde_json::Value =
+            serde_json::from_reader(rdr).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        migration::migrate(&mut dataset)?;
+        serde_json::from_value(dataset).map_err(|e| Error::new(ErrorKind::InvalidData, e))
     }
     /// Sanity check function. Call it to ensure that some properties indeed holds.
     /// Currently, the following properties are checked.
@@ -120,12 +192,19 @@ pub struct RawRead {
     /// Sequence. It is a string on an alphabet of A,C,G,T,a,c,g,t.
     /// (i.e., lowercase included)
     pub seq: String,
+    /// Per-base quality in the Phred+33 encoding, as in the fastq file.
+    /// If the input is a fasta file, it is None.
+    pub qual: Option<String>,
 }
 
 impl RawRead {
     pub fn seq(&self) -> &[u8] {
         self.seq.as_bytes()
     }
+    /// Return the quality string in the Phred+33 encoding, if any.
+    pub fn qual(&self) -> Option<&[u8]> {
+        self.qual.as_ref().map(|q| q.as_bytes())
+    }
 }
 
 impl std::fmt::Display for RawRead {
@@ -134,6 +213,7 @@ impl std::fmt::Display for RawRead {
     }
 }
 
+/// A Hi-C read pair. `pair1` and `pair2` are the IDs of the mates.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HiCPair {
     pub pair1: u64,
@@ -361,6 +441,9 @@ pub struct Node {
     pub seq: String,
     pub is_forward: bool,
     pub cigar: Vec<Op>,
+    /// Per-base quality of `seq` in the Phred+33 encoding. Already reversed if `is_forward` is false.
+    /// None if the original read does not have any quality.
+    pub qual: Option<String>,
 }
 
 impl std::fmt::Display for Node {
@@ -381,6 +464,10 @@ impl Node {
     pub fn seq(&self) -> &[u8] {
         self.seq.as_bytes()
     }
+    /// Return the quality string of `seq` in the Phred+33 encoding, if any.
+    pub fn qual(&self) -> Option<&[u8]> {
+        self.qual.as_ref().map(|q| q.as_bytes())
+    }
     pub fn original_seq(&self) -> String {
         if self.is_forward {
             self.seq.clone()
@@ -459,11 +546,15 @@ pub enum Op {
     Ins(usize),
 }
 
+/// A Hi-C pair linking two nodes. `pair1` and `pair2` are the units the mates hit,
+/// and `cluster1` and `cluster2` are the clusters of them.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HiCEdge {
     pub pair_id: u64,
     pub pair1: u64,
     pub pair2: u64,
+    pub cluster1: u64,
+    pub cluster2: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -478,10 +569,97 @@ impl Assignment {
     }
 }
 
+/// A record of a stage applied to a DataSet.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Provenance {
+    /// The name of the stage, such as `local_clustering`.
+    pub stage: String,
+    /// The version of jtk.
+    pub version: String,
+    /// The configuration of the stage in JSON, usually its `*Config` serialized.
+    /// It is kept as a string so that the binary format can hold it.
+    pub config: String,
+    /// When the stage finished, in seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub threads: usize,
+    pub seed: Option<u64>,
+}
+
+impl Provenance {
+    /// A record of a stage finished now.
+    pub fn new(
+        stage: &str,
+        version: &str,
+        config: &serde_json::Value,
+        threads: usize,
+        seed: Option<u64>,
+    ) -> Self {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+        Self {
+            stage: stage.to_string(),
+            version: version.to_string(),
+            config: config.to_string(),
+            timestamp,
+            threads,
+            seed,
+        }
+    }
+}
+
+impl std::fmt::Display for Provenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let seed = match self.seed {
+            Some(seed) => seed.to_string(),
+            None => "-".to_string(),
+        };
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            self.timestamp, self.stage, self.version, self.threads, seed, self.config
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+    #[test]
+    fn binary_roundtrip() {
+        let read = RawRead {
+            name: "read".to_string(),
+            desc: String::new(),
+            id: 0,
+            seq: "ACGT".to_string(),
+            qual: Some("!!II".to_string()),
+        };
+        let mut ds = DataSet::with_minimum_data("input.fa", vec![read], "CCS");
+        ds.coverage = Some(10.0);
+        ds.selected_chunks.push(Unit::new(0, "ACGT".to_string(), 2));
+        let mut buf = vec![];
+        ds.to_binary(&mut buf).unwrap();
+        assert!(buf.starts_with(BINARY_HEADER));
+        let recovered = DataSet::from_binary(buf.as_slice()).unwrap();
+        assert_eq!(recovered.coverage, ds.coverage);
+        assert_eq!(recovered.raw_reads[0].qual, ds.raw_reads[0].qual);
+        assert_eq!(recovered.selected_chunks, ds.selected_chunks);
+        assert_eq!(recovered.read_type, ds.read_type);
+        let config = serde_json::json!({"input": "input.fa", "read_type": "CCS"});
+        ds.provenance
+            .push(Provenance::new("entry", "0.1.0", &config, 1, None));
+        let mut buf = vec![];
+        ds.to_binary(&mut buf).unwrap();
+        let recovered = DataSet::from_binary(buf.as_slice()).unwrap();
+        assert_eq!(recovered.provenance, ds.provenance);
+        let recovered: serde_json::Value =
+            serde_json::from_str(&recovered.provenance[0].config).unwrap();
+        assert_eq!(recovered, config);
+        assert!(DataSet::from_binary(&b"{}"[..]).is_err());
+    }
 }
@@ -0,0 +1,165 @@
+//! Migration of serialized DataSets.
+//! Each DataSet carries `schema_version`. When we change the layout of the DataSet, we increment
+//! [SCHEMA_VERSION](SCHEMA_VERSION) and append a function upgrading the JSON object of the previous version
+//! into [MIGRATIONS](MIGRATIONS). Files without `schema_version` are regarded as version 0.
+use serde_json::Value;
+use std::io::{Error, ErrorKind};
+
+/// The current version of the schema.
+/// Version 0: files written before versioning.
+/// Version 1: `schema_version`, and per-base qualities of raw reads and nodes.
+/// Version 2: clusters of Hi-C edges.
+/// Version 3: provenance of the stages.
+pub const SCHEMA_VERSION: u64 = 3;
+
+/// Upgrading functions. `MIGRATIONS[i]` upgrades a JSON object of version `i` into version `i+1`.
+const MIGRATIONS: [fn(&mut Value); SCHEMA_VERSION as usize] = [v0_to_v1, v1_to_v2, v2_to_v3];
+
+/// Return the schema version of the serialized DataSet. If there is no version, it is 0.
+pub fn schema_version(dataset: &Value) -> u64 {
+    dataset
+        .get("schema_version")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(0)
+}
+
+/// The length of the prefix of JSON given to [peek_version](peek_version).
+pub const PEEK_LEN: usize = 64;
+
+/// Return the schema version if the JSON object starts with `schema_version`, as the ones written by
+/// this program do. Otherwise, return None, as the version is unknown until the entire object is read.
+pub fn peek_version(prefix: &[u8]) -> Option<u64> {
+    let prefix = String::from_utf8_lossy(prefix);
+    let rest = prefix.trim_start().strip_prefix('{')?.trim_start();
+    let rest = rest.strip_prefix("\"schema_version\"")?.trim_start();
+    let rest = rest.strip_prefix(':')?.trim_start();
+    // The number should end within the prefix.
+    let end = rest.find(|c: char| !c.is_ascii_digit())?;
+    rest[..end].parse().ok()
+}
+
+/// Check the version of the DataSet. If the version is newer than this program, return an error.
+pub fn check_version(version: u64) -> std::io::Result<()> {
+    if SCHEMA_VERSION < version {
+        let msg = format!(
+            "The DataSet has schema version {}, but this jtk supports up to version {}. Please use a newer jtk.",
+            version, SCHEMA_VERSION
+        );
+        Err(Error::new(ErrorKind::InvalidData, msg))
+    } else {
+        Ok(())
+    }
+}
+
+/// Upgrade the JSON object of a DataSet into the current version.
+pub fn migrate(dataset: &mut Value) -> std::io::Result<()> {
+    if !dataset.is_object() {
+        let msg = "The DataSet should be a JSON object.";
+        return Err(Error::new(ErrorKind::InvalidData, msg));
+    }
+    let version = schema_version(dataset);
+    check_version(version)?;
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        debug_assert_eq!(schema_version(dataset), from as u64);
+        migration(dataset);
+        dataset["schema_version"] = Value::from(from as u64 + 1);
+    }
+    Ok(())
+}
+
+// Insert `value` into `object[key]` if `object` is an object without `key`.
+fn insert_if_absent(object: &mut Value, key: &str, value: Value) {
+    if let Some(object) = object.as_object_mut() {
+        object.entry(key).or_insert(value);
+    }
+}
+
+fn v0_to_v1(dataset: &mut Value) {
+    insert_if_absent(dataset, "coverage", Value::Null);
+    insert_if_absent(dataset, "read_type", Value::from("None"));
+    let lists = [
+        "raw_reads",
+        "hic_pairs",
+        "selected_chunks",
+        "encoded_reads",
+        "hic_edges",
+        "assignments",
+    ];
+    for key in lists.iter() {
+        insert_if_absent(dataset, key, Value::Array(vec![]));
+    }
+    if let Some(reads) = dataset["raw_reads"].as_array_mut() {
+        for read in reads.iter_mut() {
+            insert_if_absent(read, "qual", Value::Null);
+        }
+    }
+    if let Some(reads) = dataset["encoded_reads"].as_array_mut() {
+        let nodes = reads
+            .iter_mut()
+            .filter_map(|read| read.get_mut("nodes"))
+            .filter_map(|nodes| nodes.as_array_mut())
+            .flat_map(|nodes| nodes.iter_mut());
+        for node in nodes {
+            insert_if_absent(node, "qual", Value::Null);
+        }
+    }
+}
+
+fn v1_to_v2(dataset: &mut Value) {
+    if let Some(edges) = dataset["hic_edges"].as_array_mut() {
+        for edge in edges.iter_mut() {
+            insert_if_absent(edge, "cluster1", Value::from(0));
+            insert_if_absent(edge, "cluster2", Value::from(0));
+        }
+    }
+}
+
+fn v2_to_v3(dataset: &mut Value) {
+    insert_if_absent(dataset, "provenance", Value::Array(vec![]));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn migrate_unversioned() {
+        let old = r#"{"input_file":"reads.fa","raw_reads":[{"name":"r","desc":"","id":0,"seq":"ACGT"}],
+        "hic_pairs":[],"selected_chunks":[],"encoded_reads":[],"hic_edges":[{"pair_id":0,"pair1":1,"pair2":2}],
+        "assignments":[],"read_type":"CLR"}"#;
+        let ds = crate::DataSet::from_json(old.as_bytes()).unwrap();
+        assert_eq!(ds.schema_version, SCHEMA_VERSION);
+        assert_eq!(ds.coverage, None);
+        assert_eq!(ds.raw_reads[0].qual, None);
+        assert_eq!(ds.read_type, crate::ReadType::CLR);
+        assert_eq!(ds.hic_edges[0].cluster1, 0);
+        assert!(ds.provenance.is_empty());
+    }
+    #[test]
+    fn peek_version_test() {
+        assert_eq!(
+            peek_version(br#"{"schema_version":3,"input_file":"#),
+            Some(3)
+        );
+        assert_eq!(peek_version(b"{\n  \"schema_version\": 12,\n"), Some(12));
+        assert_eq!(
+            peek_version(br#"{"input_file":"reads.fa","schema_version":3}"#),
+            None
+        );
+        assert_eq!(peek_version(br#"{"schema_version":3"#), None);
+        let ds = crate::DataSet::with_minimum_data("reads.fa", vec![], "CCS");
+        let json = serde_json::to_vec_pretty(&ds).unwrap();
+        assert_eq!(peek_version(&json[..PEEK_LEN]), Some(SCHEMA_VERSION));
+        let ds = crate::DataSet::from_json(json.as_slice()).unwrap();
+        assert_eq!(ds.input_file, "reads.fa");
+    }
+    #[test]
+    fn reject_newer() {
+        let new = format!(
+            r#"{{"schema_version":{},"input_file":"reads.fa"}}"#,
+            SCHEMA_VERSION + 1
+        );
+        let error = crate::DataSet::from_json(new.as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(error.to_string().contains("newer jtk"));
+    }
+}
@@ -0,0 +1,201 @@
+//! Invariant checker of DataSets.
+//! Unlike [sanity_check](crate::DataSet::sanity_check), [validate](crate::DataSet::validate) reports all the
+//! violations it finds, with the IDs of the reads and units involved.
+use crate::DataSet;
+use std::collections::{HashMap, HashSet};
+
+/// A violation of the invariants of a DataSet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    DuplicateReadId(u64),
+    DuplicateEncodedReadId(u64),
+    DuplicateUnitId(u64),
+    DuplicateAssignment(u64),
+    /// An encoded read without its raw read.
+    MissingRawRead(u64),
+    /// An encoded read which does not recover its raw read.
+    Unrecoverable(u64),
+    /// (read, unit). A node whose unit is not in the selected chunks.
+    MissingUnit(u64, u64),
+    /// (read, unit, cluster, cluster_num of the unit).
+    ClusterOutOfRange(u64, u64, u64, usize),
+    /// (read, # of nodes, # of edges). There should be exactly one edge between adjacent nodes.
+    EdgeCount(u64, usize, usize),
+    /// (read, the index of the edge). The edge does not agree with the units of the adjacent nodes.
+    EdgeMismatch(u64, usize),
+    /// (read, unit). A node with lowercase bases.
+    LowercaseNode(u64, u64),
+    /// An assignment to a read not in the raw reads.
+    DanglingAssignment(u64),
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Violation::*;
+        match self {
+            DuplicateReadId(id) => write!(f, "Duplicate raw read ID:{}", id),
+            DuplicateEncodedReadId(id) => write!(f, "Duplicate encoded read ID:{}", id),
+            DuplicateUnitId(id) => write!(f, "Duplicate unit ID:{}", id),
+            DuplicateAssignment(id) => write!(f, "Duplicate assignments to read {}", id),
+            MissingRawRead(id) => write!(f, "Encoded read {} has no raw read", id),
+            Unrecoverable(id) => write!(f, "Encoded read {} does not recover the raw read", id),
+            MissingUnit(id, unit) => write!(
+                f,
+                "Read {} has unit {} not in the selected chunks",
+                id, unit
+            ),
+            ClusterOutOfRange(id, unit, cl, num) => write!(
+                f,
+                "Read {} has cluster {} on unit {}, but the unit has {} clusters",
+                id, cl, unit, num
+            ),
+            EdgeCount(id, nodes, edges) => {
+                write!(f, "Read {} has {} nodes but {} edges", id, nodes, edges)
+            }
+            EdgeMismatch(id, idx) => write!(
+                f,
+                "The {}-th edge of read {} disagrees with the adjacent nodes",
+                idx, id
+            ),
+            LowercaseNode(id, unit) => {
+                write!(
+                    f,
+                    "Read {} has lowercase bases in the node on unit {}",
+                    id, unit
+                )
+            }
+            DanglingAssignment(id) => write!(f, "Assignment to missing read {}", id),
+        }
+    }
+}
+
+// Return the elements appearing more than once.
+fn duplicates<I: Iterator<Item = u64>>(ids: I) -> Vec<u64> {
+    let mut seen = HashSet::new();
+    let mut dups: Vec<_> = ids.filter(|&id| !seen.insert(id)).collect();
+    dups.sort_unstable();
+    dups.dedup();
+    dups
+}
+
+impl DataSet {
+    /// Check the invariants of this dataset, and return all the violations.
+    /// In contrast to `sanity_check`, the existence of the input file is not checked,
+    /// as datasets are often moved between machines.
+    pub fn validate(&self) -> Vec<Violation> {
+        use Violation::*;
+        let mut violations = vec![];
+        let raw_reads = duplicates(self.raw_reads.iter().map(|r| r.id));
+        violations.extend(raw_reads.into_iter().map(DuplicateReadId));
+        let encoded_reads = duplicates(self.encoded_reads.iter().map(|r| r.id));
+        violations.extend(encoded_reads.into_iter().map(DuplicateEncodedReadId));
+        let units = duplicates(self.selected_chunks.iter().map(|u| u.id));
+        violations.extend(units.into_iter().map(DuplicateUnitId));
+        let assignments = duplicates(self.assignments.iter().map(|a| a.id));
+        violations.extend(assignments.into_iter().map(DuplicateAssignment));
+        let raw_seqs: HashMap<_, _> = self.raw_reads.iter().map(|r| (r.id, r.seq())).collect();
+        let units: HashMap<_, _> = self.selected_chunks.iter().map(|u| (u.id, u)).collect();
+        for read in self.encoded_reads.iter() {
+            let id = read.id;
+            match raw_seqs.get(&id) {
+                None => violations.push(MissingRawRead(id)),
+                Some(seq) => {
+                    let orig = seq.iter().map(u8::to_ascii_uppercase);
+                    let recovered = read.recover_raw_read();
+                    let recovered = recovered.iter().map(u8::to_ascii_uppercase);
+                    if !orig.eq(recovered) {
+                        violations.push(Unrecoverable(id));
+                    }
+                }
+            }
+            for node in read.nodes.iter() {
+                match units.get(&node.unit) {
+                    None => violations.push(MissingUnit(id, node.unit)),
+                    // Units not clustered yet have zero clusters, while nodes on them have cluster 0.
+                    Some(unit) if unit.cluster_num.max(1) as u64 <= node.cluster => {
+                        let cl = ClusterOutOfRange(id, node.unit, node.cluster, unit.cluster_num);
+                        violations.push(cl);
+                    }
+                    Some(_) => {}
+                }
+                if node.seq().iter().any(u8::is_ascii_lowercase) {
+                    violations.push(LowercaseNode(id, node.unit));
+                }
+            }
+            if !read.nodes.is_empty() && read.nodes.len() != read.edges.len() + 1 {
+                let (nodes, edges) = (read.nodes.len(), read.edges.len());
+                violations.push(EdgeCount(id, nodes, edges));
+            } else {
+                let mismatches = read
+                    .edges
+                    .iter()
+                    .zip(read.nodes.windows(2))
+                    .enumerate()
+                    .filter(|(_, (e, w))| e.from != w[0].unit || e.to != w[1].unit)
+                    .map(|(idx, _)| EdgeMismatch(id, idx));
+                violations.extend(mismatches);
+            }
+        }
+        let dangling = self
+            .assignments
+            .iter()
+            .filter(|a| !raw_seqs.contains_key(&a.id))
+            .map(|a| DanglingAssignment(a.id));
+        violations.extend(dangling);
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    #[test]
+    fn validate_test() {
+        let read = RawRead {
+            name: "read".to_string(),
+            desc: String::new(),
+            id: 0,
+            seq: "ACGTTT".to_string(),
+            qual: None,
+        };
+        let mut ds = DataSet::with_minimum_data("reads.fa", vec![read.clone(), read], "CLR");
+        ds.selected_chunks = vec![Unit::new(0, "ACG".to_string(), 2)];
+        let node = |unit: u64, cluster: u64, seq: &str| Node {
+            position_from_start: 0,
+            unit,
+            cluster,
+            seq: seq.to_string(),
+            qual: None,
+            is_forward: true,
+            cigar: vec![Op::Match(3)],
+        };
+        let edge = Edge {
+            from: 0,
+            to: 0,
+            offset: 0,
+            label: String::new(),
+        };
+        ds.encoded_reads = vec![EncodedRead {
+            id: 0,
+            original_length: 6,
+            leading_gap: vec![],
+            trailing_gap: vec![],
+            nodes: vec![node(0, 2, "ACG"), node(1, 0, "tac")],
+            edges: vec![edge],
+        }];
+        ds.assignments = vec![Assignment::new(0, 0), Assignment::new(3, 0)];
+        let violations = ds.validate();
+        use Violation::*;
+        let expected = vec![
+            DuplicateReadId(0),
+            Unrecoverable(0),
+            ClusterOutOfRange(0, 0, 2, 2),
+            MissingUnit(0, 1),
+            LowercaseNode(0, 1),
+            EdgeMismatch(0, 0),
+            DanglingAssignment(3),
+        ];
+        assert_eq!(violations, expected);
+    }
+}
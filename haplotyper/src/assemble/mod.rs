@@ -1,6 +1,7 @@
 pub mod copy_number;
 pub mod ditch_graph;
 pub mod string_graph;
+use crate::minimap2::Minimap2;
 use definitions::*;
 use ditch_graph::*;
 use gfa::GFA;
@@ -65,19 +66,20 @@ impl Graph {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct AssembleConfig {
-    threads: usize,
     to_polish: bool,
     window_size: usize,
+    // The aligner to map the reads onto the contigs.
+    aligner: Minimap2,
 }
 
 impl std::default::Default for AssembleConfig {
     fn default() -> Self {
         Self {
-            threads: 1,
             to_polish: false,
             window_size: 100,
+            aligner: default_minimap2(1),
         }
     }
 }
@@ -85,56 +87,48 @@ impl AssembleConfig {
     pub fn new(threads: usize, window_size: usize, to_polish: bool) -> Self {
         Self {
             window_size,
-            threads,
             to_polish,
+            aligner: default_minimap2(threads),
         }
     }
+    /// Replace the aligner, by default [default_minimap2](default_minimap2).
+    pub fn aligner(mut self, aligner: Minimap2) -> Self {
+        self.aligner = aligner;
+        self
+    }
+}
+
+/// The minimap2 backend to map reads onto contigs.
+pub fn default_minimap2(threads: usize) -> Minimap2 {
+    Minimap2::new(threads)
+        .preset("map-pb")
+        .args(&["--secondary=no", "-z", "600,400"])
 }
 
 pub trait Assemble {
     fn assemble_as_gfa(&self, c: &AssembleConfig) -> GFA;
     fn assemble_as_graph(&self, c: &AssembleConfig) -> Vec<Graph>;
+    /// Assemble as `assemble_as_gfa`, and align the reads used in polishing to the contigs.
+    /// The alignments of the last round of polishing are lifted over to the polished contigs, so the reads
+    /// are not aligned again. If `c.to_polish` is false, the reads are aligned to each contig once.
+    /// Each record has `ac`(the cluster of the assignment of the read) and `gi`(the ID of the group of the contig) tags.
+    fn assemble_with_alignments(
+        &self,
+        c: &AssembleConfig,
+    ) -> std::io::Result<(GFA, crate::sam::SamHeader, Vec<crate::sam::SamRecord>)>;
+    /// Split the contigs of `gfa`, the output of `assemble_as_gfa`, into haplotypes.
+    /// The i-th element is the contigs of the i-th haplotype. See [phase_blocks](phase_blocks).
+    /// The description of each record has the ID of the group(`gi:Z:`), the phase block(`ps:i:`),
+    /// and the coverage(`cv:i:`) and the copy number(`cp:i:`) of the segment, if any.
+    fn split_into_haplotypes(&self, gfa: &GFA) -> Vec<Vec<bio_utils::fasta::Record>>;
 }
 
 impl Assemble for DataSet {
     fn assemble_as_gfa(&self, c: &AssembleConfig) -> GFA {
-        let mut cluster_and_num: HashMap<_, u32> = HashMap::new();
-        for asn in self.assignments.iter() {
-            *cluster_and_num.entry(asn.cluster).or_default() += 1;
+        match assemble_gfa(self, c, false) {
+            Ok((gfa, _)) => gfa,
+            Err(why) => panic!("{:?}", why),
         }
-        debug!("There is {} clusters.", cluster_and_num.len());
-        debug!("Start assembly");
-        let header = gfa::Content::Header(gfa::Header::default());
-        let header = gfa::Record::from_contents(header, vec![]);
-        let mut cluster_and_num: Vec<_> = cluster_and_num.into_iter().collect();
-        cluster_and_num.sort_by_key(|x| x.1);
-        cluster_and_num.reverse();
-        let records: Vec<_> = cluster_and_num
-            .into_iter()
-            .filter(|&(cl, num)| {
-                if num < 10 {
-                    debug!("Detected small group:{}(cluster:{})", num, cl);
-                    false
-                } else {
-                    true
-                }
-            })
-            .flat_map(|(cl, _)| {
-                let (records, summaries) = assemble(self, cl, c);
-                for summary in summaries {
-                    let ids: Vec<_> = summary
-                        .summary
-                        .iter()
-                        .map(|elm| format!("{}-{}", elm.unit, elm.cluster))
-                        .collect();
-                    debug!("{}\t{}", summary.id, ids.join("\t"));
-                }
-                records
-            })
-            .collect();
-        let mut header = vec![header];
-        header.extend(records);
-        GFA::from_records(header)
     }
     fn assemble_as_graph(&self, c: &AssembleConfig) -> Vec<Graph> {
         let mut cluster_and_num: HashMap<_, u32> = HashMap::new();
@@ -153,7 +147,10 @@ impl Assemble for DataSet {
                 }
             })
             .map(|(cl, _)| {
-                let (records, summaries) = assemble(&self, cl, c);
+                let (records, summaries, _) = match assemble(self, cl, c, false) {
+                    Ok(res) => res,
+                    Err(why) => panic!("{:?}", why),
+                };
                 let nodes: Vec<_> = summaries
                     .iter()
                     .map(|s| {
@@ -196,9 +193,297 @@ impl Assemble for DataSet {
             })
             .collect()
     }
+    fn assemble_with_alignments(
+        &self,
+        c: &AssembleConfig,
+    ) -> std::io::Result<(GFA, crate::sam::SamHeader, Vec<crate::sam::SamRecord>)> {
+        use crate::sam;
+        let (gfa, mut alignments) = assemble_gfa(self, c, true)?;
+        let references: Vec<_> = gfa
+            .iter()
+            .filter_map(|record| match &record.content {
+                gfa::Content::Seg(seg) => seg.sequence.as_ref().map(|s| (seg.sid.clone(), s.len())),
+                _ => None,
+            })
+            .collect();
+        let reference_ids: HashMap<_, _> = references
+            .iter()
+            .enumerate()
+            .map(|(idx, (id, _))| (id.clone(), idx))
+            .collect();
+        let mut header = sam::SamHeader::new(references);
+        let clusters: HashMap<_, _> = self
+            .assignments
+            .iter()
+            .map(|asn| (asn.id, asn.cluster))
+            .collect();
+        let clusters: HashMap<_, _> = self
+            .raw_reads
+            .iter()
+            .filter_map(|r| Some((r.name.as_str(), *clusters.get(&r.id)?)))
+            .collect();
+        let mut records = vec![];
+        for (group_id, _, ids) in cluster_groups(&gfa) {
+            for id in ids.iter() {
+                let (reference, alignments) = match (reference_ids.get(id), alignments.remove(id)) {
+                    (Some(&reference), Some(alignments)) => (reference, alignments),
+                    _ => continue,
+                };
+                for mut record in alignments {
+                    record.reference = reference;
+                    if let Some(&cl) = clusters.get(record.qname.as_str()) {
+                        record.tags.push(sam::Tag::Int(*b"ac", cl as i32));
+                    }
+                    record.tags.push(sam::Tag::Str(*b"gi", group_id.clone()));
+                    records.push(record);
+                }
+            }
+        }
+        sam::sort_records(&mut header, &mut records);
+        Ok((gfa, header, records))
+    }
+    fn split_into_haplotypes(&self, gfa: &GFA) -> Vec<Vec<bio_utils::fasta::Record>> {
+        let blocks = phase_blocks(self);
+        let tags: HashMap<_, _> = gfa
+            .iter()
+            .filter_map(|record| match &record.content {
+                gfa::Content::Seg(seg) => Some((seg.sid.as_str(), &record.tags)),
+                _ => None,
+            })
+            .collect();
+        let segments: HashMap<_, _> = gfa
+            .iter()
+            .filter_map(|record| match &record.content {
+                gfa::Content::Seg(seg) => Some((seg.sid.as_str(), seg.sequence.as_ref()?)),
+                _ => None,
+            })
+            .collect();
+        let mut haplotypes: Vec<Vec<bio_utils::fasta::Record>> = vec![];
+        for (group_id, cl, ids) in cluster_groups(gfa) {
+            let (block, hap) = match blocks.get(&cl) {
+                Some(&res) => res,
+                None => continue,
+            };
+            while haplotypes.len() <= hap {
+                haplotypes.push(vec![]);
+            }
+            for id in ids.iter() {
+                let seq = match segments.get(id.as_str()) {
+                    Some(seq) => seq,
+                    None => continue,
+                };
+                let mut desc = vec![format!("gi:Z:{}", group_id), format!("ps:i:{}", block)];
+                let seg_tags = tags
+                    .get(id.as_str())
+                    .into_iter()
+                    .flat_map(|tags| tags.iter());
+                desc.extend(
+                    seg_tags
+                        .filter(|t| t.inner.starts_with("cv:") || t.inner.starts_with("cp:"))
+                        .map(|t| t.inner.clone()),
+                );
+                let desc = Some(desc.join(" "));
+                let record = bio_utils::fasta::Record::with_data(id, &desc, seq.as_bytes());
+                haplotypes[hap].push(record);
+            }
+        }
+        haplotypes
+    }
+}
+
+// Assemble the reads in each cluster into a GFA. If `to_align` is true, also return the alignments of the reads
+// to the segments. See `assemble`.
+fn assemble_gfa(
+    ds: &DataSet,
+    c: &AssembleConfig,
+    to_align: bool,
+) -> std::io::Result<(GFA, Alignments)> {
+    let mut cluster_and_num: HashMap<_, u32> = HashMap::new();
+    for asn in ds.assignments.iter() {
+        *cluster_and_num.entry(asn.cluster).or_default() += 1;
+    }
+    debug!("There is {} clusters.", cluster_and_num.len());
+    debug!("Start assembly");
+    let header = gfa::Content::Header(gfa::Header::default());
+    let header = gfa::Record::from_contents(header, vec![]);
+    let mut cluster_and_num: Vec<_> = cluster_and_num.into_iter().collect();
+    cluster_and_num.sort_by_key(|x| x.1);
+    cluster_and_num.reverse();
+    let mut records = vec![header];
+    let mut alignments = HashMap::new();
+    for (cl, num) in cluster_and_num {
+        if num < 10 {
+            debug!("Detected small group:{}(cluster:{})", num, cl);
+            continue;
+        }
+        let (cl_records, summaries, cl_alignments) = assemble(ds, cl, c, to_align)?;
+        for summary in summaries {
+            let ids: Vec<_> = summary
+                .summary
+                .iter()
+                .map(|elm| format!("{}-{}", elm.unit, elm.cluster))
+                .collect();
+            debug!("{}\t{}", summary.id, ids.join("\t"));
+        }
+        records.extend(cl_records);
+        alignments.extend(cl_alignments);
+    }
+    Ok((GFA::from_records(records), alignments))
+}
+
+// The groups of the contigs in the GFA, as (the ID, the cluster, the IDs of the segments).
+// The cluster of a group is in its `cl:i:` tag. See `assemble`.
+fn cluster_groups(gfa: &GFA) -> impl Iterator<Item = (&String, usize, &Vec<String>)> {
+    gfa.iter().filter_map(|record| match &record.content {
+        gfa::Content::Group(gfa::Group::Set(group)) => {
+            let uid = group.uid.as_ref()?;
+            let cl = record
+                .tags
+                .iter()
+                .find_map(|t| t.inner.strip_prefix("cl:i:"))?
+                .parse()
+                .ok()?;
+            Some((uid, cl, &group.ids))
+        }
+        _ => None,
+    })
+}
+
+/// Group the clusters of the assignments into phase blocks. Clusters sharing a unit are
+/// the haplotypes of the same region, thus in the same phase block.
+/// Returns the map from a cluster to the ID of its phase block and its haplotype, where
+/// the ID of a block is 1 + the smallest cluster in it(the same as the phase set of `jtk extract --target vcf`),
+/// and the i-th haplotype is the i-th smallest cluster in the block.
+pub fn phase_blocks(ds: &DataSet) -> HashMap<usize, (usize, usize)> {
+    let mut clusters: Vec<_> = ds.assignments.iter().map(|a| a.cluster).collect();
+    clusters.sort_unstable();
+    clusters.dedup();
+    let index: HashMap<_, _> = clusters.iter().enumerate().map(|(i, &c)| (c, i)).collect();
+    let read_index: HashMap<_, _> = ds
+        .assignments
+        .iter()
+        .map(|a| (a.id, index[&a.cluster]))
+        .collect();
+    let mut fu = crate::find_union::FindUnion::new(clusters.len());
+    let mut unit_to_cluster: HashMap<u64, usize> = HashMap::new();
+    for read in ds.encoded_reads.iter() {
+        let idx = match read_index.get(&read.id) {
+            Some(&idx) => idx,
+            None => continue,
+        };
+        for node in read.nodes.iter() {
+            let other = *unit_to_cluster.entry(node.unit).or_insert(idx);
+            fu.unite(idx, other);
+        }
+    }
+    let mut blocks: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (idx, &cl) in clusters.iter().enumerate() {
+        blocks.entry(fu.find(idx).unwrap()).or_default().push(cl);
+    }
+    blocks
+        .values()
+        .flat_map(|members| {
+            // Clusters are pushed in the ascending order.
+            let block = members[0] + 1;
+            members
+                .iter()
+                .enumerate()
+                .map(move |(hap, &cl)| (cl, (block, hap)))
+        })
+        .collect()
+}
+
+// The tiles of a contig, as `ts:Z:{unit}:{cluster}{strand},...` where the strand is + or -.
+fn tiles_tag(summary: &[ContigElement]) -> gfa::SamTag {
+    let tiles: Vec<_> = summary
+        .iter()
+        .map(|n| {
+            let strand = if n.strand { '+' } else { '-' };
+            format!("{}:{}{}", n.unit, n.cluster, strand)
+        })
+        .collect();
+    gfa::SamTag::new(format!("ts:Z:{}", tiles.join(",")))
+}
+
+fn parse_tiles(value: &str) -> Option<Vec<Tile>> {
+    value
+        .split(',')
+        .filter(|tile| !tile.is_empty())
+        .map(|tile| {
+            let (tile, strand) = match tile.strip_suffix('+') {
+                Some(tile) => (tile, true),
+                None => (tile.strip_suffix('-')?, false),
+            };
+            let mut tile = tile.splitn(2, ':');
+            let unit = tile.next()?.parse().ok()?;
+            let cluster = tile.next()?.parse().ok()?;
+            Some(Tile {
+                unit,
+                cluster,
+                strand,
+            })
+        })
+        .collect()
+}
+
+/// Read the graphs in the output of `assemble_as_gfa`, one graph for each group.
+/// The tiles of each contig are the ones in the `ts` tag of the segment, or empty if there is no tag.
+/// Thus, the contigs are the same as the ones in the GFA, unlike re-assembling the reads by `assemble_as_graph`.
+pub fn graphs_from_gfa(gfa: &GFA) -> Vec<Graph> {
+    let tiles: HashMap<_, _> = gfa
+        .iter()
+        .filter_map(|record| match &record.content {
+            gfa::Content::Seg(seg) => {
+                let tag = record.tags.iter().find(|t| t.inner.starts_with("ts:Z:"));
+                let tiles = tag.and_then(|t| parse_tiles(&t.inner["ts:Z:".len()..]));
+                Some((seg.sid.as_str(), tiles.unwrap_or_default()))
+            }
+            _ => None,
+        })
+        .collect();
+    let mut graphs: Vec<Graph> = vec![];
+    let mut graph_of: HashMap<&str, usize> = HashMap::new();
+    for record in gfa.iter() {
+        if let gfa::Content::Group(gfa::Group::Set(group)) = &record.content {
+            let nodes = group.ids.iter().filter_map(|id| {
+                let segments = tiles.get(id.as_str())?.clone();
+                graph_of.insert(id.as_str(), graphs.len());
+                let id = id.clone();
+                Some(Node { id, segments })
+            });
+            let nodes = nodes.collect();
+            graphs.push(Graph {
+                nodes,
+                edges: vec![],
+            });
+        }
+    }
+    for record in gfa.iter() {
+        if let gfa::Content::Edge(e) = &record.content {
+            if let Some(&idx) = graph_of.get(e.sid1.id.as_str()) {
+                graphs[idx].edges.push(Edge {
+                    from: e.sid1.id.to_string(),
+                    from_tail: e.sid1.is_forward(),
+                    to: e.sid2.id.to_string(),
+                    to_tail: e.sid2.is_forward(),
+                });
+            }
+        }
+    }
+    graphs
 }
 
-fn assemble(ds: &DataSet, cl: usize, c: &AssembleConfig) -> (Vec<gfa::Record>, Vec<ContigSummary>) {
+// Alignments of reads to each segment, keyed by the ID of the segment. The reference IDs of the records are 0.
+type Alignments = HashMap<String, Vec<crate::sam::SamRecord>>;
+
+// Assemble the reads in the `cl`-th cluster. If `to_align` is true, also return the alignments of the reads
+// used in polishing to the segments. See `polish_segment`.
+fn assemble(
+    ds: &DataSet,
+    cl: usize,
+    c: &AssembleConfig,
+    to_align: bool,
+) -> std::io::Result<(Vec<gfa::Record>, Vec<ContigSummary>, Alignments)> {
     let clusters: HashSet<_> = ds
         .assignments
         .iter()
@@ -225,16 +510,33 @@ fn assemble(ds: &DataSet, cl: usize, c: &AssembleConfig) -> (Vec<gfa::Record>, V
     let (segments, edge, group, summaries) = graph.spell(c, cl);
     let total_base = segments.iter().map(|x| x.slen).sum::<u64>();
     debug!("{} segments({} bp in total).", segments.len(), total_base);
+    let mut alignments = HashMap::new();
     let segments = if c.to_polish {
-        segments
-            .iter()
-            .map(|segment| {
-                let summary = summaries.iter().find(|s| s.id == segment.sid).unwrap();
-                let segment = polish_segment(ds, segment, summary, c);
-                polish_segment(ds, &segment, summary, c)
-            })
-            .collect()
+        let mut polished_segments = vec![];
+        for segment in segments.iter() {
+            let summary = summaries.iter().find(|s| s.id == segment.sid).unwrap();
+            let reads = get_reads_in_cluster(ds, summary);
+            let (segment, _) = polish_segment(segment, &reads, c)?;
+            let (polished, alignment) = polish_segment(&segment, &reads, c)?;
+            if to_align {
+                let records = lift_over(&alignment, &segment, &polished)?;
+                alignments.insert(polished.sid.clone(), records);
+            }
+            polished_segments.push(polished);
+        }
+        polished_segments
     } else {
+        if to_align {
+            for segment in segments.iter() {
+                let summary = summaries.iter().find(|s| s.id == segment.sid).unwrap();
+                let reads = get_reads_in_cluster(ds, summary);
+                debug!("Aligning {} reads", reads.len());
+                let alignment = minimap2_to_segments(&[segment], &reads, c)?;
+                let header = crate::sam::SamHeader::new(vec![(segment.sid.clone(), 0)]);
+                let records = crate::sam::parse_records(alignment.as_slice(), &header)?;
+                alignments.insert(segment.sid.clone(), records);
+            }
+        }
         segments
     };
     // TODO: maybe just zip up segments and summaries would be OK?
@@ -255,6 +557,7 @@ fn assemble(ds: &DataSet, cl: usize, c: &AssembleConfig) -> (Vec<gfa::Record>, V
                 if cpnum != 0 {
                     tags.push(gfa::SamTag::new(format!("cp:i:{}", cp / cpnum)));
                 }
+                tags.push(tiles_tag(&contigsummary.summary));
                 tags
             })
             .unwrap_or(vec![]);
@@ -263,25 +566,64 @@ fn assemble(ds: &DataSet, cl: usize, c: &AssembleConfig) -> (Vec<gfa::Record>, V
     let edges = edge
         .into_iter()
         .map(|(edge, tags)| gfa::Record::from_contents(gfa::Content::Edge(edge), tags));
-    let group = gfa::Record::from_contents(gfa::Content::Group(group), vec![]);
+    let cluster = gfa::SamTag::new(format!("cl:i:{}", cl));
+    let group = gfa::Record::from_contents(gfa::Content::Group(group), vec![cluster]);
     let records: Vec<_> = std::iter::once(group).chain(nodes).chain(edges).collect();
-    (records, summaries)
+    Ok((records, summaries, alignments))
 }
 
+// Polish the segment by the reads. Return the polished segment and the alignments of the reads
+// to the given segment in SAM format.
 fn polish_segment(
-    ds: &DataSet,
     segment: &gfa::Segment,
-    summary: &ContigSummary,
+    reads: &[&RawRead],
     c: &AssembleConfig,
-) -> gfa::Segment {
-    let reads = get_reads_in_cluster(ds, summary);
+) -> std::io::Result<(gfa::Segment, Vec<u8>)> {
     debug!("Aligning {} reads", reads.len());
-    let alignments = match align_reads(segment, &reads, c) {
-        Ok(res) => res,
-        Err(why) => panic!("{:?}", why),
+    let alignment = minimap2_to_segments(&[segment], reads, c)?;
+    let alignments = kiley::sam::Sam::from_reader(std::io::BufReader::new(alignment.as_slice()));
+    debug!("Alignment done");
+    let seq = String::from_utf8(polish_by_chunking(&alignments, segment, reads, c)).unwrap();
+    let polished = gfa::Segment::from(segment.sid.clone(), seq.len(), Some(seq));
+    Ok((polished, alignment))
+}
+
+// Parse the alignments to `segment` in SAM format, and lift them over to `polished`, the polished `segment`.
+fn lift_over(
+    alignment: &[u8],
+    segment: &gfa::Segment,
+    polished: &gfa::Segment,
+) -> std::io::Result<Vec<crate::sam::SamRecord>> {
+    use crate::sam::{self, CigarOp};
+    let header = sam::SamHeader::new(vec![(segment.sid.clone(), 0)]);
+    let records = sam::parse_records(alignment, &header)?;
+    let (old, new) = match (segment.sequence.as_ref(), polished.sequence.as_ref()) {
+        (Some(old), Some(new)) => (old.as_bytes(), new.as_bytes()),
+        _ => return Ok(vec![]),
     };
-    let seq = String::from_utf8(polish_by_chunking(&alignments, &segment, &reads, c)).unwrap();
-    gfa::Segment::from(segment.sid.clone(), seq.len(), Some(seq))
+    let mode = edlib_sys::AlignMode::Global;
+    let task = edlib_sys::AlignTask::Alignment;
+    let ops = edlib_sys::edlib_align(old, new, mode, task)
+        .operations
+        .unwrap_or_default();
+    let mut cigar = vec![];
+    for op in ops {
+        let op = match op {
+            1 => CigarOp::Ins(1),
+            2 => CigarOp::Del(1),
+            _ => CigarOp::Match(1),
+        };
+        match (cigar.last_mut(), op) {
+            (Some(CigarOp::Match(l)), CigarOp::Match(_))
+            | (Some(CigarOp::Ins(l)), CigarOp::Ins(_))
+            | (Some(CigarOp::Del(l)), CigarOp::Del(_)) => *l += 1,
+            _ => cigar.push(op),
+        }
+    }
+    Ok(records
+        .iter()
+        .filter_map(|record| sam::lift_over(record, &cigar))
+        .collect())
 }
 
 fn get_reads_in_cluster<'a>(ds: &'a DataSet, summary: &ContigSummary) -> Vec<&'a RawRead> {
@@ -306,54 +648,19 @@ fn get_reads_in_cluster<'a>(ds: &'a DataSet, summary: &ContigSummary) -> Vec<&'a
         .collect()
 }
 
-fn align_reads(
-    segment: &gfa::Segment,
+// Align reads to the segments by `c.aligner`, and return the output in SAM format.
+fn minimap2_to_segments(
+    segments: &[&gfa::Segment],
     reads: &[&RawRead],
     c: &AssembleConfig,
-) -> std::io::Result<kiley::sam::Sam> {
-    use rand::{thread_rng, Rng};
-    let mut rng = thread_rng();
-    let id: u64 = rng.gen::<u64>() % 100_000_000;
-    let mut c_dir = std::env::current_dir()?;
-    c_dir.push(format!("{}", id));
-    debug!("Creating {:?}.", c_dir);
-    std::fs::create_dir(&c_dir)?;
-    // Create reference and reads.
-    let (reference, reads) = {
-        let mut reference = c_dir.clone();
-        reference.push("segment.fa");
-        use std::io::{BufWriter, Write};
-        let mut wtr = std::fs::File::create(&reference).map(BufWriter::new)?;
-        let seq = segment.sequence.as_ref().unwrap().as_bytes().to_vec();
-        let seq = String::from_utf8_lossy(&seq);
-        writeln!(&mut wtr, ">{}\n{}", &segment.sid, seq)?;
-        let mut reads_dir = c_dir.clone();
-        reads_dir.push("reads.fa");
-        let mut wtr = std::fs::File::create(&reads_dir).map(BufWriter::new)?;
-        for read in reads.iter() {
-            writeln!(&mut wtr, ">{}\n{}", read.name, read.seq)?;
-        }
-        let reference = reference.into_os_string().into_string().unwrap();
-        let reads = reads_dir.into_os_string().into_string().unwrap();
-        (reference, reads)
-    };
-    let thr = format!("{}", c.threads);
-    let args = [
-        "-x",
-        "map-pb",
-        "-a",
-        "-t",
-        &thr,
-        "--secondary=no",
-        "-z",
-        "600,400",
-    ];
-    let alignment = crate::minimap2::minimap2_args(&reference, &reads, &args);
-    let alignment = kiley::sam::Sam::from_reader(std::io::BufReader::new(alignment.as_slice()));
-    debug!("Removing {:?}", c_dir);
-    std::fs::remove_dir_all(c_dir)?;
-    debug!("Alignment done");
-    Ok(alignment)
+) -> std::io::Result<Vec<u8>> {
+    use crate::minimap2::{Aligner, AlignmentFormat};
+    let segments: Vec<_> = segments
+        .iter()
+        .map(|s| (s.sid.as_str(), s.sequence.as_ref().unwrap().as_bytes()))
+        .collect();
+    let reads: Vec<_> = reads.iter().map(|r| (r.name.as_str(), r.seq())).collect();
+    c.aligner.align(&segments, &reads, AlignmentFormat::Sam)
 }
 
 pub fn polish_by_chunking(
@@ -454,3 +761,87 @@ pub fn copy_number_estimation(
 //         current = start + 1;
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn cluster_groups_test() {
+        let group = |uid: &str| {
+            let ids = vec![format!("{}_tig", uid)];
+            let uid = Some(uid.to_string());
+            gfa::Content::Group(gfa::Group::Set(gfa::UnorderedGroup { uid, ids }))
+        };
+        let tags = vec![gfa::SamTag::new("cl:i:3".to_string())];
+        // The cluster is taken from the tag, not from the ID of the group.
+        let records = vec![
+            gfa::Record::from_contents(group("group-3"), tags),
+            gfa::Record::from_contents(group("group-4"), vec![]),
+        ];
+        let gfa = GFA::from_records(records);
+        let groups: Vec<_> = cluster_groups(&gfa).collect();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].0, "group-3");
+        assert_eq!(groups[0].1, 3);
+        assert_eq!(groups[0].2, &vec!["group-3_tig".to_string()]);
+    }
+    #[test]
+    fn phase_blocks_test() {
+        let node = |unit: u64| definitions::Node {
+            position_from_start: 0,
+            unit,
+            cluster: 0,
+            seq: String::new(),
+            qual: None,
+            is_forward: true,
+            cigar: vec![],
+        };
+        let read = |id: u64, units: &[u64]| EncodedRead {
+            id,
+            original_length: 0,
+            leading_gap: vec![],
+            trailing_gap: vec![],
+            nodes: units.iter().map(|&u| node(u)).collect(),
+            edges: vec![],
+        };
+        let mut ds = DataSet::with_minimum_data("", vec![], "CLR");
+        // Clusters 1 and 4 are on units 0-1, while 2 and 3 are on units 2-3.
+        // Cluster 5 has its own units.
+        ds.encoded_reads = vec![
+            read(0, &[0, 1]),
+            read(1, &[1, 0]),
+            read(2, &[2, 3]),
+            read(3, &[3]),
+            read(4, &[5]),
+        ];
+        let clusters = [1, 4, 3, 2, 5];
+        ds.assignments = (0..5)
+            .map(|i| Assignment::new(i, clusters[i as usize]))
+            .collect();
+        let blocks = phase_blocks(&ds);
+        assert_eq!(blocks[&1], (2, 0));
+        assert_eq!(blocks[&4], (2, 1));
+        assert_eq!(blocks[&2], (3, 0));
+        assert_eq!(blocks[&3], (3, 1));
+        assert_eq!(blocks[&5], (6, 0));
+    }
+    #[test]
+    fn tiles_tag_test() {
+        let elm = |unit, cluster, strand| ContigElement {
+            unit,
+            cluster,
+            strand,
+            occ: 1,
+            copy_number: None,
+        };
+        let tag = tiles_tag(&[elm(3, 0, true), elm(10, 2, false)]);
+        assert_eq!(tag.inner, "ts:Z:3:0+,10:2-");
+        let tiles = parse_tiles(&tag.inner["ts:Z:".len()..]).unwrap();
+        assert_eq!(tiles.len(), 2);
+        assert_eq!(
+            (tiles[1].unit, tiles[1].cluster, tiles[1].strand),
+            (10, 2, false)
+        );
+        assert!(parse_tiles("3:0").is_none());
+    }
+}
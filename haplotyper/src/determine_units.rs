@@ -4,8 +4,9 @@ use super::polish_units::PolishUnitConfig;
 // use super::Encode;
 use definitions::*;
 // use rayon::prelude::*;
+use serde::Serialize;
 use std::collections::HashMap;
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct UnitConfig {
     pub chunk_len: usize,
     pub skip_len: usize,
@@ -101,11 +102,11 @@ impl UnitConfig {
 }
 
 pub trait DetermineUnit {
-    fn select_chunks(self, config: &UnitConfig) -> Self;
+    fn select_chunks(self, config: &UnitConfig) -> std::io::Result<Self>;
 }
 
 impl DetermineUnit for definitions::DataSet {
-    fn select_chunks(mut self, config: &UnitConfig) -> Self {
+    fn select_chunks(mut self, config: &UnitConfig) -> std::io::Result<Self> {
         let mut reads: Vec<&RawRead> = self.raw_reads.iter().collect();
         reads.sort_by_key(|r| r.seq().len());
         reads.reverse();
@@ -142,18 +143,18 @@ impl DetermineUnit for definitions::DataSet {
                 })
                 .collect();
             debug!("UNITNUM\t{}\tPICKED", self.selected_chunks.len());
-            self.selected_chunks = remove_overlapping_units(&self, config.threads).unwrap();
+            self.selected_chunks = remove_overlapping_units(&self, config.threads)?;
             // 1st polishing.
             debug!("UNITNUM\t{}\tREMOVED", self.selected_chunks.len());
-            self = self.encode(config.threads);
+            self = self.encode(config.threads)?;
             let polish_config = PolishUnitConfig::new(ReadType::CLR, 3, 10, 20);
             self = self.polish_unit(&polish_config);
             // Filling gappy region.
             debug!("UNITNUM\t{}\tPOLISHED\t1", self.selected_chunks.len());
-            self = self.encode(config.threads);
+            self = self.encode(config.threads)?;
             self = fill_sparse_region(self, config);
             // 2nd polishing.
-            self = self.encode(config.threads);
+            self = self.encode(config.threads)?;
             let polish_config = PolishUnitConfig::new(ReadType::CLR, 10, 10, 20);
             self = self.polish_unit(&polish_config);
             debug!("UNITNUM\t{}\tPOLISHED\t2", self.selected_chunks.len());
@@ -175,10 +176,10 @@ impl DetermineUnit for definitions::DataSet {
         };
         self.selected_chunks = selected_chunks;
         debug!("UNITNUM\t{}\tRAWUNIT", self.selected_chunks.len());
-        self = self.encode(config.threads);
+        self = self.encode(config.threads)?;
         self = filter_unit_by_ovlp(self, config);
         debug!("UNITNUM\t{}\tFILTERED", self.selected_chunks.len());
-        self = self.encode(config.threads);
+        self = self.encode(config.threads)?;
         // Final polishing.
         let polish_config = PolishUnitConfig::new(ReadType::CLR, 10, 25, 24);
         self = self.polish_unit(&polish_config);
@@ -191,7 +192,7 @@ impl DetermineUnit for definitions::DataSet {
             unit.id = idx;
             idx += 1;
         });
-        self
+        Ok(self)
     }
 }
 
@@ -142,6 +142,7 @@ fn encode_node(
         is_forward,
         seq: String::from_utf8(seq).unwrap(),
         cigar: ops,
+        qual: None,
     };
     Some(node)
     // let (dist, ops) = kiley::bialignment::edit_dist_slow_ops_semiglobal(unit.seq(), &query);
@@ -1,10 +1,10 @@
 // use bio_utils::lasttab;
 // use bio_utils::lasttab::LastTAB;
+use crate::minimap2::{Aligner, AlignmentFormat, Minimap2};
 use definitions::DataSet;
 use definitions::{Edge, EncodedRead, Node, Op, RawRead, Unit};
 use rayon::prelude::*;
 use std::collections::HashMap;
-use std::io::*;
 pub mod deletion_fill;
 /// This is a parameter only valid for last program.
 /// TODO: As the Licencing issue, maybe we should remove these parameters as well as dependencies for last.
@@ -16,18 +16,33 @@ pub const MARGIN: usize = 50;
 const ALLOWED_END_GAP: usize = 50;
 /// Any alignment having Insertion/Deletion longer than INDEL_THRESHOLD would be discarded.
 pub const INDEL_THRESHOLD: usize = 50;
-pub trait Encode {
-    fn encode(self, threads: usize) -> Self;
+pub trait Encode: Sized {
+    /// Encode reads by minimap2, with the parameters for the read type.
+    fn encode(self, threads: usize) -> std::io::Result<Self>;
+    /// Encode reads by the `aligner`, which should output alignments covering the entire units.
+    fn encode_by_aligner<A: Aligner + ?Sized>(self, aligner: &A) -> std::io::Result<Self>;
+    /// Encode reads by precomputed alignments from the reads to the units, in PAF format with `cg:Z` tags.
+    /// The target names should be unit IDs. Alignments to unknown reads or units are discarded.
+    fn encode_by_paf(self, paf: &[u8]) -> Self;
 }
 
 impl Encode for definitions::DataSet {
-    fn encode(mut self, threads: usize) -> Self {
-        self = encode_by_mm2(self, threads).unwrap();
-        if self.read_type != definitions::ReadType::CCS {
-            let mut current: usize = self.encoded_reads.iter().map(|x| x.nodes.len()).sum();
+    fn encode(self, threads: usize) -> std::io::Result<Self> {
+        let aligner = default_minimap2(self.read_type, threads);
+        self.encode_by_aligner(&aligner)
+    }
+    fn encode_by_aligner<A: Aligner + ?Sized>(self, aligner: &A) -> std::io::Result<Self> {
+        let paf = unit_alignment(&self, aligner)?;
+        Ok(self.encode_by_paf(&paf))
+    }
+    fn encode_by_paf(self, paf: &[u8]) -> Self {
+        let alignments = parse_paf(&self, paf);
+        let mut ds = encode_by(self, &alignments);
+        if ds.read_type != definitions::ReadType::CCS {
+            let mut current: usize = ds.encoded_reads.iter().map(|x| x.nodes.len()).sum();
             loop {
-                self = deletion_fill::correct_unit_deletion(self);
-                let after: usize = self.encoded_reads.iter().map(|x| x.nodes.len()).sum();
+                ds = deletion_fill::correct_unit_deletion(ds);
+                let after: usize = ds.encoded_reads.iter().map(|x| x.nodes.len()).sum();
                 debug!("Filled:{}->{}", current, after);
                 if after <= current {
                     break;
@@ -36,20 +51,72 @@ impl Encode for definitions::DataSet {
                 }
             }
         }
-        debug!("Encoded {} reads.", self.encoded_reads.len());
-        assert!(self.encoded_reads.iter().all(|read| is_uppercase(read)));
-        self
+        attach_qualities(&mut ds);
+        debug!("Encoded {} reads.", ds.encoded_reads.len());
+        assert!(ds.encoded_reads.iter().all(|read| is_uppercase(read)));
+        ds
     }
 }
 
+/// The minimap2 backend with the parameters suitable for the read type.
+pub fn default_minimap2(read_type: definitions::ReadType, threads: usize) -> Minimap2 {
+    use definitions::ReadType;
+    let args: &[&str] = match read_type {
+        ReadType::CCS => &["-P", "-H"],
+        ReadType::CLR => &["-P", "-H", "-k", "15"],
+        ReadType::ONT => &["-P", "-k", "15"],
+        _ => &["-P"],
+    };
+    Minimap2::new(threads).args(args)
+}
+
 pub fn encode_by_mm2(ds: definitions::DataSet, p: usize) -> std::io::Result<DataSet> {
-    let mm2 = mm2_alignment(&ds, p)?;
-    let alignments: Vec<_> = String::from_utf8_lossy(&mm2)
+    let paf = mm2_alignment(&ds, p)?;
+    let alignments = parse_paf(&ds, &paf);
+    Ok(encode_by(ds, &alignments))
+}
+
+// Parse the alignments from the reads to the units, and remove the ones having long end gaps.
+// Alignments inconsistent with the dataset, such as the ones to unknown units, are discarded as well.
+fn parse_paf(ds: &DataSet, paf: &[u8]) -> Vec<bio_utils::paf::PAF> {
+    let units: HashMap<_, _> = ds
+        .selected_chunks
+        .iter()
+        .map(|u| (u.id, u.seq().len()))
+        .collect();
+    let reads: HashMap<_, _> = ds
+        .raw_reads
+        .iter()
+        .map(|r| (r.name.as_str(), r.seq().len()))
+        .collect();
+    let mut alignments = vec![];
+    let mut inconsistent = 0;
+    for aln in String::from_utf8_lossy(paf)
         .lines()
         .filter_map(|l| bio_utils::paf::PAF::new(&l))
-        .filter(|a| a.tstart < ALLOWED_END_GAP && a.tlen - a.tend < ALLOWED_END_GAP)
-        .collect();
-    Ok(encode_by(ds, &alignments))
+    {
+        let unit_len = aln.tname.parse().ok().and_then(|id: u64| units.get(&id));
+        let read_len = reads.get(aln.qname.as_str());
+        if unit_len != Some(&aln.tlen)
+            || read_len != Some(&aln.qlen)
+            || !aln.tags.contains_key("cg")
+            || aln.tstart > aln.tend
+            || aln.tend > aln.tlen
+            || aln.qstart > aln.qend
+            || aln.qend > aln.qlen
+        {
+            inconsistent += 1;
+        } else if aln.tstart < ALLOWED_END_GAP && aln.tlen - aln.tend < ALLOWED_END_GAP {
+            alignments.push(aln);
+        }
+    }
+    if inconsistent > 0 {
+        warn!(
+            "Discarded {} alignments inconsistent with the reads and units.",
+            inconsistent
+        );
+    }
+    alignments
 }
 
 pub fn encode_by(mut ds: DataSet, alignments: &[bio_utils::paf::PAF]) -> DataSet {
@@ -73,6 +140,34 @@ pub fn encode_by(mut ds: DataSet, alignments: &[bio_utils::paf::PAF]) -> DataSet
     ds
 }
 
+/// Copy the per-base qualities of the raw reads into the nodes of the encoded reads.
+/// If a raw read has no quality, its nodes would have None as well.
+/// `polish_unit` uses them to choose the nodes for the consensus.
+pub fn attach_qualities(ds: &mut DataSet) {
+    let quals: HashMap<_, _> = ds
+        .raw_reads
+        .iter()
+        .filter_map(|r| r.qual().map(|q| (r.id, q)))
+        .collect();
+    ds.encoded_reads.par_iter_mut().for_each(|read| {
+        let qual = quals.get(&read.id);
+        for node in read.nodes.iter_mut() {
+            node.qual = qual.map(|qual| {
+                let (start, end) = (
+                    node.position_from_start,
+                    node.position_from_start + node.query_length(),
+                );
+                let qual = &qual[start..end];
+                if node.is_forward {
+                    String::from_utf8_lossy(qual).to_string()
+                } else {
+                    qual.iter().rev().map(|&q| q as char).collect()
+                }
+            });
+        }
+    });
+}
+
 fn encode_read_by_paf(
     read: &RawRead,
     alns: &[&bio_utils::paf::PAF],
@@ -219,6 +314,7 @@ fn encode_paf(seq: &[u8], aln: &bio_utils::paf::PAF, unit: &Unit) -> Option<Node
             is_forward: aln.relstrand,
             seq: aligned,
             cigar: ops,
+            qual: None,
         })
 }
 
@@ -276,45 +372,30 @@ fn remove_slippy_alignment(mut nodes: Vec<Node>) -> Vec<Node> {
 }
 
 pub fn mm2_alignment(ds: &definitions::DataSet, p: usize) -> std::io::Result<Vec<u8>> {
-    use rand::{thread_rng, Rng};
-    let mut rng = thread_rng();
-    let id: u64 = rng.gen::<u64>() % 100_000_000;
-    let mut c_dir = std::env::current_dir()?;
-    c_dir.push(format!("{}", id));
-    debug!("Creating {:?}.", c_dir);
-    std::fs::create_dir(&c_dir)?;
-    // Create reference and reads.
-    let (reference, reads) = {
-        let mut reference = c_dir.clone();
-        reference.push("units.fa");
-        let mut wtr = std::fs::File::create(&reference).map(BufWriter::new)?;
-        for unit in ds.selected_chunks.iter() {
-            writeln!(&mut wtr, ">{}\n{}", unit.id, &unit.seq)?;
-        }
-        let mut reads = c_dir.clone();
-        reads.push("reads.fa");
-        let mut wtr = std::fs::File::create(&reads).map(BufWriter::new)?;
-        for read in ds.raw_reads.iter() {
-            writeln!(&mut wtr, ">{}\n{}", read.name, &read.seq)?;
-        }
-        let reference = reference.into_os_string().into_string().unwrap();
-        let reads = reads.into_os_string().into_string().unwrap();
-        (reference, reads)
-    };
-    use crate::minimap2;
-    let threads = format!("{}", p);
-    use definitions::ReadType;
-    let mut args = vec!["-t", &threads, "-c", "-P"];
-    match ds.read_type {
-        ReadType::CCS => args.extend(vec!["-H"]),
-        ReadType::CLR => args.extend(vec!["-H", "-k", "15"]),
-        ReadType::ONT => args.extend(vec!["-k", "15"]),
-        _ => {}
-    };
-    let mm2 = minimap2::minimap2_args(&reference, &reads, &args);
-    debug!("Removing {:?}", c_dir);
-    std::fs::remove_dir_all(c_dir)?;
-    Ok(mm2)
+    unit_alignment(ds, &default_minimap2(ds.read_type, p))
+}
+
+/// Align the raw reads onto the selected chunks, and return the alignments in PAF format.
+pub fn unit_alignment<A: Aligner + ?Sized>(
+    ds: &definitions::DataSet,
+    aligner: &A,
+) -> std::io::Result<Vec<u8>> {
+    let ids: Vec<_> = ds
+        .selected_chunks
+        .iter()
+        .map(|u| u.id.to_string())
+        .collect();
+    let units: Vec<_> = ids
+        .iter()
+        .zip(ds.selected_chunks.iter())
+        .map(|(id, u)| (id.as_str(), u.seq()))
+        .collect();
+    let reads: Vec<_> = ds
+        .raw_reads
+        .iter()
+        .map(|r| (r.name.as_str(), r.seq()))
+        .collect();
+    aligner.align(&units, &reads, AlignmentFormat::Paf)
 }
 
 fn is_uppercase(read: &definitions::EncodedRead) -> bool {
@@ -859,8 +940,36 @@ pub fn recover(query: &[u8], refr: &[u8], ops: &[Op]) -> (Vec<u8>, Vec<u8>, Vec<
 
 #[cfg(test)]
 mod tests {
+    use super::*;
     #[test]
     fn works() {}
+    #[test]
+    fn parse_paf_test() {
+        let read = RawRead {
+            name: "read".to_string(),
+            desc: String::new(),
+            id: 0,
+            seq: "A".repeat(200),
+            qual: None,
+        };
+        let mut ds = DataSet::with_minimum_data("reads.fa", vec![read], "CLR");
+        ds.selected_chunks = vec![Unit::new(0, "A".repeat(100), 2)];
+        let paf = [
+            "read\t200\t10\t110\t+\t0\t100\t0\t100\t100\t100\t60\tcg:Z:100M",
+            // Long end gap.
+            "read\t200\t10\t50\t+\t0\t100\t0\t40\t40\t40\t60\tcg:Z:40M",
+            // Unknown unit, unknown read, wrong lengths, and without CIGAR.
+            "read\t200\t10\t110\t+\t1\t100\t0\t100\t100\t100\t60\tcg:Z:100M",
+            "other\t200\t10\t110\t+\t0\t100\t0\t100\t100\t100\t60\tcg:Z:100M",
+            "read\t300\t10\t110\t+\t0\t100\t0\t100\t100\t100\t60\tcg:Z:100M",
+            "read\t200\t10\t110\t+\t0\t90\t0\t90\t90\t90\t60\tcg:Z:90M",
+            "read\t200\t10\t110\t+\t0\t100\t0\t100\t100\t100\t60",
+        ]
+        .join("\n");
+        let alignments = parse_paf(&ds, paf.as_bytes());
+        assert_eq!(alignments.len(), 1);
+        assert_eq!((alignments[0].qstart, alignments[0].qend), (10, 110));
+    }
     // #[test]
     // fn alignment_check() {
     //     let query = b"AAAAA";
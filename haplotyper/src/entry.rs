@@ -1,24 +1,138 @@
+use std::io::BufRead;
+/// A record of the input file. If the input is a fasta file, `qual` is None.
+#[derive(Debug, Clone)]
+pub struct ReadRecord {
+    pub id: String,
+    pub desc: String,
+    pub seq: Vec<u8>,
+    /// Per-base quality in the Phred+33 encoding.
+    pub qual: Option<Vec<u8>>,
+}
+
+impl std::convert::From<bio_utils::fasta::Record> for ReadRecord {
+    fn from(record: bio_utils::fasta::Record) -> Self {
+        Self {
+            id: record.id().to_string(),
+            desc: record.desc().cloned().unwrap_or_default(),
+            seq: record.seq().to_vec(),
+            qual: None,
+        }
+    }
+}
+
+/// Parse fasta or fastq records. The format is determined by the first character of the input,
+/// i.e., '>' for fasta and '@' for fastq.
+pub fn parse_reads<R: BufRead>(mut reader: R) -> std::io::Result<Vec<ReadRecord>> {
+    use std::io::{Error, ErrorKind};
+    let first = loop {
+        let buf = reader.fill_buf()?;
+        match buf.iter().find(|x| !x.is_ascii_whitespace()) {
+            Some(&x) => break x,
+            None if buf.is_empty() => return Ok(vec![]),
+            None => {
+                let len = buf.len();
+                reader.consume(len);
+            }
+        }
+    };
+    match first {
+        b'>' => bio_utils::fasta::parse_into_vec_from(reader)
+            .map(|records| records.into_iter().map(ReadRecord::from).collect()),
+        b'@' => parse_fastq(reader),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Unknown format. It starts with {}.", first as char),
+        )),
+    }
+}
+
+fn parse_fastq<R: BufRead>(reader: R) -> std::io::Result<Vec<ReadRecord>> {
+    use std::io::{Error, ErrorKind};
+    let mut lines = reader.lines().filter(|l| match l {
+        Ok(l) => !l.trim().is_empty(),
+        Err(_) => true,
+    });
+    let mut records = vec![];
+    while let Some(header) = lines.next() {
+        let header = header?;
+        let header = header.trim_end();
+        if !header.starts_with('@') {
+            let msg = format!("Invalid fastq header:{}", header);
+            return Err(Error::new(ErrorKind::InvalidData, msg));
+        }
+        let mut header = header[1..].splitn(2, char::is_whitespace);
+        let id = header.next().unwrap_or("").to_string();
+        let desc = header.next().map(|d| d.trim().to_string()).unwrap_or_default();
+        let mut next_line = || match lines.next() {
+            Some(line) => line.map(|l| l.trim_end().to_string()),
+            None => {
+                let msg = format!("Truncated fastq record:{}", id);
+                Err(Error::new(ErrorKind::UnexpectedEof, msg))
+            }
+        };
+        let seq = next_line()?.into_bytes();
+        let _separator = next_line()?;
+        let qual = next_line()?.into_bytes();
+        if seq.len() != qual.len() {
+            let msg = format!("Lengths of the sequence and quality differ:{}", id);
+            return Err(Error::new(ErrorKind::InvalidData, msg));
+        }
+        records.push(ReadRecord {
+            id,
+            desc,
+            seq,
+            qual: Some(qual),
+        });
+    }
+    Ok(records)
+}
+
 pub trait Entry {
-    fn new(input_file: &str, raw_data: &[bio_utils::fasta::Record], rt: &str) -> Self;
+    fn new(input_file: &str, raw_data: &[ReadRecord], rt: &str) -> Self;
 }
 
 impl Entry for definitions::DataSet {
-    fn new(input_file: &str, raw_data: &[bio_utils::fasta::Record], rt: &str) -> Self {
+    fn new(input_file: &str, raw_data: &[ReadRecord], rt: &str) -> Self {
         let raw_reads: Vec<_> = raw_data
             .iter()
             .enumerate()
             .map(|(idx, read)| definitions::RawRead {
-                name: read.id().to_string(),
-                desc: read.desc().unwrap_or(&String::new()).clone(),
+                name: read.id.clone(),
+                desc: read.desc.clone(),
                 id: idx as u64,
                 seq: read
-                    .seq()
+                    .seq
                     .iter()
                     .map(|&x| (x as char).to_ascii_uppercase())
                     .collect::<String>(),
+                qual: read
+                    .qual
+                    .as_ref()
+                    .map(|q| String::from_utf8_lossy(q).to_string()),
             })
             .collect();
         use definitions::DataSet;
         DataSet::with_minimum_data(input_file, raw_reads, rt)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn parse_fastq_test() {
+        let fastq = b"@read1 desc\nACGT\n+\n!!II\n\n@read2\nAC\n+read2\n#I\n";
+        let records = parse_reads(&fastq[..]).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].id, "read1");
+        assert_eq!(records[0].desc, "desc");
+        assert_eq!(records[0].seq, b"ACGT");
+        assert_eq!(records[0].qual.as_ref().unwrap(), b"!!II");
+        assert_eq!(records[1].id, "read2");
+        assert_eq!(records[1].qual.as_ref().unwrap(), b"#I");
+        let truncated = b"@read1\nACGT\n+\n";
+        assert!(parse_reads(&truncated[..]).is_err());
+        let broken = b"@read1\nACGT\n+\n!!I\n";
+        assert!(parse_reads(&broken[..]).is_err());
+    }
+}
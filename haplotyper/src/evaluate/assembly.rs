@@ -0,0 +1,496 @@
+//! Evaluation of the contigs against the true haplotype sequences.
+//! Each contig is cut into windows, and the windows are aligned to the haplotypes.
+//! Adjacent windows placed discontinuously, or only on different haplotypes, are misjoins.
+//! The placed windows are stacked on the haplotypes to find duplicated regions, and the regions
+//! where some window aligns equally well but no window is placed are collapsed.
+//! Errors are attributed to the units of the contigs, i.e., the tiles of [crate::assemble::Node].
+use crate::assemble::Node;
+use crate::minimap2::{Aligner, AlignmentFormat};
+use crate::typing::Contig;
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct AssemblyEvaluationConfig {
+    /// The length of the windows of contigs.
+    window: usize,
+}
+
+impl AssemblyEvaluationConfig {
+    pub fn new(window: usize) -> Self {
+        assert!(window > 0, "The window should be positive.");
+        Self { window }
+    }
+}
+
+impl std::default::Default for AssemblyEvaluationConfig {
+    fn default() -> Self {
+        Self { window: 5_000 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum MisjoinType {
+    /// The windows before and after are placed only on different haplotypes.
+    Haplotype,
+    /// The windows are on the same haplotype, but far apart or on different strands.
+    Relocation,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Misjoin {
+    /// The position in the contig.
+    pub position: usize,
+    pub misjoin_type: MisjoinType,
+    pub from: String,
+    pub to: String,
+    /// The units around the position, as `{unit}-{cluster}`.
+    pub units: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ContigEvaluation {
+    pub contig: String,
+    pub group: Option<String>,
+    pub length: usize,
+    /// The haplotype where the most windows are placed.
+    pub haplotype: Option<String>,
+    /// The fraction of the matches in the alignments of the windows.
+    pub identity: f64,
+    pub aligned_fraction: f64,
+    pub misjoins: Vec<Misjoin>,
+    /// The windows on the regions covered more than once.
+    pub duplicated_windows: usize,
+    /// The windows aligned equally well to uncovered regions.
+    pub collapsed_windows: usize,
+    /// The units in the misjoins, the duplicated, and the collapsed windows.
+    pub error_units: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HaplotypeEvaluation {
+    pub haplotype: String,
+    pub length: usize,
+    pub covered: usize,
+    pub duplicated: usize,
+    pub collapsed: usize,
+    /// The fraction of the covered bases.
+    pub completeness: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AssemblySummary {
+    pub contigs: usize,
+    pub total_length: usize,
+    pub aligned_length: usize,
+    pub identity: f64,
+    pub haplotype_misjoins: usize,
+    pub relocations: usize,
+    /// NGA50 with respect to the total length of the haplotypes.
+    /// The blocks are the contigs split at the misjoins, and their lengths are the aligned windows only.
+    pub nga50: usize,
+    pub haplotypes: Vec<HaplotypeEvaluation>,
+}
+
+// A placement of a window on a haplotype.
+#[derive(Debug, Clone, Copy)]
+struct Hit {
+    hap: usize,
+    start: usize,
+    end: usize,
+    is_forward: bool,
+    matches: usize,
+    // Aligned length minus the matches. The smaller, the better.
+    errors: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Window {
+    contig: usize,
+    start: usize,
+    end: usize,
+    // The hits with the minimum errors.
+    best: Vec<Hit>,
+}
+
+// Cut the contigs into windows. The last window shorter than the half is merged into the previous one.
+fn split_into_windows(contigs: &[Contig], window: usize) -> Vec<Window> {
+    let mut windows = vec![];
+    for (idx, contig) in contigs.iter().enumerate() {
+        let len = contig.seq.len();
+        let mut start = 0;
+        while start < len {
+            let end = match start + window {
+                end if len < end + window / 2 => len,
+                end => end,
+            };
+            windows.push(Window {
+                contig: idx,
+                start,
+                end,
+                best: vec![],
+            });
+            start = end;
+        }
+    }
+    windows
+}
+
+fn align_windows<A: Aligner + ?Sized>(
+    contigs: &[Contig],
+    haplotypes: &[(String, Vec<u8>)],
+    windows: &mut [Window],
+    aligner: &A,
+) -> std::io::Result<()> {
+    let names: Vec<_> = (0..windows.len()).map(|i| i.to_string()).collect();
+    let targets: Vec<_> = windows
+        .iter()
+        .zip(names.iter())
+        .map(|(w, name)| (name.as_str(), &contigs[w.contig].seq[w.start..w.end]))
+        .collect();
+    let queries: Vec<_> = haplotypes
+        .iter()
+        .map(|(name, seq)| (name.as_str(), seq.as_slice()))
+        .collect();
+    let hap_index: HashMap<_, _> = haplotypes
+        .iter()
+        .enumerate()
+        .map(|(i, (name, _))| (name.as_str(), i))
+        .collect();
+    let paf = aligner.align(&targets, &queries, AlignmentFormat::Paf)?;
+    for aln in String::from_utf8_lossy(&paf)
+        .lines()
+        .filter_map(bio_utils::paf::PAF::new)
+    {
+        let (idx, hap) = match (
+            aln.tname.parse::<usize>(),
+            hap_index.get(aln.qname.as_str()),
+        ) {
+            (Ok(idx), Some(&hap)) if idx < windows.len() => (idx, hap),
+            _ => continue,
+        };
+        let span = (aln.qend - aln.qstart).max(aln.tend - aln.tstart);
+        let hit = Hit {
+            hap,
+            start: aln.qstart,
+            end: aln.qend,
+            is_forward: aln.relstrand,
+            matches: aln.matchnum,
+            errors: span.saturating_sub(aln.matchnum),
+        };
+        let best = &mut windows[idx].best;
+        match best.first().map(|b| b.errors) {
+            Some(errors) if errors < hit.errors => {}
+            Some(errors) if errors == hit.errors => best.push(hit),
+            _ => *best = vec![hit],
+        }
+    }
+    Ok(())
+}
+
+// Whether `next` continues `prev` when the contig proceeds `gap` bases.
+fn is_continuous(prev: &Hit, next: &Hit, gap: usize, tolerance: usize) -> bool {
+    if prev.hap != next.hap || prev.is_forward != next.is_forward {
+        return false;
+    }
+    let diff = match prev.is_forward {
+        true => next.start as i64 - prev.start as i64,
+        false => prev.end as i64 - next.end as i64,
+    };
+    (diff - gap as i64).abs() <= tolerance as i64
+}
+
+// The units of the contig overlapping [start, end), assuming the tiles are evenly spaced.
+fn units_in(tiles: Option<&Node>, len: usize, start: usize, end: usize) -> Vec<String> {
+    let tiles = match tiles {
+        Some(node) if !node.segments.is_empty() && len > 0 => &node.segments,
+        _ => return vec![],
+    };
+    let n = tiles.len();
+    let first = (start * n / len).min(n - 1);
+    let last = (end * n).div_ceil(len).clamp(first + 1, n);
+    tiles[first..last]
+        .iter()
+        .map(|t| format!("{}-{}", t.unit, t.cluster))
+        .collect()
+}
+
+/// Evaluate the `contigs` against the `haplotypes`. `units` are the tiles of the contigs,
+/// such as the nodes of `assemble::graphs_from_gfa`, to attribute errors to units.
+pub fn evaluate_assembly<A: Aligner + ?Sized>(
+    contigs: &[Contig],
+    haplotypes: &[(String, Vec<u8>)],
+    units: &[Node],
+    aligner: &A,
+    c: &AssemblyEvaluationConfig,
+) -> std::io::Result<(Vec<ContigEvaluation>, AssemblySummary)> {
+    let mut windows = split_into_windows(contigs, c.window);
+    debug!("Aligning {} windows", windows.len());
+    align_windows(contigs, haplotypes, &mut windows, aligner)?;
+    let units: HashMap<_, _> = units.iter().map(|n| (n.id.as_str(), n)).collect();
+    let mut by_contig: Vec<Vec<&Window>> = vec![vec![]; contigs.len()];
+    for w in windows.iter() {
+        by_contig[w.contig].push(w);
+    }
+    // Place each window along the contig, and find misjoins.
+    // (contig, window start, window end, the placed hit, the other best hits)
+    let mut placements: Vec<(usize, usize, usize, Hit, Vec<Hit>)> = vec![];
+    let mut evaluations = vec![];
+    let mut blocks: Vec<usize> = vec![];
+    for (idx, (contig, windows)) in contigs.iter().zip(by_contig.iter()).enumerate() {
+        let tiles = units.get(contig.name.as_str()).copied();
+        let len = contig.seq.len();
+        let mut counts = vec![0; haplotypes.len()];
+        for hit in windows.iter().flat_map(|w| w.best.iter()) {
+            counts[hit.hap] += 1;
+        }
+        let major = (0..haplotypes.len())
+            .filter(|&h| counts[h] > 0)
+            .max_by_key(|&h| counts[h]);
+        let mut misjoins = vec![];
+        // The hits continuing from the previous windows, and the aligned length of the current block.
+        let mut current: Vec<Hit> = vec![];
+        let mut prev_start = 0;
+        let mut block_len = 0;
+        for w in windows.iter().filter(|w| !w.best.is_empty()) {
+            let gap = w.start - prev_start;
+            let continuing: Vec<Hit> = w
+                .best
+                .iter()
+                .filter(|h| current.iter().any(|p| is_continuous(p, h, gap, c.window)))
+                .copied()
+                .collect();
+            if continuing.is_empty() && !current.is_empty() {
+                let same_hap = w
+                    .best
+                    .iter()
+                    .any(|h| current.iter().any(|p| p.hap == h.hap));
+                let misjoin_type = match same_hap {
+                    true => MisjoinType::Relocation,
+                    false => MisjoinType::Haplotype,
+                };
+                let (from, to) = (current[0].hap, w.best[0].hap);
+                misjoins.push(Misjoin {
+                    position: w.start,
+                    misjoin_type,
+                    from: haplotypes[from].0.clone(),
+                    to: haplotypes[to].0.clone(),
+                    units: units_in(tiles, len, w.start.saturating_sub(1), w.start + 1),
+                });
+                blocks.push(block_len);
+                block_len = 0;
+            }
+            current = match continuing.is_empty() {
+                true => w.best.clone(),
+                false => continuing,
+            };
+            let placed = current
+                .iter()
+                .find(|h| Some(h.hap) == major)
+                .unwrap_or(&current[0]);
+            let others: Vec<_> = w
+                .best
+                .iter()
+                .filter(|h| (h.hap, h.start) != (placed.hap, placed.start))
+                .copied()
+                .collect();
+            placements.push((idx, w.start, w.end, *placed, others));
+            prev_start = w.start;
+            block_len += w.end - w.start;
+        }
+        if block_len > 0 {
+            blocks.push(block_len);
+        }
+        let aligned: Vec<_> = windows.iter().filter(|w| !w.best.is_empty()).collect();
+        let aligned_len: usize = aligned.iter().map(|w| w.end - w.start).sum();
+        let matches: usize = aligned.iter().map(|w| w.best[0].matches).sum();
+        let errors: usize = aligned.iter().map(|w| w.best[0].errors).sum();
+        evaluations.push(ContigEvaluation {
+            contig: contig.name.clone(),
+            group: contig.group.clone(),
+            length: len,
+            haplotype: major.map(|h| haplotypes[h].0.clone()),
+            identity: matches as f64 / (matches + errors).max(1) as f64,
+            aligned_fraction: aligned_len as f64 / len.max(1) as f64,
+            misjoins,
+            duplicated_windows: 0,
+            collapsed_windows: 0,
+            error_units: vec![],
+        });
+    }
+    // Stack the placed windows on the haplotypes.
+    let mut depth: Vec<Vec<i32>> = haplotypes.iter().map(|h| vec![0; h.1.len() + 1]).collect();
+    for &(_, _, _, hit, _) in placements.iter() {
+        depth[hit.hap][hit.start] += 1;
+        depth[hit.hap][hit.end] -= 1;
+    }
+    for depth in depth.iter_mut() {
+        for i in 1..depth.len() {
+            depth[i] += depth[i - 1];
+        }
+    }
+    let mut collapsed: Vec<Vec<bool>> = haplotypes.iter().map(|h| vec![false; h.1.len()]).collect();
+    for (idx, start, end, hit, others) in placements.iter() {
+        let is_duplicated = depth[hit.hap][hit.start..hit.end].iter().any(|&d| d > 1);
+        let mut is_collapsed = false;
+        for other in others.iter() {
+            let uncovered = (other.start..other.end).filter(|&i| depth[other.hap][i] == 0);
+            for i in uncovered {
+                collapsed[other.hap][i] = true;
+                is_collapsed = true;
+            }
+        }
+        let evaluation = &mut evaluations[*idx];
+        evaluation.duplicated_windows += is_duplicated as usize;
+        evaluation.collapsed_windows += is_collapsed as usize;
+        if is_duplicated || is_collapsed {
+            let tiles = units.get(contigs[*idx].name.as_str()).copied();
+            let len = contigs[*idx].seq.len();
+            let units = units_in(tiles, len, *start, *end);
+            evaluation.error_units.extend(units);
+        }
+    }
+    for evaluation in evaluations.iter_mut() {
+        let misjoin_units = evaluation.misjoins.iter().flat_map(|m| m.units.iter());
+        evaluation.error_units.extend(misjoin_units.cloned());
+        evaluation.error_units.sort();
+        evaluation.error_units.dedup();
+    }
+    let haplotype_evaluations: Vec<_> = haplotypes
+        .iter()
+        .zip(depth.iter().zip(collapsed.iter()))
+        .map(|((name, seq), (depth, collapsed))| {
+            let depth = &depth[..seq.len()];
+            let covered = depth.iter().filter(|&&d| d > 0).count();
+            HaplotypeEvaluation {
+                haplotype: name.clone(),
+                length: seq.len(),
+                covered,
+                duplicated: depth.iter().filter(|&&d| d > 1).count(),
+                collapsed: collapsed.iter().filter(|&&b| b).count(),
+                completeness: covered as f64 / seq.len().max(1) as f64,
+            }
+        })
+        .collect();
+    let total_length = contigs.iter().map(|c| c.seq.len()).sum();
+    let aligned_length = placements.iter().map(|p| p.2 - p.1).sum();
+    let (matches, length) = evaluations.iter().fold((0f64, 0f64), |(m, l), e| {
+        let aligned = e.aligned_fraction * e.length as f64;
+        (m + e.identity * aligned, l + aligned)
+    });
+    let count = |t: MisjoinType| -> usize {
+        let misjoins = evaluations.iter().flat_map(|e| e.misjoins.iter());
+        misjoins.filter(|m| m.misjoin_type == t).count()
+    };
+    let genome_size: usize = haplotypes.iter().map(|h| h.1.len()).sum();
+    let summary = AssemblySummary {
+        contigs: contigs.len(),
+        total_length,
+        aligned_length,
+        identity: matches / length.max(1f64),
+        haplotype_misjoins: count(MisjoinType::Haplotype),
+        relocations: count(MisjoinType::Relocation),
+        nga50: crate::stats::n50(&mut blocks, genome_size),
+        haplotypes: haplotype_evaluations,
+    };
+    Ok((evaluations, summary))
+}
+
+/// Write the evaluations of the contigs in TSV.
+pub fn write_tsv<W: std::io::Write>(
+    evaluations: &[ContigEvaluation],
+    mut wtr: W,
+) -> std::io::Result<()> {
+    writeln!(
+        &mut wtr,
+        "contig\tgroup\tlength\thaplotype\tidentity\taligned\tmisjoins\tduplicated\tcollapsed\tunits"
+    )?;
+    for e in evaluations.iter() {
+        writeln!(
+            &mut wtr,
+            "{}\t{}\t{}\t{}\t{:.4}\t{:.4}\t{}\t{}\t{}\t{}",
+            e.contig,
+            e.group.as_deref().unwrap_or("-"),
+            e.length,
+            e.haplotype.as_deref().unwrap_or("-"),
+            e.identity,
+            e.aligned_fraction,
+            e.misjoins.len(),
+            e.duplicated_windows,
+            e.collapsed_windows,
+            e.error_units.join(",")
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble::Tile;
+    use crate::minimap2::EdlibAligner;
+    use crate::simulate::random_seq;
+    use rand::SeedableRng;
+    use rand_xoshiro::Xoshiro256PlusPlus;
+    #[test]
+    fn evaluate_assembly_test() {
+        let mut rng: Xoshiro256PlusPlus = SeedableRng::seed_from_u64(942);
+        let hap1 = random_seq(20_000, &mut rng);
+        let hap2 = random_seq(20_000, &mut rng);
+        let haplotypes = vec![("hap1".to_string(), hap1), ("hap2".to_string(), hap2)];
+        let (hap1, hap2) = (&haplotypes[0].1, &haplotypes[1].1);
+        // tig0 is the entire hap1 in the reverse strand, followed by 4K unaligned bases.
+        // tig1 is a misjoin between hap2[0..10K] and hap1[10K..20K].
+        let mut tig0 = bio_utils::revcmp(hap1);
+        tig0.extend(random_seq(4_000, &mut rng));
+        let tig1: Vec<_> = hap2[..10_000]
+            .iter()
+            .chain(&hap1[10_000..])
+            .copied()
+            .collect();
+        let contigs = vec![
+            Contig {
+                name: "tig0".to_string(),
+                group: None,
+                seq: tig0,
+            },
+            Contig {
+                name: "tig1".to_string(),
+                group: None,
+                seq: tig1,
+            },
+        ];
+        let tiles = (0..4)
+            .map(|unit| Tile {
+                unit,
+                cluster: 0,
+                strand: true,
+            })
+            .collect();
+        let units = vec![Node {
+            id: "tig1".to_string(),
+            segments: tiles,
+        }];
+        let aligner = EdlibAligner::new(15, 10, 0.1);
+        let config = AssemblyEvaluationConfig::new(2_000);
+        let (contigs, summary) =
+            evaluate_assembly(&contigs, &haplotypes, &units, &aligner, &config).unwrap();
+        assert_eq!(contigs[0].haplotype.as_deref(), Some("hap1"));
+        assert!(contigs[0].misjoins.is_empty());
+        assert_eq!(contigs[0].identity, 1f64);
+        assert!((contigs[0].aligned_fraction - 20f64 / 24f64).abs() < 0.001);
+        assert_eq!(contigs[1].misjoins.len(), 1);
+        let misjoin = &contigs[1].misjoins[0];
+        assert_eq!(misjoin.position, 10_000);
+        assert_eq!(misjoin.misjoin_type, MisjoinType::Haplotype);
+        assert_eq!(misjoin.units, vec!["1-0".to_string(), "2-0".to_string()]);
+        // hap1[10K..20K] is covered twice, while hap2[10K..20K] is missing.
+        assert_eq!(contigs[1].duplicated_windows, 5);
+        assert_eq!(contigs[0].duplicated_windows, 5);
+        assert_eq!(summary.haplotypes[0].duplicated, 10_000);
+        assert_eq!(summary.haplotypes[1].covered, 10_000);
+        assert_eq!(summary.haplotype_misjoins, 1);
+        // The unaligned tail of tig0 is not a part of the block.
+        assert_eq!(summary.nga50, 20_000);
+    }
+}
@@ -0,0 +1,494 @@
+//! Evaluation of the clustering against the true haplotypes of the reads.
+//! The truth is a table from a read name to its haplotype, optionally with the origin
+//! coordinates, or the descriptions of the reads written by `jtk simulate`.
+pub mod assembly;
+use crate::simulate::Origin;
+use definitions::DataSet;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+/// A locus of a unit on a haplotype should be supported by this number of nodes.
+const MIN_SUPPORT: usize = 2;
+
+/// The haplotype of a read, and its origin(start, end, is_forward) if known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Truth {
+    pub haplotype: String,
+    pub origin: Option<(usize, usize, bool)>,
+}
+
+impl std::convert::From<Origin> for Truth {
+    fn from(origin: Origin) -> Self {
+        Self {
+            haplotype: origin.haplotype,
+            origin: Some((origin.start, origin.end, origin.is_forward)),
+        }
+    }
+}
+
+/// Parse a truth table in TSV: name, haplotype, and optionally start, end, and strand(+/-).
+/// Lines starting with `#` and the header written by `jtk simulate` are skipped.
+pub fn parse_truth<R: std::io::BufRead>(reader: R) -> std::io::Result<HashMap<String, Truth>> {
+    let mut truth = HashMap::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.is_empty() || line.starts_with('#') || line.starts_with("name\thaplotype") {
+            continue;
+        }
+        let fields: Vec<_> = line.split('\t').collect();
+        if fields.len() < 2 {
+            let msg = format!("{} does not have a haplotype.", line);
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, msg));
+        }
+        let start: Option<usize> = fields.get(2).and_then(|x| x.parse().ok());
+        let end: Option<usize> = fields.get(3).and_then(|x| x.parse().ok());
+        let is_forward = fields.get(4).map(|&x| x != "-").unwrap_or(true);
+        if let (Some(start), Some(end)) = (start, end) {
+            if end < start {
+                let msg = format!("{} ends before its start.", line);
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, msg));
+            }
+        }
+        let origin = start.zip(end).map(|(start, end)| (start, end, is_forward));
+        let haplotype = fields[1].to_string();
+        truth.insert(fields[0].to_string(), Truth { haplotype, origin });
+    }
+    Ok(truth)
+}
+
+/// The truth from the descriptions of the reads, written by `jtk simulate`.
+pub fn truth_from_reads(ds: &DataSet) -> HashMap<String, Truth> {
+    ds.raw_reads
+        .iter()
+        .filter_map(|read| {
+            let origin: Origin = read.desc.parse().ok()?;
+            Some((read.name.clone(), Truth::from(origin)))
+        })
+        .collect()
+}
+
+/// The evaluation of the local clustering of a unit.
+#[derive(Debug, Clone, Serialize)]
+pub struct UnitEvaluation {
+    pub unit: u64,
+    pub cluster_num: usize,
+    /// The true copy number. The number of the loci on the haplotypes if the origins are known,
+    /// otherwise the number of the haplotypes with the unit.
+    pub copy_num: usize,
+    /// The number of the nodes of the reads with the truth.
+    pub nodes: usize,
+    pub rand_index: f64,
+    /// The fraction of the nodes in the majority haplotype of their clusters.
+    pub accuracy: f64,
+}
+
+/// The errors of a phase block, a set of clusters from `assemble::phase_blocks`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PhaseBlockEvaluation {
+    pub block: usize,
+    pub clusters: Vec<usize>,
+    pub reads: usize,
+    /// The reads not in the majority haplotype of their clusters.
+    pub hamming_errors: usize,
+    /// The adjacent reads of a haplotype, ordered by the origins, in clusters of different majority haplotypes.
+    /// Counted on each haplotype. None if the origins are not known.
+    pub switch_errors: Option<usize>,
+    pub adjacent_pairs: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Summary {
+    pub reads: usize,
+    pub reads_with_truth: usize,
+    pub haplotypes: Vec<String>,
+    pub units: usize,
+    /// The mean over the units, weighted by the number of the nodes.
+    pub rand_index: f64,
+    pub accuracy: f64,
+    pub cluster_num_correct: usize,
+    pub cluster_num_over: usize,
+    pub cluster_num_under: usize,
+    pub assigned_reads: usize,
+    /// The fraction of the reads in the majority haplotype of their clusters.
+    pub purity: f64,
+    /// The fraction of the reads in the majority cluster of their haplotypes.
+    pub completeness: f64,
+    pub hamming_errors: usize,
+    pub hamming_error_rate: f64,
+    pub switch_errors: Option<usize>,
+    pub switch_error_rate: Option<f64>,
+    pub phase_blocks: Vec<PhaseBlockEvaluation>,
+}
+
+pub trait Evaluate {
+    fn evaluate(&self, truth: &HashMap<String, Truth>) -> (Vec<UnitEvaluation>, Summary);
+}
+
+impl Evaluate for DataSet {
+    fn evaluate(&self, truth: &HashMap<String, Truth>) -> (Vec<UnitEvaluation>, Summary) {
+        let mut haplotypes: Vec<_> = truth.values().map(|t| t.haplotype.clone()).collect();
+        haplotypes.sort();
+        haplotypes.dedup();
+        let hap_index: HashMap<_, _> = haplotypes.iter().enumerate().map(|(i, h)| (h, i)).collect();
+        let truth: HashMap<u64, (usize, &Truth)> = self
+            .raw_reads
+            .iter()
+            .filter_map(|read| {
+                let truth = truth.get(&read.name)?;
+                Some((read.id, (hap_index[&truth.haplotype], truth)))
+            })
+            .collect();
+        debug!("{} reads have the truth.", truth.len());
+        let units = evaluate_units(self, &truth);
+        let total: usize = units.iter().map(|u| u.nodes).sum::<usize>().max(1);
+        let weighted = |f: fn(&UnitEvaluation) -> f64| -> f64 {
+            units.iter().map(|u| f(u) * u.nodes as f64).sum::<f64>() / total as f64
+        };
+        let rand_index = weighted(|u| u.rand_index);
+        let accuracy = weighted(|u| u.accuracy);
+        let cluster_num_correct = units.iter().filter(|u| u.cluster_num == u.copy_num).count();
+        let cluster_num_over = units.iter().filter(|u| u.cluster_num > u.copy_num).count();
+        let cluster_num_under = units.iter().filter(|u| u.cluster_num < u.copy_num).count();
+        let pairs: Vec<_> = self
+            .assignments
+            .iter()
+            .filter_map(|asn| truth.get(&asn.id).map(|&(hap, _)| (asn.cluster, hap)))
+            .collect();
+        let assigned_reads = pairs.len();
+        let purity = majority_fraction(pairs.iter().copied());
+        let completeness = majority_fraction(pairs.iter().map(|&(cl, hap)| (hap, cl)));
+        let phase_blocks = evaluate_phase_blocks(self, &truth);
+        let hamming_errors: usize = phase_blocks.iter().map(|b| b.hamming_errors).sum();
+        let hamming_error_rate = hamming_errors as f64 / assigned_reads.max(1) as f64;
+        let switch_errors: Option<usize> = phase_blocks
+            .iter()
+            .filter_map(|b| b.switch_errors)
+            .fold(None, |acc, x| Some(acc.unwrap_or(0) + x));
+        let adjacent_pairs: usize = phase_blocks.iter().map(|b| b.adjacent_pairs).sum();
+        let switch_error_rate = switch_errors.map(|x| x as f64 / adjacent_pairs.max(1) as f64);
+        let summary = Summary {
+            reads: self.raw_reads.len(),
+            reads_with_truth: truth.len(),
+            haplotypes,
+            units: units.len(),
+            rand_index,
+            accuracy,
+            cluster_num_correct,
+            cluster_num_over,
+            cluster_num_under,
+            assigned_reads,
+            purity,
+            completeness,
+            hamming_errors,
+            hamming_error_rate,
+            switch_errors,
+            switch_error_rate,
+            phase_blocks,
+        };
+        (units, summary)
+    }
+}
+
+// The fraction of the items in the majority label of their groups. Items are (group, label).
+fn majority_fraction<I: Iterator<Item = (usize, usize)>>(items: I) -> f64 {
+    let mut counts: HashMap<usize, HashMap<usize, usize>> = HashMap::new();
+    let mut total = 0;
+    for (group, label) in items {
+        *counts.entry(group).or_default().entry(label).or_default() += 1;
+        total += 1;
+    }
+    let majority: usize = counts
+        .values()
+        .map(|count| count.values().max().copied().unwrap_or(0))
+        .sum();
+    majority as f64 / total.max(1) as f64
+}
+
+fn evaluate_units(ds: &DataSet, truth: &HashMap<u64, (usize, &Truth)>) -> Vec<UnitEvaluation> {
+    // (haplotype, cluster, position on the haplotype if known)
+    let mut nodes: HashMap<u64, Vec<(usize, u64, Option<usize>)>> = HashMap::new();
+    for read in ds.encoded_reads.iter() {
+        let (hap, truth) = match truth.get(&read.id) {
+            Some(&res) => res,
+            None => continue,
+        };
+        for node in read.nodes.iter() {
+            let position = truth.origin.map(|(start, end, is_forward)| {
+                // Scale the position by the length of the origin to correct the indels.
+                let scale = (end - start) as f64 / read.original_length.max(1) as f64;
+                let pos = match is_forward {
+                    true => node.position_from_start,
+                    false => {
+                        let len = node.position_from_start + node.seq.len();
+                        read.original_length.saturating_sub(len)
+                    }
+                };
+                start + (pos as f64 * scale) as usize
+            });
+            nodes
+                .entry(node.unit)
+                .or_default()
+                .push((hap, node.cluster, position));
+        }
+    }
+    let mut units: Vec<_> = ds
+        .selected_chunks
+        .iter()
+        .filter_map(|unit| {
+            let nodes = nodes.get(&unit.id)?;
+            if nodes.len() < 2 {
+                return None;
+            }
+            let label: Vec<_> = nodes.iter().map(|x| x.0).collect();
+            let pred: Vec<_> = nodes.iter().map(|x| x.1).collect();
+            let rand_index = crate::local_clustering::rand_index(&label, &pred);
+            let accuracy = majority_fraction(nodes.iter().map(|x| (x.1 as usize, x.0)));
+            let copy_num = copy_number(nodes, unit.seq().len());
+            Some(UnitEvaluation {
+                unit: unit.id,
+                cluster_num: unit.cluster_num,
+                copy_num,
+                nodes: nodes.len(),
+                rand_index,
+                accuracy,
+            })
+        })
+        .collect();
+    units.sort_by_key(|u| u.unit);
+    units
+}
+
+// The number of the loci on each haplotype, where the positions within `len` are in the same locus.
+// If no position is known, the number of the haplotypes.
+fn copy_number(nodes: &[(usize, u64, Option<usize>)], len: usize) -> usize {
+    let mut positions: HashMap<usize, Vec<usize>> = HashMap::new();
+    for &(hap, _, pos) in nodes.iter() {
+        let positions = positions.entry(hap).or_default();
+        positions.extend(pos);
+    }
+    positions
+        .values_mut()
+        .map(|positions| {
+            if positions.is_empty() {
+                return 1;
+            }
+            positions.sort_unstable();
+            let mut loci = vec![1];
+            for w in positions.windows(2) {
+                if w[1] - w[0] < len {
+                    *loci.last_mut().unwrap() += 1;
+                } else {
+                    loci.push(1);
+                }
+            }
+            loci.iter()
+                .filter(|&&support| MIN_SUPPORT <= support)
+                .count()
+        })
+        .sum()
+}
+
+fn evaluate_phase_blocks(
+    ds: &DataSet,
+    truth: &HashMap<u64, (usize, &Truth)>,
+) -> Vec<PhaseBlockEvaluation> {
+    let blocks = crate::assemble::phase_blocks(ds);
+    // Block -> (cluster, haplotype, start of the origin)
+    let mut reads: HashMap<usize, Vec<(usize, usize, Option<usize>)>> = HashMap::new();
+    for asn in ds.assignments.iter() {
+        if let (Some(&(block, _)), Some(&(hap, truth))) =
+            (blocks.get(&asn.cluster), truth.get(&asn.id))
+        {
+            let start = truth.origin.map(|x| x.0);
+            reads
+                .entry(block)
+                .or_default()
+                .push((asn.cluster, hap, start));
+        }
+    }
+    let mut evaluations: Vec<_> = reads
+        .into_iter()
+        .map(|(block, reads)| {
+            let mut clusters: Vec<_> = reads
+                .iter()
+                .map(|x| x.0)
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .collect();
+            clusters.sort_unstable();
+            let majority = majority_haplotypes(&reads);
+            let hamming_errors = reads
+                .iter()
+                .filter(|&&(cl, hap, _)| majority[&cl] != hap)
+                .count();
+            // (start, the majority haplotype of the cluster)
+            let mut by_haplotype: HashMap<usize, Vec<(usize, usize)>> = HashMap::new();
+            for &(cl, hap, start) in reads.iter() {
+                if let Some(start) = start {
+                    by_haplotype
+                        .entry(hap)
+                        .or_default()
+                        .push((start, majority[&cl]));
+                }
+            }
+            let (mut switches, mut adjacent_pairs) = (0, 0);
+            for reads in by_haplotype.values_mut() {
+                reads.sort_unstable();
+                adjacent_pairs += reads.len() - 1;
+                switches += reads.windows(2).filter(|w| w[0].1 != w[1].1).count();
+            }
+            let switch_errors = (!by_haplotype.is_empty()).then_some(switches);
+            PhaseBlockEvaluation {
+                block,
+                clusters,
+                reads: reads.len(),
+                hamming_errors,
+                switch_errors,
+                adjacent_pairs,
+            }
+        })
+        .collect();
+    evaluations.sort_by_key(|b| b.block);
+    evaluations
+}
+
+// The majority haplotype of each cluster. Ties are broken by the smaller haplotype.
+// Several clusters can have the same haplotype, as a haplotype can be split into clusters.
+fn majority_haplotypes(reads: &[(usize, usize, Option<usize>)]) -> HashMap<usize, usize> {
+    let mut counts: HashMap<usize, HashMap<usize, usize>> = HashMap::new();
+    for &(cl, hap, _) in reads.iter() {
+        *counts.entry(cl).or_default().entry(hap).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .map(|(cl, counts)| {
+            let (hap, _) = counts
+                .into_iter()
+                .max_by(|(h1, c1), (h2, c2)| c1.cmp(c2).then(h2.cmp(h1)))
+                .unwrap();
+            (cl, hap)
+        })
+        .collect()
+}
+
+/// Write the evaluations of the units in TSV.
+pub fn write_tsv<W: std::io::Write>(units: &[UnitEvaluation], mut wtr: W) -> std::io::Result<()> {
+    writeln!(
+        &mut wtr,
+        "unit\tcluster_num\tcopy_num\tnodes\trand_index\taccuracy"
+    )?;
+    for u in units.iter() {
+        writeln!(
+            &mut wtr,
+            "{}\t{}\t{}\t{}\t{:.4}\t{:.4}",
+            u.unit, u.cluster_num, u.copy_num, u.nodes, u.rand_index, u.accuracy
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{encoded_read, raw_read};
+    use definitions::*;
+    fn node(unit: u64, cluster: u64, position_from_start: usize) -> Node {
+        crate::fixtures::node(position_from_start, unit, cluster, &"A".repeat(100))
+    }
+    #[test]
+    fn parse_truth_test() {
+        let table =
+            "name\thaplotype\tstart\tend\tstrand\nr0\thap1\t10\t1010\t-\n# comment\nr1\thap2\n";
+        let truth = parse_truth(table.as_bytes()).unwrap();
+        assert_eq!(truth.len(), 2);
+        assert_eq!(truth["r0"].origin, Some((10, 1010, false)));
+        assert_eq!(truth["r1"].haplotype, "hap2");
+        assert_eq!(truth["r1"].origin, None);
+        let error = parse_truth("r0\thap1\t1010\t10\n".as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+    #[test]
+    fn evaluate_test() {
+        // Unit 0 is at 0 and 5000 on the two haplotypes, while unit 1 is only at 500.
+        let mut ds = DataSet::with_minimum_data("test", vec![], "CLR");
+        ds.selected_chunks = vec![
+            Unit::new(0, "A".repeat(100), 4),
+            Unit::new(1, "A".repeat(100), 2),
+        ];
+        let mut truth = HashMap::new();
+        for id in 0..16u64 {
+            let (hap, locus) = (id as usize % 2, (id as usize / 2) % 2);
+            let start = 5000 * locus + id as usize;
+            let name = format!("read{}", id);
+            ds.raw_reads.push(raw_read(id, &name, &"A".repeat(1000)));
+            let origin = Some((start, start + 1000, true));
+            let haplotype = format!("hap{}", hap);
+            truth.insert(name, Truth { haplotype, origin });
+            // The 6th read is in the wrong cluster.
+            let cluster = if id == 6 { 1 - hap } else { hap };
+            let mut nodes = vec![node(0, cluster as u64, 0)];
+            if locus == 0 {
+                nodes.push(node(1, cluster as u64, 500));
+            }
+            ds.encoded_reads.push(encoded_read(id, 1000, nodes));
+            ds.assignments.push(Assignment::new(id, cluster));
+        }
+        let (units, summary) = ds.evaluate(&truth);
+        assert_eq!(units.len(), 2);
+        assert_eq!(units[0].copy_num, 4);
+        assert_eq!(units[0].accuracy, 15f64 / 16f64);
+        assert_eq!(units[1].copy_num, 2);
+        assert_eq!(units[1].accuracy, 1f64);
+        assert_eq!(summary.cluster_num_correct, 2);
+        assert_eq!(summary.reads_with_truth, 16);
+        assert_eq!(summary.phase_blocks.len(), 1);
+        assert_eq!(summary.hamming_errors, 1);
+        assert_eq!(summary.switch_errors, Some(2));
+    }
+    #[test]
+    fn large_cluster_test() {
+        // Cluster 256 should not be confused with cluster 0.
+        let mut ds = DataSet::with_minimum_data("test", vec![], "CLR");
+        ds.selected_chunks = vec![Unit::new(0, "A".repeat(100), 2)];
+        let mut truth = HashMap::new();
+        for &(id, cluster) in [(0, 0), (1, 256)].iter() {
+            let name = format!("read{}", id);
+            ds.raw_reads.push(raw_read(id, &name, &"A".repeat(1000)));
+            let (haplotype, origin) = ("hap0".to_string(), None);
+            truth.insert(name, Truth { haplotype, origin });
+            ds.encoded_reads
+                .push(encoded_read(id, 1000, vec![node(0, cluster, 0)]));
+            ds.assignments.push(Assignment::new(id, cluster as usize));
+        }
+        let (units, _) = ds.evaluate(&truth);
+        assert_eq!(units[0].rand_index, 0f64);
+    }
+    #[test]
+    fn split_haplotype_test() {
+        // Three clusters in a block on two haplotypes. hap0 is split into clusters 0 and 2.
+        let mut ds = DataSet::with_minimum_data("test", vec![], "CLR");
+        ds.selected_chunks = vec![Unit::new(0, "A".repeat(100), 3)];
+        let mut truth = HashMap::new();
+        for id in 0..12u64 {
+            let hap = id as usize % 2;
+            let cluster = match (hap, id < 6) {
+                (0, true) => 0,
+                (0, false) => 2,
+                _ => 1,
+            };
+            let name = format!("read{}", id);
+            ds.raw_reads.push(raw_read(id, &name, &"A".repeat(1000)));
+            let start = 100 * id as usize;
+            let origin = Some((start, start + 1000, true));
+            let haplotype = format!("hap{}", hap);
+            truth.insert(name, Truth { haplotype, origin });
+            let nodes = vec![node(0, cluster as u64, 0)];
+            ds.encoded_reads.push(encoded_read(id, 1000, nodes));
+            ds.assignments.push(Assignment::new(id, cluster));
+        }
+        let (_, summary) = ds.evaluate(&truth);
+        assert_eq!(summary.phase_blocks.len(), 1);
+        assert_eq!(summary.phase_blocks[0].clusters, vec![0, 1, 2]);
+        assert_eq!(summary.hamming_errors, 0);
+        assert_eq!(summary.switch_errors, Some(0));
+    }
+}
@@ -4,15 +4,72 @@ pub enum ExtractTarget {
     HiCReads,
     Units,
     Assignments,
+    /// Alignments of the encoded reads to the selected chunks.
+    UnitAlignments,
+    /// Phased variants between the clusters of each unit.
+    Variants,
 }
 
 pub trait Extract {
     fn extract_fasta(&self, target: ExtractTarget) -> Vec<fasta::Record>;
     fn extract_assignments(&self) -> Vec<(usize, String, String)>;
+    /// Alignments between reads and units, one record per node, sorted by the coordinate.
+    /// The selected chunks are the reference sequences, and the cluster of a node is recorded in
+    /// `HP`(1-origin, to be grouped by IGV) and `uc`(0-origin) tags.
+    fn extract_alignments(&self) -> (sam::SamHeader, Vec<sam::SamRecord>);
+    /// Variants between the haplotypes, i.e., clusters, of each unit, with the selected chunks as the reference.
+    /// Returns the (name, length) pairs of the references and the variants. See [vcf](crate::vcf) for details.
+    fn extract_variants(&self) -> (Vec<(String, usize)>, Vec<vcf::Variant>);
 }
 
+use crate::sam;
+use crate::vcf;
 use bio_utils::fasta;
 impl Extract for definitions::DataSet {
+    fn extract_alignments(&self) -> (sam::SamHeader, Vec<sam::SamRecord>) {
+        use std::collections::HashMap;
+        let references: Vec<_> = self
+            .selected_chunks
+            .iter()
+            .map(|u| (format!("{}", u.id), u.seq().len()))
+            .collect();
+        let unit_index: HashMap<_, _> = self
+            .selected_chunks
+            .iter()
+            .enumerate()
+            .map(|(idx, u)| (u.id, idx))
+            .collect();
+        let id2name: HashMap<_, _> = self.raw_reads.iter().map(|r| (r.id, &r.name)).collect();
+        let mut records: Vec<_> = self
+            .encoded_reads
+            .iter()
+            .flat_map(|read| {
+                let name = id2name.get(&read.id).map(|x| x.as_str()).unwrap_or("-");
+                let unit_index = &unit_index;
+                read.nodes
+                    .iter()
+                    .enumerate()
+                    .filter_map(move |(idx, node)| {
+                        let reference = *unit_index.get(&node.unit)?;
+                        let supplementary = if idx == 0 { 0 } else { sam::FLAG_SUPPLEMENTARY };
+                        let record = node_to_record(node, name, reference)?;
+                        let flag = record.flag | supplementary;
+                        Some(sam::SamRecord { flag, ..record })
+                    })
+            })
+            .collect();
+        let mut header = sam::SamHeader::new(references);
+        sam::sort_records(&mut header, &mut records);
+        (header, records)
+    }
+    fn extract_variants(&self) -> (Vec<(String, usize)>, Vec<vcf::Variant>) {
+        let references: Vec<_> = self
+            .selected_chunks
+            .iter()
+            .map(|u| (format!("{}", u.id), u.seq().len()))
+            .collect();
+        (references, vcf::call_variants(self))
+    }
     fn extract_assignments(&self) -> Vec<(usize, String, String)> {
         use std::collections::HashMap;
         let id2name: HashMap<_, _> = self
@@ -66,3 +123,121 @@ impl Extract for definitions::DataSet {
         }
     }
 }
+
+// Convert a node into an alignment record. As the cigar of a node spans the entire unit,
+// leading/trailing deletions are trimmed and leading/trailing insertions are soft-clipped.
+fn node_to_record(
+    node: &definitions::Node,
+    name: &str,
+    reference: usize,
+) -> Option<sam::SamRecord> {
+    use definitions::Op;
+    let mut cigar: Vec<sam::CigarOp> = vec![];
+    for op in node.cigar.iter() {
+        let op = match *op {
+            Op::Match(l) => sam::CigarOp::Match(l as u32),
+            Op::Ins(l) => sam::CigarOp::Ins(l as u32),
+            Op::Del(l) => sam::CigarOp::Del(l as u32),
+        };
+        match (cigar.last_mut(), op) {
+            (Some(sam::CigarOp::Match(l)), sam::CigarOp::Match(m))
+            | (Some(sam::CigarOp::Ins(l)), sam::CigarOp::Ins(m))
+            | (Some(sam::CigarOp::Del(l)), sam::CigarOp::Del(m)) => *l += m,
+            _ => cigar.push(op),
+        }
+    }
+    let (mut pos, mut head_clip, mut tail_clip) = (0, 0, 0);
+    cigar.reverse();
+    while let Some(&op) = cigar.last() {
+        match op {
+            sam::CigarOp::Del(l) => pos += l as usize,
+            sam::CigarOp::Ins(l) => head_clip += l,
+            _ => break,
+        }
+        cigar.pop();
+    }
+    cigar.reverse();
+    while let Some(&op) = cigar.last() {
+        match op {
+            sam::CigarOp::Del(_) => {}
+            sam::CigarOp::Ins(l) => tail_clip += l,
+            _ => break,
+        }
+        cigar.pop();
+    }
+    if cigar.is_empty() {
+        return None;
+    }
+    if head_clip > 0 {
+        cigar.insert(0, sam::CigarOp::SoftClip(head_clip));
+    }
+    if tail_clip > 0 {
+        cigar.push(sam::CigarOp::SoftClip(tail_clip));
+    }
+    let flag = if node.is_forward {
+        0
+    } else {
+        sam::FLAG_REVERSE
+    };
+    let qual = node
+        .qual()
+        .map(|q| q.iter().map(|x| x.saturating_sub(33)).collect())
+        .unwrap_or_default();
+    let cluster = node.cluster as i32;
+    Some(sam::SamRecord {
+        qname: name.to_string(),
+        flag,
+        reference,
+        pos,
+        mapq: 255,
+        cigar,
+        seq: node.seq().to_vec(),
+        qual,
+        tags: vec![
+            sam::Tag::Int(*b"HP", cluster + 1),
+            sam::Tag::Int(*b"uc", cluster),
+        ],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use definitions::Op;
+    #[test]
+    fn node_to_record_test() {
+        let mut node = crate::fixtures::node(10, 0, 1, "AACGTAC");
+        node.is_forward = false;
+        node.cigar = vec![
+            Op::Del(2),
+            Op::Ins(1),
+            Op::Ins(1),
+            Op::Match(2),
+            Op::Del(1),
+            Op::Match(1),
+            Op::Match(1),
+            Op::Ins(1),
+            Op::Del(3),
+        ];
+        node.qual = Some("IIIIIII".to_string());
+        let record = node_to_record(&node, "read", 3).unwrap();
+        assert_eq!(record.reference, 3);
+        assert_eq!(record.pos, 2);
+        assert_eq!(record.flag, sam::FLAG_REVERSE);
+        let cigar = vec![
+            sam::CigarOp::SoftClip(2),
+            sam::CigarOp::Match(2),
+            sam::CigarOp::Del(1),
+            sam::CigarOp::Match(2),
+            sam::CigarOp::SoftClip(1),
+        ];
+        assert_eq!(record.cigar, cigar);
+        assert_eq!(record.end(), 7);
+        assert_eq!(record.seq, b"AACGTAC".to_vec());
+        assert_eq!(record.qual, vec![40; 7]);
+        let tags = vec![sam::Tag::Int(*b"HP", 2), sam::Tag::Int(*b"uc", 1)];
+        assert_eq!(record.tags, tags);
+        node.cigar = vec![Op::Del(3), Op::Ins(7)];
+        assert!(node_to_record(&node, "read", 3).is_none());
+    }
+}
@@ -0,0 +1,36 @@
+//! Reads and nodes to build small DataSets in tests.
+use definitions::{EncodedRead, Node, Op, RawRead};
+
+/// A read without any description or quality.
+pub fn raw_read(id: u64, name: &str, seq: &str) -> RawRead {
+    RawRead {
+        name: name.to_string(),
+        desc: String::new(),
+        id,
+        seq: seq.to_string(),
+        qual: None,
+    }
+}
+
+/// A forward node aligned to the unit without any indel.
+pub fn node(position_from_start: usize, unit: u64, cluster: u64, seq: &str) -> Node {
+    Node {
+        position_from_start,
+        unit,
+        cluster,
+        seq: seq.to_string(),
+        is_forward: true,
+        cigar: vec![Op::Match(seq.len())],
+        qual: None,
+    }
+}
+
+/// An encoded read without any gap or edge.
+pub fn encoded_read(id: u64, original_length: usize, nodes: Vec<Node>) -> EncodedRead {
+    EncodedRead {
+        id,
+        original_length,
+        nodes,
+        ..EncodedRead::default()
+    }
+}
@@ -0,0 +1,244 @@
+//! Joining phase blocks by Hi-C contacts.
+//! First, reads are clustered by `global_clustering`, and each cluster is regarded as a phase block.
+//! Then, two blocks are joined if there are enough Hi-C edges between them, and they
+//! are the dominant partners of each other. Blocks sharing a unit with different clusters,
+//! i.e., the other haplotype of the same region, are never joined.
+use super::{GlobalClustering, GlobalClusteringConfig};
+use crate::find_union::FindUnion;
+use definitions::{Assignment, DataSet};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct HiCPhasingConfig {
+    pub global: GlobalClusteringConfig,
+    /// The minimum number of Hi-C edges to join two blocks.
+    pub min_contacts: usize,
+    /// The minimum fraction of the Hi-C edges between two blocks in all the Hi-C edges from each block.
+    pub min_fraction: f64,
+}
+
+impl HiCPhasingConfig {
+    pub fn new(global: GlobalClusteringConfig, min_contacts: usize, min_fraction: f64) -> Self {
+        Self {
+            global,
+            min_contacts,
+            min_fraction,
+        }
+    }
+}
+
+pub fn global_clustering_hic(ds: DataSet, c: &HiCPhasingConfig) -> DataSet {
+    let mut ds = ds.global_clustering(&c.global);
+    info!(
+        "Phase block N50 before Hi-C:{}",
+        phase_block_n50(&ds, &ds.assignments)
+    );
+    if ds.hic_edges.is_empty() {
+        debug!("No Hi-C edges. Run `jtk hic` in advance.");
+        return ds;
+    }
+    ds.assignments = join_blocks(&ds, c);
+    info!(
+        "Phase block N50 after Hi-C:{}",
+        phase_block_n50(&ds, &ds.assignments)
+    );
+    ds
+}
+
+fn join_blocks(ds: &DataSet, c: &HiCPhasingConfig) -> Vec<Assignment> {
+    // Renumber the blocks into 0..blocks.len().
+    let blocks: Vec<usize> = {
+        let blocks: HashSet<_> = ds.assignments.iter().map(|asn| asn.cluster).collect();
+        let mut blocks: Vec<_> = blocks.into_iter().collect();
+        blocks.sort_unstable();
+        blocks
+    };
+    let block_index: HashMap<_, _> = blocks.iter().enumerate().map(|(i, &b)| (b, i)).collect();
+    let read_block: HashMap<_, _> = ds
+        .assignments
+        .iter()
+        .map(|asn| (asn.id, block_index[&asn.cluster]))
+        .collect();
+    // (unit, cluster) -> block, by the majority of the reads.
+    let mut votes: HashMap<(u64, u64), HashMap<usize, u32>> = HashMap::new();
+    for read in ds.encoded_reads.iter() {
+        let block = match read_block.get(&read.id) {
+            Some(&block) => block,
+            None => continue,
+        };
+        for node in read.nodes.iter() {
+            *votes
+                .entry((node.unit, node.cluster))
+                .or_default()
+                .entry(block)
+                .or_default() += 1;
+        }
+    }
+    // block -> unit -> cluster, by the majority of the nodes, so that a few misclustered nodes
+    // do not make a conflict.
+    let mut contents: Vec<HashMap<u64, u64>> = vec![HashMap::new(); blocks.len()];
+    {
+        let mut majority: HashMap<(usize, u64), (u32, u64)> = HashMap::new();
+        for (&(unit, cluster), votes) in votes.iter() {
+            for (&block, &count) in votes.iter() {
+                let best = majority.entry((block, unit)).or_insert((count, cluster));
+                *best = (*best).max((count, cluster));
+            }
+        }
+        for ((block, unit), (_, cluster)) in majority {
+            contents[block].insert(unit, cluster);
+        }
+    }
+    let node_block: HashMap<_, _> = votes
+        .into_iter()
+        .filter_map(|(node, votes)| {
+            let (&block, _) = votes.iter().max_by_key(|x| (x.1, x.0))?;
+            Some((node, block))
+        })
+        .collect();
+    let mut contacts: HashMap<(usize, usize), usize> = HashMap::new();
+    let mut totals = vec![0; blocks.len()];
+    for edge in ds.hic_edges.iter() {
+        let block1 = node_block.get(&(edge.pair1, edge.cluster1));
+        let block2 = node_block.get(&(edge.pair2, edge.cluster2));
+        if let (Some(&b1), Some(&b2)) = (block1, block2) {
+            if b1 != b2 {
+                *contacts.entry((b1.min(b2), b1.max(b2))).or_default() += 1;
+                totals[b1] += 1;
+                totals[b2] += 1;
+            }
+        }
+    }
+    let mut contacts: Vec<_> = contacts.into_iter().collect();
+    contacts.sort_by(|x, y| y.1.cmp(&x.1).then(x.0.cmp(&y.0)));
+    let mut fu = FindUnion::new(blocks.len());
+    for ((b1, b2), count) in contacts {
+        let is_dominant = |b: usize| c.min_fraction * totals[b] as f64 <= count as f64;
+        if count < c.min_contacts || !is_dominant(b1) || !is_dominant(b2) {
+            continue;
+        }
+        let (r1, r2) = (fu.find(b1).unwrap(), fu.find(b2).unwrap());
+        if r1 == r2 {
+            continue;
+        }
+        let is_conflicting = contents[r1]
+            .iter()
+            .any(|(unit, cl)| contents[r2].get(unit).is_some_and(|cl2| cl != cl2));
+        if is_conflicting {
+            trace!("CONFLICT\t{}\t{}\t{}", blocks[b1], blocks[b2], count);
+            continue;
+        }
+        debug!(
+            "Join {} and {} by {} contacts.",
+            blocks[b1], blocks[b2], count
+        );
+        fu.unite(r1, r2).unwrap();
+        let root = fu.find(r1).unwrap();
+        let (content1, content2) = (
+            std::mem::take(&mut contents[r1]),
+            std::mem::take(&mut contents[r2]),
+        );
+        contents[root].extend(content1.into_iter().chain(content2));
+    }
+    ds.assignments
+        .iter()
+        .map(|asn| {
+            let root = fu.find(block_index[&asn.cluster]).unwrap();
+            Assignment::new(asn.id, blocks[root])
+        })
+        .collect()
+}
+
+/// The N50 of the phase blocks, i.e., clusters of `assignments`.
+/// The length of a block is the total length of the units in the reads assigned to it.
+pub fn phase_block_n50(ds: &DataSet, assignments: &[Assignment]) -> usize {
+    let unit_len: HashMap<_, _> = ds
+        .selected_chunks
+        .iter()
+        .map(|u| (u.id, u.seq().len()))
+        .collect();
+    let read_block: HashMap<_, _> = assignments.iter().map(|a| (a.id, a.cluster)).collect();
+    let mut units: HashMap<usize, HashSet<u64>> = HashMap::new();
+    for read in ds.encoded_reads.iter() {
+        if let Some(block) = read_block.get(&read.id) {
+            let units = units.entry(*block).or_default();
+            units.extend(read.nodes.iter().map(|n| n.unit));
+        }
+    }
+    let mut lens: Vec<usize> = units
+        .values()
+        .map(|units| units.iter().filter_map(|u| unit_len.get(u)).sum())
+        .collect();
+    let total: usize = lens.iter().sum();
+    crate::stats::n50(&mut lens, total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn join_blocks_test() {
+        use definitions::*;
+        let node = |unit: u64, cluster: u64| Node {
+            position_from_start: 0,
+            unit,
+            cluster,
+            seq: String::new(),
+            qual: None,
+            is_forward: true,
+            cigar: vec![],
+        };
+        let read = |id: u64, nodes: Vec<Node>| EncodedRead {
+            id,
+            original_length: 0,
+            leading_gap: vec![],
+            trailing_gap: vec![],
+            nodes,
+            edges: vec![],
+        };
+        let mut ds = DataSet::with_minimum_data("", vec![], "CLR");
+        ds.selected_chunks = (0..4).map(|i| Unit::new(i, "A".repeat(100), 2)).collect();
+        // Two haplotypes, each splitted into [0,1] and [2,3].
+        ds.encoded_reads = vec![
+            read(0, vec![node(0, 0), node(1, 0)]),
+            read(1, vec![node(0, 1), node(1, 1)]),
+            read(2, vec![node(2, 0), node(3, 0)]),
+            read(3, vec![node(2, 1), node(3, 1)]),
+            read(4, vec![node(2, 0), node(3, 0)]),
+            read(5, vec![node(2, 0), node(3, 0)]),
+            // Block 0 reaches unit 2, where the last node is misclustered.
+            read(6, vec![node(0, 0), node(1, 0), node(2, 0)]),
+            read(7, vec![node(0, 0), node(1, 0), node(2, 0)]),
+            read(8, vec![node(0, 0), node(1, 0), node(2, 1)]),
+        ];
+        let blocks = [0, 1, 2, 3, 2, 2, 0, 0, 0];
+        ds.assignments = (0..9)
+            .map(|i| Assignment::new(i, blocks[i as usize]))
+            .collect();
+        let contact = |pair_id, (pair1, cluster1), (pair2, cluster2)| HiCEdge {
+            pair_id,
+            pair1,
+            pair2,
+            cluster1,
+            cluster2,
+        };
+        ds.hic_edges = (0..10)
+            .map(|i| contact(i, (0, 0), (3, 0)))
+            .chain((10..20).map(|i| contact(i, (1, 1), (2, 1))))
+            .chain((20..22).map(|i| contact(i, (0, 0), (2, 1))))
+            // Contacts between the two haplotypes of the same region should be ignored.
+            .chain((22..40).map(|i| contact(i, (0, 0), (1, 1))))
+            .collect();
+        assert_eq!(phase_block_n50(&ds, &ds.assignments), 200);
+        let config = GlobalClusteringConfig::new(3, 10, 1, -1, -2);
+        let config = HiCPhasingConfig::new(config, 5, 0.3);
+        let assignments = join_blocks(&ds, &config);
+        let cluster: Vec<_> = assignments.iter().map(|a| a.cluster).collect();
+        assert_eq!(cluster[0], cluster[2]);
+        assert_eq!(cluster[1], cluster[3]);
+        assert_ne!(cluster[0], cluster[1]);
+        assert_eq!(cluster[0], cluster[8]);
+        assert_eq!(phase_block_n50(&ds, &assignments), 400);
+    }
+}
@@ -2,10 +2,13 @@
 pub mod error_correction;
 use super::unit_correlation;
 mod clustering;
+mod hic_phasing;
 pub mod path_clustering;
 use de_bruijn_graph::*;
 use definitions::DataSet;
+pub use hic_phasing::{phase_block_n50, HiCPhasingConfig};
 use path_phasing::phase_with_lk;
+use serde::Serialize;
 use std::collections::{HashMap, HashSet};
 struct ReadWrapper<'a>(&'a definitions::EncodedRead);
 struct FilteredRead {
@@ -80,7 +83,7 @@ impl<'a> IntoDeBruijnNodes for ReadWrapper<'a> {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize)]
 pub struct GlobalClusteringConfig {
     pub k_mer: usize,
     pub min_cluster_size: usize,
@@ -111,9 +114,14 @@ impl GlobalClusteringConfig {
 pub trait GlobalClustering {
     fn global_clustering_graph(self, c: &GlobalClusteringConfig) -> Self;
     fn global_clustering(self, c: &GlobalClusteringConfig) -> Self;
+    /// `global_clustering`, followed by joining the resulting clusters(phase blocks) by `hic_edges`.
+    fn global_clustering_hic(self, c: &HiCPhasingConfig) -> Self;
 }
 
 impl GlobalClustering for definitions::DataSet {
+    fn global_clustering_hic(self, c: &HiCPhasingConfig) -> Self {
+        hic_phasing::global_clustering_hic(self, c)
+    }
     fn global_clustering_graph(mut self, _c: &GlobalClusteringConfig) -> Self {
         let c = GlobalClusteringConfig::new(3, 10, 1, -1, -2);
         let reads = error_correction::local_correction(&self, &c);
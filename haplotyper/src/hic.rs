@@ -0,0 +1,228 @@
+//! Hi-C read pairs.
+//! Each mate is mapped onto the selected chunks by minimap2, and then assigned to the cluster
+//! of the nearest node on the unit. Pairs linking two different (unit, cluster) nodes become `hic_edges`.
+use crate::entry::ReadRecord;
+use crate::minimap2::Minimap2;
+use definitions::{DataSet, HiCEdge, HiCPair};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+
+// The maximum number of nodes to be compared with a mate, for each cluster.
+const MAX_NODES_PER_CLUSTER: usize = 20;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HiCConfig {
+    // The maximum edit distance between a mate and a node, divided by the length of the mate.
+    max_diff: f64,
+    // The aligner to map the mates onto the units.
+    aligner: Minimap2,
+}
+
+impl std::default::Default for HiCConfig {
+    fn default() -> Self {
+        Self {
+            max_diff: 0.2,
+            aligner: default_minimap2(1),
+        }
+    }
+}
+
+impl HiCConfig {
+    pub fn new(threads: usize, max_diff: f64) -> Self {
+        Self {
+            max_diff,
+            aligner: default_minimap2(threads),
+        }
+    }
+    /// Replace the aligner, by default [default_minimap2](default_minimap2).
+    pub fn aligner(mut self, aligner: Minimap2) -> Self {
+        self.aligner = aligner;
+        self
+    }
+}
+
+/// The minimap2 backend to map Hi-C mates onto units.
+pub fn default_minimap2(threads: usize) -> Minimap2 {
+    Minimap2::new(threads).preset("sr")
+}
+
+pub trait HiC {
+    /// Append Hi-C read pairs. The i-th records of `mates1` and `mates2` are the mates of a pair.
+    fn add_hic_pairs(
+        &mut self,
+        mates1: &[ReadRecord],
+        mates2: &[ReadRecord],
+    ) -> std::io::Result<()>;
+    /// Map each mate onto the selected chunks, and fill `hic_edges` by the pairs linking
+    /// two different (unit, cluster) nodes. Previous `hic_edges` are discarded.
+    /// As the clusters are taken from the encoded reads, call this again after clustering.
+    fn compute_hic_edges(&mut self, c: &HiCConfig) -> std::io::Result<()>;
+}
+
+impl HiC for DataSet {
+    fn add_hic_pairs(
+        &mut self,
+        mates1: &[ReadRecord],
+        mates2: &[ReadRecord],
+    ) -> std::io::Result<()> {
+        if mates1.len() != mates2.len() {
+            let msg = format!(
+                "The numbers of mates differ:{} and {}",
+                mates1.len(),
+                mates2.len()
+            );
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, msg));
+        }
+        let offset = self.hic_pairs.len() as u64;
+        let pairs = mates1
+            .iter()
+            .zip(mates2.iter())
+            .enumerate()
+            .map(|(idx, (m1, m2))| {
+                let pair_id = offset + idx as u64;
+                HiCPair {
+                    pair1: 2 * pair_id,
+                    pair2: 2 * pair_id + 1,
+                    pair_id,
+                    seq1: to_upper(&m1.seq),
+                    seq2: to_upper(&m2.seq),
+                }
+            });
+        self.hic_pairs.extend(pairs);
+        debug!("{} Hi-C pairs in total.", self.hic_pairs.len());
+        Ok(())
+    }
+    fn compute_hic_edges(&mut self, c: &HiCConfig) -> std::io::Result<()> {
+        self.hic_edges.clear();
+        if self.hic_pairs.is_empty() || self.selected_chunks.is_empty() {
+            return Ok(());
+        }
+        let paf = mm2_hic_alignment(self, c)?;
+        // The longest alignment of each mate.
+        let mut best_hits: HashMap<String, bio_utils::paf::PAF> = HashMap::new();
+        let alignments = String::from_utf8_lossy(&paf)
+            .lines()
+            .filter_map(bio_utils::paf::PAF::new)
+            .collect::<Vec<_>>();
+        for aln in alignments {
+            let len = aln.qend - aln.qstart;
+            match best_hits.get(&aln.qname) {
+                Some(best) if len <= best.qend - best.qstart => {}
+                _ => {
+                    best_hits.insert(aln.qname.clone(), aln);
+                }
+            }
+        }
+        debug!("{} mates are mapped.", best_hits.len());
+        let mut nodes: HashMap<u64, HashMap<u64, Vec<&[u8]>>> = HashMap::new();
+        for node in self.encoded_reads.iter().flat_map(|r| r.nodes.iter()) {
+            let slot = nodes
+                .entry(node.unit)
+                .or_default()
+                .entry(node.cluster)
+                .or_default();
+            if slot.len() < MAX_NODES_PER_CLUSTER {
+                slot.push(node.seq());
+            }
+        }
+        let map_mate = |name: String, seq: &[u8]| -> Option<(u64, u64)> {
+            let aln = best_hits.get(&name)?;
+            let unit: u64 = aln.tname.parse().ok()?;
+            let query = if aln.relstrand {
+                seq[aln.qstart..aln.qend].to_vec()
+            } else {
+                bio_utils::revcmp(&seq[aln.qstart..aln.qend])
+            };
+            let cluster = match nodes.get(&unit) {
+                Some(clusters) => nearest_cluster(&query, clusters, c)?,
+                None => 0,
+            };
+            Some((unit, cluster))
+        };
+        let edges: Vec<_> = self
+            .hic_pairs
+            .par_iter()
+            .filter_map(|pair| {
+                let (pair1, cluster1) = map_mate(format!("{}_1", pair.pair_id), pair.seq1())?;
+                let (pair2, cluster2) = map_mate(format!("{}_2", pair.pair_id), pair.seq2())?;
+                let edge = HiCEdge {
+                    pair_id: pair.pair_id,
+                    pair1,
+                    pair2,
+                    cluster1,
+                    cluster2,
+                };
+                ((pair1, cluster1) != (pair2, cluster2)).then_some(edge)
+            })
+            .collect();
+        debug!(
+            "{} Hi-C edges out of {} pairs.",
+            edges.len(),
+            self.hic_pairs.len()
+        );
+        self.hic_edges = edges;
+        Ok(())
+    }
+}
+
+fn to_upper(seq: &[u8]) -> String {
+    seq.iter()
+        .map(|&x| (x as char).to_ascii_uppercase())
+        .collect()
+}
+
+// Return the cluster of the node nearest to `query`. If the nearest nodes are in
+// different clusters, or too far, return None.
+fn nearest_cluster(
+    query: &[u8],
+    clusters: &HashMap<u64, Vec<&[u8]>>,
+    c: &HiCConfig,
+) -> Option<u64> {
+    if clusters.len() == 1 {
+        return clusters.keys().next().copied();
+    }
+    let dist = |seq: &[u8]| {
+        let mode = edlib_sys::AlignMode::Infix;
+        let task = edlib_sys::AlignTask::Alignment;
+        edlib_sys::edlib_align(query, seq, mode, task).dist
+    };
+    let dists: Vec<_> = clusters
+        .iter()
+        .filter_map(|(&cluster, seqs)| {
+            seqs.iter()
+                .map(|seq| dist(seq))
+                .min()
+                .map(|dist| (cluster, dist))
+        })
+        .collect();
+    let &(cluster, min) = dists.iter().min_by_key(|x| x.1)?;
+    let thr = (query.len() as f64 * c.max_diff).floor() as u32;
+    let is_unique = dists.iter().filter(|x| x.1 == min).count() == 1;
+    (min <= thr && is_unique).then_some(cluster)
+}
+
+fn mm2_hic_alignment(ds: &DataSet, c: &HiCConfig) -> std::io::Result<Vec<u8>> {
+    use crate::minimap2::{Aligner, AlignmentFormat};
+    let ids: Vec<_> = ds
+        .selected_chunks
+        .iter()
+        .map(|u| u.id.to_string())
+        .collect();
+    let units: Vec<_> = ids
+        .iter()
+        .zip(ds.selected_chunks.iter())
+        .map(|(id, u)| (id.as_str(), u.seq()))
+        .collect();
+    let names: Vec<_> = ds
+        .hic_pairs
+        .iter()
+        .map(|p| (format!("{}_1", p.pair_id), format!("{}_2", p.pair_id)))
+        .collect();
+    let mates: Vec<_> = names
+        .iter()
+        .zip(ds.hic_pairs.iter())
+        .flat_map(|((n1, n2), p)| vec![(n1.as_str(), p.seq1()), (n2.as_str(), p.seq2())])
+        .collect();
+    c.aligner.align(&units, &mates, AlignmentFormat::Paf)
+}
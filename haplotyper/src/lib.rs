@@ -4,29 +4,46 @@ mod determine_units;
 pub mod em_correction;
 pub mod encode;
 mod entry;
+pub mod evaluate;
 mod extract;
 mod filter_unit;
 pub mod find_union;
+#[cfg(test)]
+mod fixtures;
 pub mod global_clustering;
+pub mod hic;
 pub mod local_clustering;
+mod merge;
 pub mod minimap2;
 pub mod multiplicity_estimation;
 mod pick_component;
 mod polish_clustering;
 mod polish_units;
 pub mod repeat_masking;
+pub mod report;
 pub mod resolve_unit_repeats;
+pub mod sam;
+pub mod simulate;
+pub mod stream;
+mod subset;
+pub mod typing;
 pub mod unit_correlation;
+pub mod vcf;
 mod view;
 #[macro_use]
 extern crate log;
 pub use assemble::{Assemble, AssembleConfig};
 pub use encode::Encode;
-pub use entry::Entry;
+pub use entry::{parse_reads, Entry, ReadRecord};
+pub use evaluate::Evaluate;
 pub use extract::Extract;
 pub use extract::ExtractTarget;
+pub use hic::{HiC, HiCConfig};
+pub use merge::Merge;
 pub use repeat_masking::{RepeatMask, RepeatMaskConfig};
-pub use view::View;
+pub use report::Report;
+pub use subset::{Subset, SubsetConfig};
+pub use view::{View, ViewIndex};
 pub mod stats;
 pub use determine_units::*;
 pub use em_correction::ClusteringCorrection;
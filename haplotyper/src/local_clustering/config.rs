@@ -7,12 +7,14 @@ const STABLE_LIMIT: u32 = 6;
 const VARIANT_NUMBER: usize = 2;
 const P_VALUE: f64 = 0.01;
 const RETRY_LIMIT: u64 = 4;
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ClusteringConfig<F: Fn(u8, u8) -> i32> {
     pub cluster_num: usize,
     pub subchunk_length: usize,
     pub limit: u64,
+    #[serde(skip)]
     pub alnparam: AlignmentParameters<F>,
+    #[serde(skip)]
     pub poa_config: poa_hmm::Config,
     pub id: u64,
     pub stable_limit: u32,
@@ -16,7 +16,7 @@ use variant_calling::*;
 pub mod kmeans;
 
 /// Return rand index.
-pub fn rand_index(label: &[u8], pred: &[u8]) -> f64 {
+pub fn rand_index<L: PartialEq, P: PartialEq>(label: &[L], pred: &[P]) -> f64 {
     assert_eq!(label.len(), pred.len());
     let mut both_same_pair = 0;
     let mut both_diff_pair = 0;
@@ -0,0 +1,131 @@
+//! Merging two DataSets, e.g., two sequencing runs of a sample.
+use definitions::DataSet;
+use std::io::{Error, ErrorKind};
+
+pub trait Merge: Sized {
+    /// Append `other` to this dataset. The IDs of the reads, units, Hi-C pairs and clusters of `other`
+    /// are renumbered so that they do not collide with the ones of this dataset,
+    /// and the references to them are rewritten accordingly.
+    /// The read types of the two datasets should be the same.
+    fn merge(self, other: Self) -> std::io::Result<Self>;
+}
+
+fn next_id<I: Iterator<Item = u64>>(ids: I) -> u64 {
+    ids.max().map(|x| x + 1).unwrap_or(0)
+}
+
+impl Merge for DataSet {
+    fn merge(mut self, mut other: Self) -> std::io::Result<Self> {
+        if self.read_type != other.read_type {
+            let msg = format!(
+                "Read types differ:{:?} and {:?}",
+                self.read_type, other.read_type
+            );
+            return Err(Error::new(ErrorKind::InvalidData, msg));
+        }
+        let read_offset = next_id(self.raw_reads.iter().map(|r| r.id));
+        let pair_offset = next_id(self.hic_pairs.iter().map(|p| p.pair_id));
+        let cluster_offset = next_id(self.assignments.iter().map(|a| a.cluster as u64)) as usize;
+        let unit_offset = next_id(
+            self.selected_chunks.iter().map(|u| u.id).chain(
+                self.encoded_reads
+                    .iter()
+                    .flat_map(|r| r.nodes.iter().map(|n| n.unit)),
+            ),
+        );
+        let unit = |id: u64| id + unit_offset;
+        for u in other.selected_chunks.iter_mut() {
+            u.id = unit(u.id);
+        }
+        for read in other.raw_reads.iter_mut() {
+            read.id += read_offset;
+        }
+        for read in other.encoded_reads.iter_mut() {
+            read.id += read_offset;
+            for node in read.nodes.iter_mut() {
+                node.unit = unit(node.unit);
+            }
+            for edge in read.edges.iter_mut() {
+                edge.from = unit(edge.from);
+                edge.to = unit(edge.to);
+            }
+        }
+        for asn in other.assignments.iter_mut() {
+            asn.id += read_offset;
+            asn.cluster += cluster_offset;
+        }
+        // The IDs of the mates are derived from the ID of the pair. See `hic::HiC::add_hic_pairs`.
+        for pair in other.hic_pairs.iter_mut() {
+            pair.pair_id += pair_offset;
+            pair.pair1 = 2 * pair.pair_id;
+            pair.pair2 = 2 * pair.pair_id + 1;
+        }
+        for edge in other.hic_edges.iter_mut() {
+            edge.pair_id += pair_offset;
+            edge.pair1 = unit(edge.pair1);
+            edge.pair2 = unit(edge.pair2);
+        }
+        debug!(
+            "Merging {} reads into {} reads.",
+            other.raw_reads.len(),
+            self.raw_reads.len()
+        );
+        self.coverage = match (self.coverage, other.coverage) {
+            (Some(x), Some(y)) => Some(x + y),
+            _ => None,
+        };
+        self.raw_reads.extend(other.raw_reads);
+        self.hic_pairs.extend(other.hic_pairs);
+        self.selected_chunks.extend(other.selected_chunks);
+        self.encoded_reads.extend(other.encoded_reads);
+        self.hic_edges.extend(other.hic_edges);
+        self.assignments.extend(other.assignments);
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::*;
+    use definitions::*;
+    fn dataset() -> DataSet {
+        let read = raw_read(0, "read", "ACGT");
+        // `sanity_check` needs an existing input file.
+        let input = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+        let mut ds = DataSet::with_minimum_data(input, vec![read], "CLR");
+        ds.selected_chunks = vec![Unit::new(3, "ACGT".to_string(), 2)];
+        ds.encoded_reads = vec![encoded_read(0, 4, vec![node(0, 3, 1, "ACGT")])];
+        ds.assignments = vec![Assignment::new(0, 0)];
+        ds.hic_pairs = vec![HiCPair {
+            pair1: 0,
+            pair2: 1,
+            pair_id: 0,
+            seq1: "ACGT".to_string(),
+            seq2: "ACGT".to_string(),
+        }];
+        ds
+    }
+    #[test]
+    fn merge_test() {
+        let ds = dataset().merge(dataset()).unwrap();
+        assert_eq!(ds.raw_reads.len(), 2);
+        assert_eq!(ds.raw_reads[1].id, 1);
+        let units: Vec<_> = ds.selected_chunks.iter().map(|u| u.id).collect();
+        assert_eq!(units, vec![3, 7]);
+        assert_eq!(ds.encoded_reads[1].id, 1);
+        assert_eq!(ds.encoded_reads[1].nodes[0].unit, 7);
+        assert_eq!(ds.assignments[1].id, 1);
+        assert_eq!(ds.assignments[1].cluster, 1);
+        let mates: Vec<_> = ds
+            .hic_pairs
+            .iter()
+            .map(|p| (p.pair_id, p.pair1, p.pair2))
+            .collect();
+        assert_eq!(mates, vec![(0, 0, 1), (1, 2, 3)]);
+        assert!(ds.sanity_check());
+        let mut ccs = dataset();
+        ccs.read_type = ReadType::CCS;
+        assert!(dataset().merge(ccs).is_err());
+    }
+}
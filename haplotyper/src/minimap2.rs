@@ -1,45 +1,471 @@
-/// Invole minimap2 with specified input and return the stdout directry.
-pub fn minimap2(
-    query: &str,
-    target: &str,
+//! Aligners to map reads onto units or contigs.
+//! [Minimap2] invokes the `minimap2` binary as a subprocess, while [EdlibAligner] aligns sequences
+//! in this process, so that the pipeline can run where minimap2 is not installed.
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{BufWriter, Error, Write};
+use std::path::{Path, PathBuf};
+
+/// A named sequence.
+pub type Record<'a> = (&'a str, &'a [u8]);
+
+/// The output format of an aligner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlignmentFormat {
+    /// PAF with the `cg:Z` CIGAR tag.
+    Paf,
+    /// SAM with `@SQ` headers.
+    Sam,
+}
+
+pub trait Aligner {
+    /// Align `queries` onto `targets`, and return the alignments in the `format`.
+    fn align(
+        &self,
+        targets: &[Record],
+        queries: &[Record],
+        format: AlignmentFormat,
+    ) -> std::io::Result<Vec<u8>>;
+}
+
+/// The subprocess backend.
+#[derive(Debug, Clone, Serialize)]
+pub struct Minimap2 {
+    binary: PathBuf,
+    preset: Option<String>,
+    args: Vec<String>,
     threads: usize,
-    preset: &str,
-    is_ava: bool,
-    is_sam: bool,
-) -> Vec<u8> {
-    let thr = format!("{}", threads);
-    let mut args = vec!["-x", preset];
-    if is_ava {
-        args.push("-X");
-    }
-    if is_sam {
-        args.push("-a")
-    } else {
-        args.push("-c")
-    }
-    args.extend(&["-t", &thr, target, query]);
-    let aln = std::process::Command::new("minimap2")
-        .args(&args)
-        .output()
-        .unwrap();
-    if !aln.status.success() {
-        panic!("Minimap2,{:?}", String::from_utf8_lossy(&aln.stderr));
-    } else {
-        aln.stdout
-    }
-}
-
-pub fn minimap2_args(target: &str, query: &str, args: &[&str]) -> Vec<u8> {
-    let mut args = args.to_vec();
-    args.push(target);
-    args.push(query);
-    let aln = std::process::Command::new("minimap2")
-        .args(&args)
-        .output()
-        .unwrap();
-    if !aln.status.success() {
-        panic!("Minimap2,{}", String::from_utf8_lossy(&aln.stderr));
-    } else {
-        aln.stdout
+    // The directory to put the temporary FASTA files.
+    tmpdir: PathBuf,
+    // If false, the temporary files are kept for debugging when minimap2 fails.
+    cleanup_on_error: bool,
+}
+
+impl std::default::Default for Minimap2 {
+    fn default() -> Self {
+        Self {
+            binary: PathBuf::from("minimap2"),
+            preset: None,
+            args: vec![],
+            threads: 1,
+            tmpdir: std::env::temp_dir(),
+            cleanup_on_error: true,
+        }
+    }
+}
+
+impl Minimap2 {
+    pub fn new(threads: usize) -> Self {
+        Self {
+            threads,
+            ..Default::default()
+        }
+    }
+    pub fn binary<P: AsRef<Path>>(mut self, binary: P) -> Self {
+        self.binary = binary.as_ref().to_path_buf();
+        self
+    }
+    pub fn preset(mut self, preset: &str) -> Self {
+        self.preset = Some(preset.to_string());
+        self
+    }
+    /// Append other arguments, such as `-k 15`. Do not pass the input files or `-a/-c`.
+    pub fn args(mut self, args: &[&str]) -> Self {
+        self.args.extend(args.iter().map(|x| x.to_string()));
+        self
+    }
+    pub fn tmpdir<P: AsRef<Path>>(mut self, tmpdir: P) -> Self {
+        self.tmpdir = tmpdir.as_ref().to_path_buf();
+        self
+    }
+    pub fn cleanup_on_error(mut self, cleanup_on_error: bool) -> Self {
+        self.cleanup_on_error = cleanup_on_error;
+        self
+    }
+    fn run(
+        &self,
+        dir: &Path,
+        targets: &[Record],
+        queries: &[Record],
+        format: AlignmentFormat,
+    ) -> std::io::Result<Vec<u8>> {
+        let write_fasta = |name: &str, records: &[Record]| -> std::io::Result<PathBuf> {
+            let path = dir.join(name);
+            let mut wtr = std::fs::File::create(&path).map(BufWriter::new)?;
+            for (id, seq) in records.iter() {
+                writeln!(&mut wtr, ">{}\n{}", id, String::from_utf8_lossy(seq))?;
+            }
+            wtr.flush()?;
+            Ok(path)
+        };
+        let target = write_fasta("target.fa", targets)?;
+        let query = write_fasta("query.fa", queries)?;
+        let mut command = std::process::Command::new(&self.binary);
+        if let Some(preset) = self.preset.as_ref() {
+            command.args(["-x", preset]);
+        }
+        command.args(&self.args);
+        match format {
+            AlignmentFormat::Paf => command.arg("-c"),
+            AlignmentFormat::Sam => command.arg("-a"),
+        };
+        command.args(["-t", &format!("{}", self.threads)]);
+        command.arg(&target).arg(&query);
+        debug!("{:?}", command);
+        let output = command.output().map_err(|why| {
+            let msg = format!("Failed to run {:?}:{}", self.binary, why);
+            Error::new(why.kind(), msg)
+        })?;
+        if output.status.success() {
+            Ok(output.stdout)
+        } else {
+            let msg = format!("Minimap2,{}", String::from_utf8_lossy(&output.stderr));
+            Err(Error::other(msg))
+        }
+    }
+}
+
+impl Aligner for Minimap2 {
+    fn align(
+        &self,
+        targets: &[Record],
+        queries: &[Record],
+        format: AlignmentFormat,
+    ) -> std::io::Result<Vec<u8>> {
+        use rand::{thread_rng, Rng};
+        let id: u64 = thread_rng().gen::<u64>() % 100_000_000;
+        let dir = self.tmpdir.join(format!("jtk_{}", id));
+        debug!("Creating {:?}.", dir);
+        std::fs::create_dir_all(&dir)?;
+        let result = self.run(&dir, targets, queries, format);
+        if result.is_ok() || self.cleanup_on_error {
+            debug!("Removing {:?}", dir);
+            if let Err(why) = std::fs::remove_dir_all(&dir) {
+                warn!("Failed to remove {:?}: {}", dir, why);
+            }
+        } else {
+            warn!("Minimap2 failed. Keeping {:?}", dir);
+        }
+        result
+    }
+}
+
+/// The in-process backend.
+/// Candidate locations are found by shared k-mers, and then each target is aligned to the
+/// candidate region by edlib in the infix mode. Thus, the targets should be shorter than the queries,
+/// as units to reads, and each alignment covers the entire target.
+#[derive(Debug, Clone, Serialize)]
+pub struct EdlibAligner {
+    k: usize,
+    // The minimum number of the shared k-mers in a diagonal band to align.
+    min_seeds: usize,
+    // The maximum edit distance, divided by the length of the target.
+    max_diff: f64,
+}
+
+impl std::default::Default for EdlibAligner {
+    fn default() -> Self {
+        Self {
+            k: 12,
+            min_seeds: 3,
+            max_diff: 0.3,
+        }
+    }
+}
+
+// The width of a diagonal band.
+const BAND: i64 = 256;
+// K-mers occurring more than this in the targets are not used as seeds.
+const MAX_OCCURRENCE: usize = 500;
+
+impl EdlibAligner {
+    pub fn new(k: usize, min_seeds: usize, max_diff: f64) -> Self {
+        assert!(0 < k && k <= 32);
+        Self {
+            k,
+            min_seeds,
+            max_diff,
+        }
+    }
+    fn index(&self, targets: &[Record]) -> HashMap<u64, Vec<(usize, usize)>> {
+        let mut index: HashMap<u64, Vec<(usize, usize)>> = HashMap::new();
+        for (idx, (_, seq)) in targets.iter().enumerate() {
+            for (pos, kmer) in kmers(seq, self.k) {
+                index.entry(kmer).or_default().push((idx, pos));
+            }
+        }
+        index.retain(|_, hits| hits.len() <= MAX_OCCURRENCE);
+        index
+    }
+    // Return (target index, start, end, operations) of the alignments of `seq`.
+    // The operations are the ones of edlib, where the target is the query of edlib.
+    fn align_strand(
+        &self,
+        seq: &[u8],
+        targets: &[Record],
+        index: &HashMap<u64, Vec<(usize, usize)>>,
+    ) -> Vec<(usize, usize, usize, Vec<u8>)> {
+        let mut bands: HashMap<(usize, i64), usize> = HashMap::new();
+        for (pos, kmer) in kmers(seq, self.k) {
+            for &(idx, tpos) in index.get(&kmer).into_iter().flatten() {
+                let diagonal = pos as i64 - tpos as i64;
+                *bands.entry((idx, diagonal.div_euclid(BAND))).or_default() += 1;
+            }
+        }
+        let mut bands: Vec<_> = bands
+            .into_iter()
+            .filter(|&(_, count)| self.min_seeds <= count)
+            .map(|(band, _)| band)
+            .collect();
+        bands.sort_unstable();
+        // Merge adjacent bands of the same target into a candidate region.
+        let mut candidates: Vec<(usize, i64, i64)> = vec![];
+        for (idx, band) in bands {
+            match candidates.last_mut() {
+                Some(last) if last.0 == idx && last.2 + 1 >= band => last.2 = band,
+                _ => candidates.push((idx, band, band)),
+            }
+        }
+        let mut alignments: Vec<(usize, usize, usize, Vec<u8>)> = vec![];
+        for (idx, first, last) in candidates {
+            let target = targets[idx].1;
+            let start = ((first - 1) * BAND).max(0) as usize;
+            let end = ((last + 2) * BAND + target.len() as i64).max(0) as usize;
+            let end = end.min(seq.len());
+            if end <= start {
+                continue;
+            }
+            let mode = edlib_sys::AlignMode::Infix;
+            let task = edlib_sys::AlignTask::Alignment;
+            let aln = edlib_sys::edlib_align(target, &seq[start..end], mode, task);
+            let dist_thr = (target.len() as f64 * self.max_diff).floor() as u32;
+            if dist_thr < aln.dist {
+                continue;
+            }
+            let (aln_start, aln_end) = match aln.locations.as_ref().and_then(|l| l.first()) {
+                Some(&loc) => loc,
+                None => continue,
+            };
+            let (aln_start, aln_end) = (start + aln_start, start + aln_end + 1);
+            let is_dup = alignments
+                .iter()
+                .any(|a| a.0 == idx && a.1 == aln_start && a.2 == aln_end);
+            if let (Some(ops), false) = (aln.operations, is_dup) {
+                alignments.push((idx, aln_start, aln_end, ops));
+            }
+        }
+        alignments
+    }
+}
+
+impl Aligner for EdlibAligner {
+    fn align(
+        &self,
+        targets: &[Record],
+        queries: &[Record],
+        format: AlignmentFormat,
+    ) -> std::io::Result<Vec<u8>> {
+        let targets: Vec<(&str, Vec<u8>)> = targets
+            .iter()
+            .map(|(id, seq)| (*id, seq.to_ascii_uppercase()))
+            .collect();
+        let targets: Vec<Record> = targets.iter().map(|(id, seq)| (*id, &seq[..])).collect();
+        let index = self.index(&targets);
+        debug!("Indexed {} k-mers.", index.len());
+        let lines: Vec<Vec<String>> = queries
+            .par_iter()
+            .map(|&(name, seq)| {
+                let forward = seq.to_ascii_uppercase();
+                let reverse = bio_utils::revcmp(&forward);
+                let mut lines = vec![];
+                for (is_forward, seq) in [(true, &forward), (false, &reverse)] {
+                    for (idx, start, end, ops) in self.align_strand(seq, &targets, &index) {
+                        let aln = Alignment {
+                            query: (name, seq),
+                            target: targets[idx],
+                            is_forward,
+                            start,
+                            end,
+                            ops: &ops,
+                        };
+                        let is_primary = lines.is_empty();
+                        let line = match format {
+                            AlignmentFormat::Paf => aln.to_paf(),
+                            AlignmentFormat::Sam => aln.to_sam(is_primary),
+                        };
+                        lines.push(line);
+                    }
+                }
+                lines
+            })
+            .collect();
+        let mut output = vec![];
+        if format == AlignmentFormat::Sam {
+            for (id, seq) in targets.iter() {
+                writeln!(&mut output, "@SQ\tSN:{}\tLN:{}", id, seq.len())?;
+            }
+        }
+        for line in lines.iter().flatten() {
+            writeln!(&mut output, "{}", line)?;
+        }
+        Ok(output)
+    }
+}
+
+// An alignment between the entire target and query[start..end].
+// Note that the query is reverse complemented if `is_forward` is false.
+struct Alignment<'a> {
+    query: Record<'a>,
+    target: Record<'a>,
+    is_forward: bool,
+    start: usize,
+    end: usize,
+    ops: &'a [u8],
+}
+
+impl<'a> Alignment<'a> {
+    // Run-length encoded CIGAR. Edlib operation 1 is an extra base in the target, i.e., a deletion.
+    fn cigar(&self) -> Vec<(usize, char)> {
+        let mut cigar: Vec<(usize, char)> = vec![];
+        for &op in self.ops.iter() {
+            let op = match op {
+                1 => 'D',
+                2 => 'I',
+                _ => 'M',
+            };
+            match cigar.last_mut() {
+                Some(last) if last.1 == op => last.0 += 1,
+                _ => cigar.push((1, op)),
+            }
+        }
+        cigar
+    }
+    fn to_paf(&self) -> String {
+        let qlen = self.query.1.len();
+        let (qstart, qend) = match self.is_forward {
+            true => (self.start, self.end),
+            false => (qlen - self.end, qlen - self.start),
+        };
+        let matches = self.ops.iter().filter(|&&op| op == 0).count();
+        let cigar: String = self
+            .cigar()
+            .iter()
+            .map(|(len, op)| format!("{}{}", len, op))
+            .collect();
+        let tlen = self.target.1.len();
+        let strand = if self.is_forward { '+' } else { '-' };
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t0\t{}\t{}\t{}\t60\ttp:A:P\tcg:Z:{}",
+            self.query.0,
+            qlen,
+            qstart,
+            qend,
+            strand,
+            self.target.0,
+            tlen,
+            tlen,
+            matches,
+            self.ops.len(),
+            cigar
+        )
+    }
+    fn to_sam(&self, is_primary: bool) -> String {
+        let mut flag = 0;
+        if !self.is_forward {
+            flag |= 0x10;
+        }
+        if !is_primary {
+            flag |= 0x800;
+        }
+        let mut cigar = vec![];
+        if self.start > 0 {
+            cigar.push((self.start, 'S'));
+        }
+        cigar.extend(self.cigar());
+        if self.end < self.query.1.len() {
+            cigar.push((self.query.1.len() - self.end, 'S'));
+        }
+        let cigar: String = cigar
+            .iter()
+            .map(|(len, op)| format!("{}{}", len, op))
+            .collect();
+        format!(
+            "{}\t{}\t{}\t1\t60\t{}\t*\t0\t0\t{}\t*",
+            self.query.0,
+            flag,
+            self.target.0,
+            cigar,
+            String::from_utf8_lossy(self.query.1)
+        )
+    }
+}
+
+// Enumerate the (position, 2-bit encoded k-mer) of `seq`, skipping k-mers with ambiguous bases.
+fn kmers(seq: &[u8], k: usize) -> impl Iterator<Item = (usize, u64)> + '_ {
+    let mask = if k == 32 { !0 } else { (1 << (2 * k)) - 1 };
+    let mut kmer = 0u64;
+    let mut valid = 0;
+    seq.iter().enumerate().filter_map(move |(pos, base)| {
+        let bits = match base {
+            b'A' => 0,
+            b'C' => 1,
+            b'G' => 2,
+            b'T' => 3,
+            _ => {
+                valid = 0;
+                return None;
+            }
+        };
+        kmer = ((kmer << 2) | bits) & mask;
+        valid += 1;
+        (k <= valid).then(|| (pos + 1 - k, kmer))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_xoshiro::Xoroshiro128StarStar;
+    #[test]
+    fn kmers_test() {
+        let kmers: Vec<_> = kmers(b"ACGNACGT", 3).collect();
+        assert_eq!(kmers, vec![(0, 0b000110), (4, 0b000110), (5, 0b011011)]);
+    }
+    #[test]
+    fn edlib_aligner_test() {
+        let mut rng: Xoroshiro128StarStar = SeedableRng::seed_from_u64(10);
+        let random = |len: usize, rng: &mut Xoroshiro128StarStar| -> Vec<u8> {
+            (0..len).map(|_| b"ACGT"[rng.gen_range(0..4)]).collect()
+        };
+        let unit = random(500, &mut rng);
+        let mut read = random(300, &mut rng);
+        read.extend(&unit);
+        read.extend(random(200, &mut rng));
+        read.extend(bio_utils::revcmp(&unit));
+        read.extend(random(100, &mut rng));
+        let targets = vec![("0", unit.as_slice())];
+        let queries = vec![("read", read.as_slice())];
+        let aligner = EdlibAligner::default();
+        let paf = aligner
+            .align(&targets, &queries, AlignmentFormat::Paf)
+            .unwrap();
+        let paf = String::from_utf8(paf).unwrap();
+        let mut hits: Vec<Vec<&str>> = paf.lines().map(|l| l.split('\t').collect()).collect();
+        hits.sort_by_key(|fields| fields[2].parse::<usize>().unwrap());
+        assert_eq!(hits.len(), 2);
+        assert_eq!(
+            &hits[0][1..9],
+            &["1600", "300", "800", "+", "0", "500", "0", "500"]
+        );
+        assert_eq!(&hits[1][1..5], &["1600", "1000", "1500", "-"]);
+        assert_eq!(hits[1][13], "cg:Z:500M");
+        let sam = aligner
+            .align(&targets, &queries, AlignmentFormat::Sam)
+            .unwrap();
+        let sam = String::from_utf8(sam).unwrap();
+        assert!(sam.starts_with("@SQ\tSN:0\tLN:500\n"));
+        assert_eq!(sam.lines().count(), 3);
     }
 }
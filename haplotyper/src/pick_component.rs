@@ -1,6 +1,7 @@
 use definitions::*;
+use serde::Serialize;
 use std::collections::HashSet;
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize)]
 pub struct ComponentPickingConfig {
     /// How many component would we take?
     component_number: usize,
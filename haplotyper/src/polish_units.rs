@@ -4,8 +4,9 @@ use poa_hmm::POA;
 use rand::{seq::SliceRandom, SeedableRng};
 use rand_xoshiro::Xoshiro256PlusPlus;
 use rayon::prelude::*;
+use serde::Serialize;
 use std::collections::HashMap;
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize)]
 pub struct PolishUnitConfig {
     consensus_size: usize,
     filter_size: usize,
@@ -29,6 +30,7 @@ impl PolishUnitConfig {
     }
 }
 /// Polishing units by partial order alignment graph.
+/// If the reads have qualities, the nodes with higher mean quality are used first.
 /// Note that after calling this function,
 /// all the encoded reads would be removed.
 /// This removal is to force users to encode reads by aligning the
@@ -64,7 +66,7 @@ impl PolishUnit for DataSet {
                     // let cons = consensus(pileup, len, c);
                     // cons.map(|c| (id, c))
                     // let start = std::time::Instant::now();
-                    let seqs: Vec<_> = pileup.iter().map(|x| x.seq()).take(c.cons_size).collect();
+                    let seqs = select_nodes(pileup, c.cons_size);
                     let cons = kiley::ternary_consensus_by_chunk(&seqs, 100);
                     let cons = kiley::bialignment::polish_until_converge_banded(&cons, &seqs, 100);
                     // let end = std::time::Instant::now();
@@ -86,6 +88,21 @@ impl PolishUnit for DataSet {
 }
 
 use definitions::Node;
+// The sequences of the `size` nodes with the highest mean base quality.
+// Nodes without any quality come last, and ties keep the order of the pileup.
+fn select_nodes<'a>(pileup: &[&'a Node], size: usize) -> Vec<&'a [u8]> {
+    let mut pileup = pileup.to_vec();
+    pileup.sort_by_cached_key(|node| std::cmp::Reverse(mean_quality(node)));
+    pileup.iter().map(|x| x.seq()).take(size).collect()
+}
+
+// The mean Phred score of the node, or None if it has no quality.
+fn mean_quality(node: &Node) -> Option<u64> {
+    let qual = node.qual()?;
+    let sum: u64 = qual.iter().map(|&q| q.saturating_sub(33) as u64).sum();
+    Some(sum / qual.len().max(1) as u64)
+}
+
 #[allow(dead_code)]
 fn consensus(pileup: &[&Node], len: usize, c: &PolishUnitConfig) -> Option<String> {
     let subchunks: Vec<_> = pileup
@@ -135,3 +152,25 @@ fn score(x: u8, y: u8) -> i32 {
         -1
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn select_nodes_test() {
+        let mut nodes: Vec<_> = ["AAAA", "CCCC", "GGGG", "TTTT"]
+            .iter()
+            .map(|seq| crate::fixtures::node(0, 0, 0, seq))
+            .collect();
+        nodes[1].qual = Some("++++".to_string());
+        nodes[2].qual = Some("IIII".to_string());
+        nodes[3].qual = Some("!!!!".to_string());
+        let pileup: Vec<_> = nodes.iter().collect();
+        let seqs = select_nodes(&pileup, 3);
+        assert_eq!(seqs, vec![b"GGGG", b"CCCC", b"TTTT"]);
+        // Without qualities, the first nodes are used.
+        nodes.iter_mut().for_each(|node| node.qual = None);
+        let pileup: Vec<_> = nodes.iter().collect();
+        assert_eq!(select_nodes(&pileup, 2), vec![b"AAAA", b"CCCC"]);
+    }
+}
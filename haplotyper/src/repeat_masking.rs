@@ -1,6 +1,7 @@
 use rayon::prelude::*;
+use serde::Serialize;
 use std::collections::{HashMap, HashSet};
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct RepeatMaskConfig {
     k: usize,
     freq: f64,
@@ -0,0 +1,280 @@
+//! A report of a DataSet in a single HTML file.
+//! The data are embedded in the file as JSON, and the plots are drawn by an embedded script
+//! without any external library, so the file can be opened offline and attached as is.
+use crate::assemble::Graph;
+use crate::stats::{Metrics, Stats};
+use definitions::DataSet;
+use serde::Serialize;
+use std::collections::HashMap;
+const TEMPLATE: &str = include_str!("report.html");
+const DATA_MARKER: &str = "__REPORT_DATA__";
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ReportConfig {
+    /// The number of the bins of the histograms.
+    bins: usize,
+    /// Edges of the unit graph supported by fewer reads than this are not drawn.
+    min_edge_count: usize,
+}
+
+impl ReportConfig {
+    pub fn new(bins: usize, min_edge_count: usize) -> Self {
+        Self {
+            bins,
+            min_edge_count,
+        }
+    }
+}
+
+impl std::default::Default for ReportConfig {
+    fn default() -> Self {
+        Self {
+            bins: 50,
+            min_edge_count: 2,
+        }
+    }
+}
+
+/// The i-th bin counts the values in `[start + i * width, start + (i + 1) * width)`.
+/// The last bin includes the maximum.
+#[derive(Debug, Clone, Serialize)]
+pub struct Histogram {
+    pub start: f64,
+    pub width: f64,
+    pub counts: Vec<usize>,
+}
+
+impl Histogram {
+    pub fn new(xs: &[f64], bins: usize) -> Self {
+        if xs.is_empty() {
+            return Self {
+                start: 0f64,
+                width: 1f64,
+                counts: vec![],
+            };
+        }
+        let bins = bins.max(1);
+        let start = xs.iter().copied().fold(f64::INFINITY, f64::min);
+        let end = xs.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let width = match end - start {
+            range if range > 0f64 => range / bins as f64,
+            _ => 1f64,
+        };
+        let mut counts = vec![0; bins];
+        for x in xs {
+            let bin = ((x - start) / width).floor() as usize;
+            counts[bin.min(bins - 1)] += 1;
+        }
+        Self {
+            start,
+            width,
+            counts,
+        }
+    }
+}
+
+/// The graph of (unit, cluster) pairs, connected if they are adjacent in some encoded read.
+#[derive(Debug, Clone, Serialize)]
+pub struct UnitGraph {
+    pub nodes: Vec<UnitNode>,
+    pub edges: Vec<UnitEdge>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UnitNode {
+    pub unit: u64,
+    pub cluster: u64,
+    pub count: usize,
+}
+
+/// `from` and `to` are the indices of the nodes.
+#[derive(Debug, Clone, Serialize)]
+pub struct UnitEdge {
+    pub from: usize,
+    pub to: usize,
+    pub count: usize,
+}
+
+/// A contig in the output of `jtk assemble`, read by `assemble::graphs_from_gfa`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ContigReport {
+    pub id: String,
+    /// The index of the group in the GFA, i.e., the reads assembled together.
+    pub graph: usize,
+    pub units: usize,
+    /// The sum of the lengths of the units.
+    pub length: usize,
+    /// The number of the (unit, cluster) pairs which appear more than once in the contig.
+    pub duplicated: usize,
+    /// The number of the edges to the other contigs.
+    pub degree: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportData {
+    pub title: String,
+    pub metrics: Metrics,
+    pub read_lengths: Histogram,
+    pub encoded_rates: Histogram,
+    pub unit_graph: UnitGraph,
+    /// The number of the units for each copy number, which is the number of the clusters
+    /// estimated by `multiplicity_estimation`.
+    pub copy_numbers: Vec<(usize, usize)>,
+    pub contigs: Vec<ContigReport>,
+}
+
+pub trait Report {
+    /// Collect the data of the report. `graphs` are the contigs read by `assemble::graphs_from_gfa`,
+    /// and can be empty if the reads are not assembled.
+    fn report(&self, graphs: &[Graph], c: &ReportConfig) -> ReportData;
+}
+
+impl Report for DataSet {
+    fn report(&self, graphs: &[Graph], c: &ReportConfig) -> ReportData {
+        let lens: Vec<_> = self
+            .raw_reads
+            .iter()
+            .map(|r| r.seq().len() as f64)
+            .collect();
+        let rates: Vec<_> = self
+            .encoded_reads
+            .iter()
+            .map(|r| r.encoded_length() as f64 / r.original_length.max(1) as f64)
+            .collect();
+        let mut copy_numbers: HashMap<usize, usize> = HashMap::new();
+        for unit in self.selected_chunks.iter() {
+            *copy_numbers.entry(unit.cluster_num).or_default() += 1;
+        }
+        let mut copy_numbers: Vec<_> = copy_numbers.into_iter().collect();
+        copy_numbers.sort_unstable();
+        ReportData {
+            title: self.input_file.clone(),
+            metrics: self.metrics(),
+            read_lengths: Histogram::new(&lens, c.bins),
+            encoded_rates: Histogram::new(&rates, c.bins),
+            unit_graph: unit_graph(self, c),
+            copy_numbers,
+            contigs: contig_reports(self, graphs),
+        }
+    }
+}
+
+fn unit_graph(ds: &DataSet, c: &ReportConfig) -> UnitGraph {
+    let mut counts: HashMap<(u64, u64), usize> = HashMap::new();
+    let mut edge_counts: HashMap<_, usize> = HashMap::new();
+    for read in ds.encoded_reads.iter() {
+        for node in read.nodes.iter() {
+            *counts.entry((node.unit, node.cluster)).or_default() += 1;
+        }
+        for w in read.nodes.windows(2) {
+            let from = (w[0].unit, w[0].cluster);
+            let to = (w[1].unit, w[1].cluster);
+            let edge = if from <= to { (from, to) } else { (to, from) };
+            *edge_counts.entry(edge).or_default() += 1;
+        }
+    }
+    let mut nodes: Vec<_> = counts.into_iter().collect();
+    nodes.sort_unstable();
+    let index: HashMap<_, _> = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, &(node, _))| (node, i))
+        .collect();
+    let mut edges: Vec<_> = edge_counts
+        .into_iter()
+        .filter(|&(_, count)| c.min_edge_count <= count)
+        .map(|((from, to), count)| UnitEdge {
+            from: index[&from],
+            to: index[&to],
+            count,
+        })
+        .collect();
+    edges.sort_unstable_by_key(|e| (e.from, e.to));
+    let nodes = nodes
+        .into_iter()
+        .map(|((unit, cluster), count)| UnitNode {
+            unit,
+            cluster,
+            count,
+        })
+        .collect();
+    UnitGraph { nodes, edges }
+}
+
+fn contig_reports(ds: &DataSet, graphs: &[Graph]) -> Vec<ContigReport> {
+    let unit_lens: HashMap<_, _> = ds
+        .selected_chunks
+        .iter()
+        .map(|u| (u.id, u.seq().len()))
+        .collect();
+    let unit_lens = &unit_lens;
+    graphs
+        .iter()
+        .enumerate()
+        .flat_map(|(i, graph)| {
+            graph.nodes.iter().map(move |node| {
+                let mut tiles: HashMap<_, usize> = HashMap::new();
+                for tile in node.segments.iter() {
+                    *tiles.entry((tile.unit, tile.cluster)).or_default() += 1;
+                }
+                let degree = graph
+                    .edges
+                    .iter()
+                    .filter(|e| e.from == node.id || e.to == node.id)
+                    .count();
+                ContigReport {
+                    id: node.id.clone(),
+                    graph: i,
+                    units: node.segments.len(),
+                    length: node
+                        .segments
+                        .iter()
+                        .filter_map(|t| unit_lens.get(&t.unit))
+                        .sum(),
+                    duplicated: tiles.values().filter(|&&count| 1 < count).count(),
+                    degree,
+                }
+            })
+        })
+        .collect()
+}
+
+/// Write the report as a self-contained HTML file.
+pub fn write_html<W: std::io::Write>(data: &ReportData, mut wtr: W) -> std::io::Result<()> {
+    // `</` in the strings would close the script element embedding the data.
+    let json = serde_json::to_string(data)?.replace("</", "<\\/");
+    let mut parts = TEMPLATE.splitn(2, DATA_MARKER);
+    let (header, footer) = (parts.next().unwrap(), parts.next().unwrap_or(""));
+    wtr.write_all(header.as_bytes())?;
+    wtr.write_all(json.as_bytes())?;
+    wtr.write_all(footer.as_bytes())?;
+    wtr.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn histogram_test() {
+        let xs = vec![0f64, 1f64, 2.5, 9.9, 10f64];
+        let hist = Histogram::new(&xs, 5);
+        assert_eq!(hist.width, 2f64);
+        assert_eq!(hist.counts, vec![2, 1, 0, 0, 2]);
+        let hist = Histogram::new(&[3f64, 3f64], 4);
+        assert_eq!(hist.counts, vec![2, 0, 0, 0]);
+        assert!(Histogram::new(&[], 4).counts.is_empty());
+    }
+    #[test]
+    fn write_html_test() {
+        let mut ds = DataSet::with_minimum_data("</script>.fa", vec![], "CCS");
+        ds.selected_chunks = vec![definitions::Unit::new(0, "ACGT".to_string(), 2)];
+        let data = ds.report(&[], &ReportConfig::default());
+        assert_eq!(data.copy_numbers, vec![(2, 1)]);
+        let mut html = vec![];
+        write_html(&data, &mut html).unwrap();
+        let html = String::from_utf8(html).unwrap();
+        assert!(!html.contains(DATA_MARKER));
+        assert!(html.contains("<\\/script>.fa"));
+        assert!(!html.contains("src=\"http"));
+    }
+}
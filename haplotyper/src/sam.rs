@@ -0,0 +1,752 @@
+//! Minimal SAM/BAM writer.
+//! We mainly write alignments, so this module implements just enough of the SAM/BAM specification:
+//! text SAM, BAM compressed by BGZF, and a parser of the SAM output of minimap2.
+use std::collections::HashMap;
+use std::io::{BufRead, Error, ErrorKind, Write};
+
+/// Header of SAM/BAM. `references` are (name, length) pairs, and the reference ID of a record is the index of this vector.
+#[derive(Debug, Clone)]
+pub struct SamHeader {
+    pub references: Vec<(String, usize)>,
+    pub is_sorted: bool,
+}
+
+impl SamHeader {
+    pub fn new(references: Vec<(String, usize)>) -> Self {
+        Self {
+            references,
+            is_sorted: false,
+        }
+    }
+    fn to_text(&self) -> String {
+        let order = if self.is_sorted {
+            "coordinate"
+        } else {
+            "unsorted"
+        };
+        let mut text = format!("@HD\tVN:1.6\tSO:{}\n", order);
+        for (name, len) in self.references.iter() {
+            text += &format!("@SQ\tSN:{}\tLN:{}\n", name, len);
+        }
+        text += "@PG\tID:jtk\tPN:jtk\n";
+        text
+    }
+}
+
+/// Optional field of a record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Tag {
+    Int([u8; 2], i32),
+    Str([u8; 2], String),
+}
+
+/// Operations of CIGAR strings. The numbers are the codes in the BAM format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CigarOp {
+    Match(u32),
+    Ins(u32),
+    Del(u32),
+    SoftClip(u32),
+    HardClip(u32),
+}
+
+impl CigarOp {
+    fn code(&self) -> (u32, u32, char) {
+        match *self {
+            CigarOp::Match(l) => (l, 0, 'M'),
+            CigarOp::Ins(l) => (l, 1, 'I'),
+            CigarOp::Del(l) => (l, 2, 'D'),
+            CigarOp::SoftClip(l) => (l, 4, 'S'),
+            CigarOp::HardClip(l) => (l, 5, 'H'),
+        }
+    }
+    fn consumes_reference(&self) -> bool {
+        matches!(self, CigarOp::Match(_) | CigarOp::Del(_))
+    }
+    fn with_len(&self, len: u32) -> Self {
+        match self {
+            CigarOp::Match(_) => CigarOp::Match(len),
+            CigarOp::Ins(_) => CigarOp::Ins(len),
+            CigarOp::Del(_) => CigarOp::Del(len),
+            CigarOp::SoftClip(_) => CigarOp::SoftClip(len),
+            CigarOp::HardClip(_) => CigarOp::HardClip(len),
+        }
+    }
+}
+
+// Push `op` to `ops`, merging it into the last operation if they are the same kind.
+fn push_op(ops: &mut Vec<CigarOp>, op: CigarOp) {
+    if let Some(last) = ops.last_mut() {
+        let ((len, code, _), (l, c, _)) = (last.code(), op.code());
+        if code == c {
+            *last = op.with_len(len + l);
+            return;
+        }
+    }
+    ops.push(op);
+}
+
+pub const FLAG_UNMAPPED: u16 = 0x4;
+pub const FLAG_REVERSE: u16 = 0x10;
+pub const FLAG_SECONDARY: u16 = 0x100;
+pub const FLAG_SUPPLEMENTARY: u16 = 0x800;
+/// The maximum length of a read name in BAM. Longer names are truncated.
+pub const MAX_QNAME_LEN: usize = 254;
+
+/// An alignment record. `pos` is 0-based. If `qual` is empty, the quality is unavailable.
+#[derive(Debug, Clone)]
+pub struct SamRecord {
+    pub qname: String,
+    pub flag: u16,
+    pub reference: usize,
+    pub pos: usize,
+    pub mapq: u8,
+    pub cigar: Vec<CigarOp>,
+    pub seq: Vec<u8>,
+    /// Phred scores(not +33).
+    pub qual: Vec<u8>,
+    pub tags: Vec<Tag>,
+}
+
+impl SamRecord {
+    /// The end position(exclusive) on the reference.
+    pub fn end(&self) -> usize {
+        let len = self
+            .cigar
+            .iter()
+            .filter(|op| op.consumes_reference())
+            .map(|op| op.code().0 as usize)
+            .sum::<usize>();
+        self.pos + len
+    }
+    fn to_line(&self, header: &SamHeader) -> String {
+        let rname = &header.references[self.reference].0;
+        let cigar: String = self
+            .cigar
+            .iter()
+            .map(|op| {
+                let (len, _, c) = op.code();
+                format!("{}{}", len, c)
+            })
+            .collect();
+        let seq = if self.seq.is_empty() {
+            "*".into()
+        } else {
+            String::from_utf8_lossy(&self.seq)
+        };
+        let qual: String = if self.qual.is_empty() {
+            "*".to_string()
+        } else {
+            self.qual.iter().map(|&q| (q + 33) as char).collect()
+        };
+        let mut line = format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t*\t0\t0\t{}\t{}",
+            self.qname,
+            self.flag,
+            rname,
+            self.pos + 1,
+            self.mapq,
+            cigar,
+            seq,
+            qual
+        );
+        for tag in self.tags.iter() {
+            match tag {
+                Tag::Int(t, x) => line += &format!("\t{}:i:{}", String::from_utf8_lossy(t), x),
+                Tag::Str(t, x) => line += &format!("\t{}:Z:{}", String::from_utf8_lossy(t), x),
+            }
+        }
+        line
+    }
+    // Parse a line of SAM. Return None if the record is unmapped.
+    fn from_line(line: &str, references: &HashMap<&str, usize>) -> std::io::Result<Option<Self>> {
+        let invalid = |what: &str| {
+            let msg = format!("Invalid {} in SAM record:{}", what, line);
+            Error::new(ErrorKind::InvalidData, msg)
+        };
+        let fields: Vec<_> = line.split('\t').collect();
+        if fields.len() < 11 {
+            return Err(invalid("number of fields"));
+        }
+        let flag: u16 = fields[1].parse().map_err(|_| invalid("flag"))?;
+        if flag & FLAG_UNMAPPED != 0 {
+            return Ok(None);
+        }
+        let reference = *references
+            .get(fields[2])
+            .ok_or_else(|| invalid("reference"))?;
+        let pos: usize = fields[3].parse().map_err(|_| invalid("position"))?;
+        let mapq: u8 = fields[4].parse().map_err(|_| invalid("mapping quality"))?;
+        let cigar = parse_cigar(fields[5]).ok_or_else(|| invalid("cigar"))?;
+        let seq = match fields[9] {
+            "*" => vec![],
+            seq => seq.as_bytes().to_vec(),
+        };
+        let qual = match fields[10] {
+            "*" => vec![],
+            qual => qual.bytes().map(|q| q.saturating_sub(33)).collect(),
+        };
+        let tags = fields[11..]
+            .iter()
+            .filter_map(|tag| {
+                let mut tag = tag.splitn(3, ':');
+                let (name, tp, value) = (tag.next()?, tag.next()?, tag.next()?);
+                let name = match name.as_bytes() {
+                    &[x, y] => [x, y],
+                    _ => return None,
+                };
+                match tp {
+                    "i" => value.parse().ok().map(|x| Tag::Int(name, x)),
+                    "Z" => Some(Tag::Str(name, value.to_string())),
+                    _ => None,
+                }
+            })
+            .collect();
+        Ok(Some(Self {
+            qname: fields[0].to_string(),
+            flag,
+            reference,
+            pos: pos.max(1) - 1,
+            mapq,
+            cigar,
+            seq,
+            qual,
+            tags,
+        }))
+    }
+    fn to_bam(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        // The length of the read name, including the trailing NUL, is stored in one byte.
+        let qname = &self.qname.as_bytes()[..self.qname.len().min(MAX_QNAME_LEN)];
+        let cigar: Vec<u32> = self
+            .cigar
+            .iter()
+            .map(|op| {
+                let (len, code, _) = op.code();
+                len << 4 | code
+            })
+            .collect();
+        // BAM holds at most 65535 CIGAR operations. As the SAM specification says, a longer CIGAR
+        // is moved into the CG tag, and the CIGAR field becomes `<query length>S<reference length>N`.
+        let (cigar, long_cigar) = if cigar.len() <= u16::MAX as usize {
+            (cigar, None)
+        } else {
+            let (qlen, rlen) = (self.seq.len() as u32, (self.end() - self.pos) as u32);
+            (vec![qlen << 4 | 4, rlen << 4 | 3], Some(cigar))
+        };
+        let (ref_id, pos) = (self.reference as i32, self.pos as i32);
+        let bin = reg2bin(self.pos, self.end().max(self.pos + 1)) as u16;
+        buf.extend(&ref_id.to_le_bytes());
+        buf.extend(&pos.to_le_bytes());
+        buf.push(qname.len() as u8 + 1);
+        buf.push(self.mapq);
+        buf.extend(&bin.to_le_bytes());
+        buf.extend(&(cigar.len() as u16).to_le_bytes());
+        buf.extend(&self.flag.to_le_bytes());
+        buf.extend(&(self.seq.len() as i32).to_le_bytes());
+        // Mate reference ID, mate position, and template length.
+        buf.extend(&(-1i32).to_le_bytes());
+        buf.extend(&(-1i32).to_le_bytes());
+        buf.extend(&0i32.to_le_bytes());
+        buf.extend(qname);
+        buf.push(0);
+        for op in cigar.iter() {
+            buf.extend(&op.to_le_bytes());
+        }
+        for pair in self.seq.chunks(2) {
+            let upper = encode_base(pair[0]) << 4;
+            let lower = pair.get(1).map(|&b| encode_base(b)).unwrap_or(0);
+            buf.push(upper | lower);
+        }
+        if self.qual.is_empty() {
+            buf.resize(buf.len() + self.seq.len(), 0xFF);
+        } else {
+            buf.extend(self.qual.iter());
+        }
+        for tag in self.tags.iter() {
+            match tag {
+                Tag::Int(t, x) => {
+                    buf.extend(t);
+                    buf.push(b'i');
+                    buf.extend(&x.to_le_bytes());
+                }
+                Tag::Str(t, x) => {
+                    buf.extend(t);
+                    buf.push(b'Z');
+                    buf.extend(x.as_bytes());
+                    buf.push(0);
+                }
+            }
+        }
+        if let Some(cigar) = long_cigar {
+            buf.extend(b"CGBI");
+            buf.extend(&(cigar.len() as u32).to_le_bytes());
+            for op in cigar.iter() {
+                buf.extend(&op.to_le_bytes());
+            }
+        }
+        buf
+    }
+}
+
+fn parse_cigar(cigar: &str) -> Option<Vec<CigarOp>> {
+    let mut ops = vec![];
+    let mut len = 0;
+    for c in cigar.bytes() {
+        if c.is_ascii_digit() {
+            len = 10 * len + (c - b'0') as u32;
+            continue;
+        }
+        let op = match c {
+            b'M' | b'=' | b'X' => CigarOp::Match(len),
+            b'I' => CigarOp::Ins(len),
+            b'D' | b'N' => CigarOp::Del(len),
+            b'S' => CigarOp::SoftClip(len),
+            b'H' => CigarOp::HardClip(len),
+            _ => return None,
+        };
+        ops.push(op);
+        len = 0;
+    }
+    Some(ops)
+}
+
+fn encode_base(base: u8) -> u8 {
+    match base.to_ascii_uppercase() {
+        b'=' => 0,
+        b'A' => 1,
+        b'C' => 2,
+        b'M' => 3,
+        b'G' => 4,
+        b'R' => 5,
+        b'S' => 6,
+        b'V' => 7,
+        b'T' => 8,
+        b'W' => 9,
+        b'Y' => 10,
+        b'H' => 11,
+        b'K' => 12,
+        b'D' => 13,
+        b'B' => 14,
+        _ => 15,
+    }
+}
+
+/// Compute the bin of [start, end) as in the SAM specification.
+/// The offsets are the numbers of the bins in the upper levels, i.e., ((1 << 3k) - 1) / 7.
+fn reg2bin(start: usize, end: usize) -> usize {
+    let end = end - 1;
+    if start >> 14 == end >> 14 {
+        4681 + (start >> 14)
+    } else if start >> 17 == end >> 17 {
+        585 + (start >> 17)
+    } else if start >> 20 == end >> 20 {
+        73 + (start >> 20)
+    } else if start >> 23 == end >> 23 {
+        9 + (start >> 23)
+    } else if start >> 26 == end >> 26 {
+        1 + (start >> 26)
+    } else {
+        0
+    }
+}
+
+/// Parse alignments in SAM format, e.g., the output of minimap2. Reference names are resolved by `header`.
+/// Unmapped records are skipped, and only integer and string tags are kept.
+pub fn parse_records<R: BufRead>(reader: R, header: &SamHeader) -> std::io::Result<Vec<SamRecord>> {
+    let references: HashMap<_, _> = header
+        .references
+        .iter()
+        .enumerate()
+        .map(|(idx, (name, _))| (name.as_str(), idx))
+        .collect();
+    let mut records = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        if line.is_empty() || line.starts_with('@') {
+            continue;
+        }
+        if let Some(record) = SamRecord::from_line(&line, &references)? {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+/// Lift `record` over to a new reference. `alignment` is the alignment of the old reference
+/// to the new one, i.e., `Ins` is the bases only in the old reference and `Del` is the ones only in the new one.
+/// The bases of the read aligned to the bases only in the old reference become insertions(or soft clips at the ends),
+/// and the tags are dropped as they are not valid anymore. Return None if no base of the read is aligned to the new reference.
+pub fn lift_over(record: &SamRecord, alignment: &[CigarOp]) -> Option<SamRecord> {
+    let mut columns = alignment
+        .iter()
+        .flat_map(|op| {
+            let (len, _, c) = op.code();
+            std::iter::repeat_n(c, len as usize)
+        })
+        .peekable();
+    let (mut old_pos, mut new_pos) = (0, 0);
+    while old_pos < record.pos {
+        match columns.next()? {
+            'M' => {
+                old_pos += 1;
+                new_pos += 1;
+            }
+            'I' => old_pos += 1,
+            _ => new_pos += 1,
+        }
+    }
+    let (mut ops, mut pos) = (vec![], None);
+    for &op in record.cigar.iter() {
+        if !op.consumes_reference() {
+            push_op(&mut ops, op);
+            continue;
+        }
+        for _ in 0..op.code().0 {
+            while columns.peek() == Some(&'D') {
+                columns.next();
+                if pos.is_some() {
+                    push_op(&mut ops, CigarOp::Del(1));
+                }
+                new_pos += 1;
+            }
+            match (op, columns.next()) {
+                (CigarOp::Match(_), Some('M')) => {
+                    pos.get_or_insert(new_pos);
+                    push_op(&mut ops, CigarOp::Match(1));
+                    new_pos += 1;
+                }
+                (CigarOp::Match(_), _) => push_op(&mut ops, CigarOp::Ins(1)),
+                (_, Some('M')) => {
+                    if pos.is_some() {
+                        push_op(&mut ops, CigarOp::Del(1));
+                    }
+                    new_pos += 1;
+                }
+                _ => {}
+            }
+        }
+    }
+    let pos = pos?;
+    Some(SamRecord {
+        pos,
+        cigar: clip_ends(&ops),
+        tags: vec![],
+        ..record.clone()
+    })
+}
+
+// Drop the deletions at the ends, and turn the insertions at the ends into soft clips.
+fn clip_ends(ops: &[CigarOp]) -> Vec<CigarOp> {
+    let is_match = |op: &CigarOp| matches!(op, CigarOp::Match(_));
+    let first = ops.iter().position(is_match).unwrap_or(0);
+    let last = ops.iter().rposition(is_match).unwrap_or(0);
+    let clips = |ops: &[CigarOp]| {
+        ops.iter().fold((0, 0), |(hard, soft), op| match *op {
+            CigarOp::HardClip(l) => (hard + l, soft),
+            CigarOp::SoftClip(l) | CigarOp::Ins(l) => (hard, soft + l),
+            _ => (hard, soft),
+        })
+    };
+    let (head_hard, head_soft) = clips(&ops[..first]);
+    let (tail_hard, tail_soft) = clips(&ops[last + 1..]);
+    let mut clipped = vec![];
+    if 0 < head_hard {
+        clipped.push(CigarOp::HardClip(head_hard));
+    }
+    if 0 < head_soft {
+        clipped.push(CigarOp::SoftClip(head_soft));
+    }
+    clipped.extend(ops[first..=last].iter().copied());
+    if 0 < tail_soft {
+        clipped.push(CigarOp::SoftClip(tail_soft));
+    }
+    if 0 < tail_hard {
+        clipped.push(CigarOp::HardClip(tail_hard));
+    }
+    clipped
+}
+
+/// Sort records by their coordinates, and mark the header as sorted.
+pub fn sort_records(header: &mut SamHeader, records: &mut [SamRecord]) {
+    records.sort_by_key(|r| (r.reference, r.pos));
+    header.is_sorted = true;
+}
+
+pub fn write_sam<W: Write>(
+    mut wtr: W,
+    header: &SamHeader,
+    records: &[SamRecord],
+) -> std::io::Result<()> {
+    write!(wtr, "{}", header.to_text())?;
+    for record in records.iter() {
+        writeln!(wtr, "{}", record.to_line(header))?;
+    }
+    wtr.flush()
+}
+
+pub fn write_bam<W: Write>(
+    wtr: W,
+    header: &SamHeader,
+    records: &[SamRecord],
+) -> std::io::Result<()> {
+    let mut wtr = BgzfWriter::new(wtr);
+    let text = header.to_text();
+    wtr.write_all(b"BAM\x01")?;
+    wtr.write_all(&(text.len() as i32).to_le_bytes())?;
+    wtr.write_all(text.as_bytes())?;
+    wtr.write_all(&(header.references.len() as i32).to_le_bytes())?;
+    for (name, len) in header.references.iter() {
+        wtr.write_all(&(name.len() as i32 + 1).to_le_bytes())?;
+        wtr.write_all(name.as_bytes())?;
+        wtr.write_all(&[0])?;
+        wtr.write_all(&(*len as i32).to_le_bytes())?;
+    }
+    for record in records.iter() {
+        let record = record.to_bam();
+        wtr.write_all(&(record.len() as i32).to_le_bytes())?;
+        wtr.write_all(&record)?;
+    }
+    wtr.finish()
+}
+
+/// Write SAM, or BAM if the path ends with ".bam".
+pub fn write_alignments<P: AsRef<std::path::Path>>(
+    path: P,
+    header: &SamHeader,
+    records: &[SamRecord],
+) -> std::io::Result<()> {
+    let is_bam = path.as_ref().extension().is_some_and(|ext| ext == "bam");
+    let wtr = std::fs::File::create(&path).map(std::io::BufWriter::new)?;
+    if is_bam {
+        write_bam(wtr, header, records)
+    } else {
+        write_sam(wtr, header, records)
+    }
+}
+
+// The maximum size of the uncompressed data in a BGZF block.
+const BGZF_BLOCK_SIZE: usize = 0xff00;
+const BGZF_EOF: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// BGZF compressor, i.e., a series of gzip blocks with the block size in the extra field.
+struct BgzfWriter<W: Write> {
+    inner: W,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> BgzfWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            buffer: Vec::with_capacity(BGZF_BLOCK_SIZE),
+        }
+    }
+    fn write_block(&mut self) -> std::io::Result<()> {
+        use flate2::write::DeflateEncoder;
+        let mut encoder = DeflateEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(&self.buffer)?;
+        let compressed = encoder.finish()?;
+        let mut crc = flate2::Crc::new();
+        crc.update(&self.buffer);
+        // Header(18 bytes) + compressed data + CRC32(4 bytes) + ISIZE(4 bytes) - 1.
+        let block_size = (compressed.len() + 25) as u16;
+        let header = [
+            0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43,
+            0x02, 0x00,
+        ];
+        self.inner.write_all(&header)?;
+        self.inner.write_all(&block_size.to_le_bytes())?;
+        self.inner.write_all(&compressed)?;
+        self.inner.write_all(&crc.sum().to_le_bytes())?;
+        self.inner
+            .write_all(&(self.buffer.len() as u32).to_le_bytes())?;
+        self.buffer.clear();
+        Ok(())
+    }
+    fn finish(mut self) -> std::io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.write_block()?;
+        }
+        self.inner.write_all(&BGZF_EOF)?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Write for BgzfWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(BGZF_BLOCK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+        if self.buffer.len() == BGZF_BLOCK_SIZE {
+            self.write_block()?;
+        }
+        Ok(len)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn reg2bin_test() {
+        assert_eq!(reg2bin(0, 1), 4681);
+        assert_eq!(reg2bin(0, 1 << 14), 4681);
+        assert_eq!(reg2bin(0, (1 << 14) + 1), 585);
+        assert_eq!(reg2bin(0, 1 << 29), 0);
+    }
+    #[test]
+    fn write_test() {
+        let header = SamHeader::new(vec![("0".to_string(), 8)]);
+        let record = SamRecord {
+            qname: "read".to_string(),
+            flag: FLAG_REVERSE,
+            reference: 0,
+            pos: 2,
+            mapq: 255,
+            cigar: vec![CigarOp::SoftClip(1), CigarOp::Match(3), CigarOp::Del(1)],
+            seq: b"ACGT".to_vec(),
+            qual: vec![],
+            tags: vec![Tag::Int(*b"HP", 1)],
+        };
+        assert_eq!(record.end(), 6);
+        let mut sam = vec![];
+        write_sam(&mut sam, &header, std::slice::from_ref(&record)).unwrap();
+        let sam = String::from_utf8(sam).unwrap();
+        let line = sam.lines().last().unwrap();
+        assert_eq!(
+            line,
+            "read\t16\t0\t3\t255\t1S3M1D\t*\t0\t0\tACGT\t*\tHP:i:1"
+        );
+        let mut bam = vec![];
+        write_bam(&mut bam, &header, &[record]).unwrap();
+        assert!(bam.ends_with(&BGZF_EOF));
+        let mut decoded = vec![];
+        use std::io::Read;
+        flate2::read::MultiGzDecoder::new(bam.as_slice())
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert!(decoded.starts_with(b"BAM\x01"));
+    }
+    #[test]
+    fn long_qname_test() {
+        let record = SamRecord {
+            qname: "r".repeat(300),
+            flag: 0,
+            reference: 0,
+            pos: 0,
+            mapq: 60,
+            cigar: vec![CigarOp::Match(4)],
+            seq: b"ACGT".to_vec(),
+            qual: vec![],
+            tags: vec![],
+        };
+        let bam = record.to_bam();
+        assert_eq!(bam[8] as usize, MAX_QNAME_LEN + 1);
+        let qname = &bam[32..32 + MAX_QNAME_LEN + 1];
+        assert!(qname[..MAX_QNAME_LEN].iter().all(|&b| b == b'r'));
+        assert_eq!(qname[MAX_QNAME_LEN], 0);
+    }
+    #[test]
+    fn long_cigar_test() {
+        let cigar: Vec<_> = (0..40_000)
+            .flat_map(|_| vec![CigarOp::Match(2), CigarOp::Ins(1)])
+            .collect();
+        let record = SamRecord {
+            qname: "read".to_string(),
+            flag: 0,
+            reference: 0,
+            pos: 10,
+            mapq: 60,
+            cigar,
+            seq: vec![b'A'; 120_000],
+            qual: vec![],
+            tags: vec![],
+        };
+        let bam = record.to_bam();
+        let to_u32 = |x: &[u8]| u32::from_le_bytes([x[0], x[1], x[2], x[3]]);
+        assert_eq!(u16::from_le_bytes([bam[12], bam[13]]), 2);
+        let cigar_start = 32 + "read".len() + 1;
+        assert_eq!(to_u32(&bam[cigar_start..]), 120_000 << 4 | 4);
+        assert_eq!(to_u32(&bam[cigar_start + 4..]), 80_000 << 4 | 3);
+        let tag = &bam[bam.len() - (4 + 4 + 4 * 80_000)..];
+        assert_eq!(&tag[..4], b"CGBI");
+        assert_eq!(to_u32(&tag[4..]), 80_000);
+        assert_eq!(to_u32(&tag[8..]), 2 << 4);
+        assert_eq!(to_u32(&tag[12..]), 1 << 4 | 1);
+    }
+    #[test]
+    fn lift_over_test() {
+        let record = SamRecord {
+            qname: "read".to_string(),
+            flag: 0,
+            reference: 0,
+            pos: 2,
+            mapq: 60,
+            cigar: vec![
+                CigarOp::SoftClip(1),
+                CigarOp::Match(6),
+                CigarOp::Del(1),
+                CigarOp::Match(2),
+            ],
+            seq: b"AACGTACGT".to_vec(),
+            qual: vec![],
+            tags: vec![Tag::Int(*b"NM", 1)],
+        };
+        // The first base of the old reference is deleted, and a base is inserted after the 4th base.
+        let alignment = vec![
+            CigarOp::Ins(1),
+            CigarOp::Match(3),
+            CigarOp::Del(1),
+            CigarOp::Match(8),
+        ];
+        let lifted = lift_over(&record, &alignment).unwrap();
+        assert_eq!(lifted.pos, 1);
+        let cigar = vec![
+            CigarOp::SoftClip(1),
+            CigarOp::Match(2),
+            CigarOp::Del(1),
+            CigarOp::Match(4),
+            CigarOp::Del(1),
+            CigarOp::Match(2),
+        ];
+        assert_eq!(lifted.cigar, cigar);
+        assert!(lifted.tags.is_empty());
+        // The bases aligned to the ones only in the old reference become soft clips at the ends.
+        let alignment = vec![CigarOp::Match(4), CigarOp::Ins(7)];
+        let lifted = lift_over(&record, &alignment).unwrap();
+        assert_eq!(lifted.pos, 2);
+        let cigar = vec![
+            CigarOp::SoftClip(1),
+            CigarOp::Match(2),
+            CigarOp::SoftClip(6),
+        ];
+        assert_eq!(lifted.cigar, cigar);
+        let alignment = vec![CigarOp::Ins(11), CigarOp::Del(11)];
+        assert!(lift_over(&record, &alignment).is_none());
+    }
+    #[test]
+    fn parse_test() {
+        let header = SamHeader::new(vec![("tig".to_string(), 100)]);
+        let sam = "@SQ\tSN:tig\tLN:100\n\
+                   r1\t0\ttig\t11\t60\t2S4M1I3M\t*\t0\t0\tACGTACGTAC\t*\tNM:i:1\ttp:A:P\n\
+                   r2\t4\t*\t0\t0\t*\t*\t0\t0\tACGT\t*\n";
+        let records = parse_records(sam.as_bytes(), &header).unwrap();
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record.pos, 10);
+        assert_eq!(record.end(), 17);
+        assert_eq!(record.tags, vec![Tag::Int(*b"NM", 1)]);
+        assert_eq!(
+            record.to_line(&header),
+            "r1\t0\ttig\t11\t60\t2S4M1I3M\t*\t0\t0\tACGTACGTAC\t*\tNM:i:1"
+        );
+        let broken = "r1\t0\tunknown\t11\t60\t10M\t*\t0\t0\tACGTACGTAC\t*\n";
+        assert!(parse_records(broken.as_bytes(), &header).is_err());
+    }
+}
@@ -0,0 +1,334 @@
+//! Simulation of haplotypes and reads, to test the stages end to end without real data.
+//! The haplotypes are either given as templates or generated from a random sequence with
+//! SNVs, short indels, structural variations, and repeats. Reads are sampled from them
+//! with the length and error profile of CCS, CLR, or ONT reads, and each read records its
+//! origin in the description, like `haplotype=hap1 start=100 end=15234 strand=+`.
+use definitions::ReadType;
+use rand::Rng;
+use rand_distr::{Distribution, LogNormal};
+const BASES: &[u8] = b"ACGT";
+
+/// The configuration to generate random haplotypes.
+#[derive(Debug, Clone, Copy)]
+pub struct HaplotypeConfig {
+    /// The length of the template sequence shared by the haplotypes.
+    pub length: usize,
+    /// The number of haplotypes.
+    pub ploidy: usize,
+    /// The rate of SNVs and short indels between the template and each haplotype.
+    pub divergence: f64,
+    /// The number of structural variations in each haplotype.
+    pub sv_num: usize,
+    /// The length of each structural variation.
+    pub sv_length: usize,
+    /// The number of the copies of a repeat inserted into the template.
+    pub repeat_num: usize,
+    /// The length of the repeat.
+    pub repeat_length: usize,
+    /// The divergence between the copies of the repeat.
+    pub repeat_divergence: f64,
+}
+
+impl std::default::Default for HaplotypeConfig {
+    fn default() -> Self {
+        Self {
+            length: 100_000,
+            ploidy: 2,
+            divergence: 0.001,
+            sv_num: 0,
+            sv_length: 5_000,
+            repeat_num: 0,
+            repeat_length: 2_000,
+            repeat_divergence: 0.01,
+        }
+    }
+}
+
+/// The length distribution and the error rates of reads.
+#[derive(Debug, Clone, Copy)]
+pub struct ReadProfile {
+    pub mean_length: f64,
+    pub sd_length: f64,
+    pub min_length: usize,
+    pub mismatch: f64,
+    pub insertion: f64,
+    pub deletion: f64,
+}
+
+impl ReadProfile {
+    pub fn new(read_type: ReadType) -> Self {
+        let (mean_length, sd_length) = match read_type {
+            ReadType::CCS => (15_000f64, 1_500f64),
+            ReadType::CLR => (15_000f64, 8_000f64),
+            ReadType::ONT => (20_000f64, 15_000f64),
+            ReadType::None => (15_000f64, 3_000f64),
+        };
+        let (mismatch, insertion, deletion) = match read_type {
+            ReadType::CCS => (0.003, 0.003, 0.004),
+            ReadType::CLR => (0.02, 0.08, 0.04),
+            ReadType::ONT => (0.04, 0.03, 0.05),
+            ReadType::None => (0f64, 0f64, 0f64),
+        };
+        Self {
+            mean_length,
+            sd_length,
+            min_length: 1_000,
+            mismatch,
+            insertion,
+            deletion,
+        }
+    }
+    pub fn error_rate(&self) -> f64 {
+        self.mismatch + self.insertion + self.deletion
+    }
+    // Log-normal distribution with the mean and the standard deviation of the profile.
+    fn length_distribution(&self) -> LogNormal<f64> {
+        let sigma2 = (1f64 + (self.sd_length / self.mean_length).powi(2)).ln();
+        let mu = self.mean_length.ln() - sigma2 / 2f64;
+        LogNormal::new(mu, sigma2.sqrt()).unwrap()
+    }
+}
+
+/// Where a read comes from. The coordinates are 0-based and half-open on the forward strand
+/// of the haplotype, even if the read is sampled from the reverse strand.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    pub haplotype: String,
+    pub start: usize,
+    pub end: usize,
+    pub is_forward: bool,
+}
+
+impl std::fmt::Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let strand = if self.is_forward { '+' } else { '-' };
+        write!(
+            f,
+            "haplotype={} start={} end={} strand={}",
+            self.haplotype, self.start, self.end, strand
+        )
+    }
+}
+
+impl std::str::FromStr for Origin {
+    type Err = String;
+    /// Parse the origin from a description written by the simulator.
+    /// The other tokens in the description are ignored.
+    fn from_str(desc: &str) -> Result<Self, Self::Err> {
+        let (mut haplotype, mut start, mut end, mut is_forward) = (None, None, None, None);
+        for token in desc.split_whitespace() {
+            let mut kv = token.splitn(2, '=');
+            match (kv.next(), kv.next()) {
+                (Some("haplotype"), Some(value)) => haplotype = Some(value.to_string()),
+                (Some("start"), Some(value)) => start = value.parse().ok(),
+                (Some("end"), Some(value)) => end = value.parse().ok(),
+                (Some("strand"), Some(value)) => is_forward = Some(value == "+"),
+                _ => {}
+            }
+        }
+        match (haplotype, start, end, is_forward) {
+            (Some(haplotype), Some(start), Some(end), Some(is_forward)) => Ok(Self {
+                haplotype,
+                start,
+                end,
+                is_forward,
+            }),
+            _ => Err(format!("{} does not have the origin of a read.", desc)),
+        }
+    }
+}
+
+/// A simulated read.
+#[derive(Debug, Clone)]
+pub struct SimulatedRead {
+    pub name: String,
+    pub origin: Origin,
+    pub seq: Vec<u8>,
+}
+
+impl std::convert::From<&SimulatedRead> for bio_utils::fasta::Record {
+    fn from(read: &SimulatedRead) -> Self {
+        let desc = Some(read.origin.to_string());
+        bio_utils::fasta::Record::with_data(&read.name, &desc, &read.seq)
+    }
+}
+
+pub fn random_seq<R: Rng>(len: usize, rng: &mut R) -> Vec<u8> {
+    (0..len).map(|_| BASES[rng.gen_range(0..4)]).collect()
+}
+
+/// Introduce mismatches, insertions, and deletions into `seq` at the given rates per base.
+pub fn introduce_errors<R: Rng>(
+    seq: &[u8],
+    mismatch: f64,
+    insertion: f64,
+    deletion: f64,
+    rng: &mut R,
+) -> Vec<u8> {
+    let mut result = Vec::with_capacity(seq.len());
+    for &base in seq {
+        let prob: f64 = rng.gen();
+        if prob < mismatch {
+            let others: Vec<_> = BASES.iter().filter(|&&b| b != base).collect();
+            result.push(*others[rng.gen_range(0..others.len())]);
+        } else if prob < mismatch + deletion {
+            // Deletion.
+        } else if prob < mismatch + deletion + insertion {
+            result.push(BASES[rng.gen_range(0..4)]);
+            result.push(base);
+        } else {
+            result.push(base);
+        }
+    }
+    result
+}
+
+// Insert a deletion, an insertion, or an inversion of `len` bases at random.
+fn introduce_sv<R: Rng>(seq: &mut Vec<u8>, len: usize, rng: &mut R) {
+    if seq.len() <= len {
+        return;
+    }
+    let start = rng.gen_range(0..seq.len() - len);
+    match rng.gen_range(0..3) {
+        0 => {
+            seq.drain(start..start + len);
+        }
+        1 => {
+            let insertion = random_seq(len, rng);
+            seq.splice(start..start, insertion);
+        }
+        _ => {
+            let inversion = bio_utils::revcmp(&seq[start..start + len]);
+            seq.splice(start..start + len, inversion);
+        }
+    }
+}
+
+/// Generate haplotypes, named `hap1`, `hap2`, ..., by the configuration.
+pub fn random_haplotypes<R: Rng>(config: &HaplotypeConfig, rng: &mut R) -> Vec<(String, Vec<u8>)> {
+    let mut template = random_seq(config.length, rng);
+    let repeat = random_seq(config.repeat_length, rng);
+    let mut positions: Vec<_> = (0..config.repeat_num)
+        .map(|_| rng.gen_range(0..=template.len()))
+        .collect();
+    positions.sort_unstable_by(|x, y| y.cmp(x));
+    for pos in positions {
+        let div = config.repeat_divergence;
+        let copy = introduce_errors(&repeat, div * 0.8, div * 0.1, div * 0.1, rng);
+        template.splice(pos..pos, copy);
+    }
+    let div = config.divergence;
+    (0..config.ploidy)
+        .map(|i| {
+            let mut seq = introduce_errors(&template, div * 0.8, div * 0.1, div * 0.1, rng);
+            for _ in 0..config.sv_num {
+                introduce_sv(&mut seq, config.sv_length, rng);
+            }
+            (format!("hap{}", i + 1), seq)
+        })
+        .collect()
+}
+
+/// Sample reads from each haplotype up to the `coverage` by the profile.
+/// The reads are named `{haplotype}_{index}`.
+pub fn simulate_reads<R: Rng>(
+    haplotypes: &[(String, Vec<u8>)],
+    coverage: f64,
+    profile: &ReadProfile,
+    rng: &mut R,
+) -> Vec<SimulatedRead> {
+    let lengths = profile.length_distribution();
+    let mut reads = vec![];
+    for (name, hap) in haplotypes.iter().filter(|(_, hap)| !hap.is_empty()) {
+        let target = (hap.len() as f64 * coverage).ceil() as usize;
+        let mut total = 0;
+        while total < target {
+            let len = (lengths.sample(rng) as usize)
+                .max(profile.min_length)
+                .min(hap.len());
+            let start = rng.gen_range(0..=hap.len() - len);
+            let end = start + len;
+            let is_forward = rng.gen_bool(0.5);
+            let fragment = match is_forward {
+                true => hap[start..end].to_vec(),
+                false => bio_utils::revcmp(&hap[start..end]),
+            };
+            let (mism, ins, del) = (profile.mismatch, profile.insertion, profile.deletion);
+            let seq = introduce_errors(&fragment, mism, ins, del, rng);
+            let origin = Origin {
+                haplotype: name.clone(),
+                start,
+                end,
+                is_forward,
+            };
+            let name = format!("{}_{}", name, reads.len());
+            reads.push(SimulatedRead { name, origin, seq });
+            total += len;
+        }
+    }
+    reads
+}
+
+/// Write the origins of the reads in TSV: name, haplotype, start, end, and strand.
+pub fn write_truth<W: std::io::Write>(reads: &[SimulatedRead], mut wtr: W) -> std::io::Result<()> {
+    writeln!(&mut wtr, "name\thaplotype\tstart\tend\tstrand")?;
+    for read in reads.iter() {
+        let origin = &read.origin;
+        let strand = if origin.is_forward { '+' } else { '-' };
+        writeln!(
+            &mut wtr,
+            "{}\t{}\t{}\t{}\t{}",
+            read.name, origin.haplotype, origin.start, origin.end, strand
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_xoshiro::Xoshiro256PlusPlus;
+    #[test]
+    fn origin_test() {
+        let origin = Origin {
+            haplotype: "hap2".to_string(),
+            start: 100,
+            end: 15234,
+            is_forward: false,
+        };
+        let desc = format!("{} other=token", origin);
+        assert_eq!(desc.parse::<Origin>(), Ok(origin));
+        assert!("haplotype=hap1 start=0".parse::<Origin>().is_err());
+    }
+    #[test]
+    fn simulate_reads_test() {
+        let mut rng: Xoshiro256PlusPlus = SeedableRng::seed_from_u64(4382);
+        let config = HaplotypeConfig {
+            length: 20_000,
+            divergence: 0.01,
+            ..Default::default()
+        };
+        let haplotypes = random_haplotypes(&config, &mut rng);
+        assert_eq!(haplotypes.len(), 2);
+        assert_ne!(haplotypes[0].1, haplotypes[1].1);
+        let profile = ReadProfile::new(ReadType::None);
+        let reads = simulate_reads(&haplotypes, 5f64, &profile, &mut rng);
+        for (name, hap) in haplotypes.iter() {
+            let reads: Vec<_> = reads
+                .iter()
+                .filter(|r| &r.origin.haplotype == name)
+                .collect();
+            let total: usize = reads.iter().map(|r| r.seq.len()).sum();
+            assert!(total >= 5 * hap.len());
+            for read in reads {
+                let origin = &read.origin;
+                let seq = match origin.is_forward {
+                    true => read.seq.clone(),
+                    false => bio_utils::revcmp(&read.seq),
+                };
+                assert_eq!(&hap[origin.start..origin.end], seq.as_slice());
+            }
+        }
+    }
+}
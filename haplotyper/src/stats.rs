@@ -1,9 +1,182 @@
+use definitions::DataSet;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
 pub trait Stats {
     fn stats<W: std::io::Write>(&self, wtr: W) -> std::io::Result<()>;
+    /// The same metrics as `stats` and the clustering metrics, in a structured form.
+    fn metrics(&self) -> Metrics;
 }
 
-impl Stats for definitions::DataSet {
+/// A section is None if the DataSet does not have it yet.
+#[derive(Debug, Clone, Serialize)]
+pub struct Metrics {
+    /// The stages applied so far. The metrics are the ones after the last stage.
+    pub stages: Vec<String>,
+    pub coverage: Option<f64>,
+    pub raw_reads: Option<ReadMetrics>,
+    pub hic_pairs: Option<HiCMetrics>,
+    pub chunks: Option<ChunkMetrics>,
+    pub encoded_reads: Option<EncodingMetrics>,
+    pub units: Vec<UnitMetrics>,
+    pub assignments: Option<AssignmentMetrics>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadMetrics {
+    pub reads: usize,
+    pub total_length: usize,
+    pub mean_length: usize,
+    pub max_length: usize,
+    pub min_length: usize,
+    pub n50: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HiCMetrics {
+    pub pairs: usize,
+    pub total_length: usize,
+    pub mean_length: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChunkMetrics {
+    pub units: usize,
+    pub total_length: usize,
+    pub mean_length: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EncodingMetrics {
+    pub reads: usize,
+    /// The raw reads not encoded.
+    pub gappy_reads: usize,
+    pub gap_mean: usize,
+    /// The encoded length divided by the total length.
+    pub encoded_rate: f64,
+    /// The i-th element is the number of the reads whose encoded rates are in [i/10, (i+1)/10).
+    /// The last one includes 1.
+    pub encoded_rate_distribution: Vec<usize>,
+    pub mean_nodes: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UnitMetrics {
+    pub unit: u64,
+    /// The number of the nodes.
+    pub coverage: usize,
+    pub cluster_num: usize,
+    /// The i-th element is the number of the nodes in the i-th cluster.
+    pub cluster_sizes: Vec<usize>,
+    /// The smallest cluster size divided by the largest one.
+    pub balance: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AssignmentMetrics {
+    /// The number of the reads carrying an assignment.
+    pub reads: usize,
+    pub clusters: usize,
+    /// (cluster, the number of the reads) in the ascending order of the clusters.
+    pub cluster_sizes: Vec<(usize, usize)>,
+    /// The smallest cluster size divided by the largest one.
+    pub balance: f64,
+}
+
+fn balance(sizes: impl Iterator<Item = usize> + Clone) -> f64 {
+    let max = sizes.clone().max().unwrap_or(0);
+    let min = sizes.min().unwrap_or(0);
+    min as f64 / max.max(1) as f64
+}
+
+/// The length L such that the blocks longer than or equal to L cover the half of the `total`,
+/// e.g., the sum of `lengths` for N50, or the genome size for NG50. Return 0 if there is no such L.
+pub fn n50(lengths: &mut [usize], total: usize) -> usize {
+    lengths.sort_unstable_by(|x, y| y.cmp(x));
+    let mut acc = 0;
+    for &len in lengths.iter() {
+        acc += len;
+        if 2 * acc >= total {
+            return len;
+        }
+    }
+    0
+}
+
+impl Metrics {
+    /// Write the metrics in TSV of `key\tvalue`, where a key is like `raw_reads.n50` or `units.3.coverage`.
+    pub fn write_tsv<W: std::io::Write>(&self, mut wtr: W) -> std::io::Result<()> {
+        writeln!(&mut wtr, "key\tvalue")?;
+        writeln!(&mut wtr, "stages\t{}", self.stages.join(","))?;
+        if let Some(coverage) = self.coverage {
+            writeln!(&mut wtr, "coverage\t{}", coverage)?;
+        }
+        if let Some(r) = self.raw_reads.as_ref() {
+            writeln!(&mut wtr, "raw_reads.reads\t{}", r.reads)?;
+            writeln!(&mut wtr, "raw_reads.total_length\t{}", r.total_length)?;
+            writeln!(&mut wtr, "raw_reads.mean_length\t{}", r.mean_length)?;
+            writeln!(&mut wtr, "raw_reads.max_length\t{}", r.max_length)?;
+            writeln!(&mut wtr, "raw_reads.min_length\t{}", r.min_length)?;
+            writeln!(&mut wtr, "raw_reads.n50\t{}", r.n50)?;
+        }
+        if let Some(h) = self.hic_pairs.as_ref() {
+            writeln!(&mut wtr, "hic_pairs.pairs\t{}", h.pairs)?;
+            writeln!(&mut wtr, "hic_pairs.total_length\t{}", h.total_length)?;
+            writeln!(&mut wtr, "hic_pairs.mean_length\t{}", h.mean_length)?;
+        }
+        if let Some(c) = self.chunks.as_ref() {
+            writeln!(&mut wtr, "chunks.units\t{}", c.units)?;
+            writeln!(&mut wtr, "chunks.total_length\t{}", c.total_length)?;
+            writeln!(&mut wtr, "chunks.mean_length\t{}", c.mean_length)?;
+        }
+        if let Some(e) = self.encoded_reads.as_ref() {
+            writeln!(&mut wtr, "encoded_reads.reads\t{}", e.reads)?;
+            writeln!(&mut wtr, "encoded_reads.gappy_reads\t{}", e.gappy_reads)?;
+            writeln!(&mut wtr, "encoded_reads.gap_mean\t{}", e.gap_mean)?;
+            writeln!(
+                &mut wtr,
+                "encoded_reads.encoded_rate\t{:.4}",
+                e.encoded_rate
+            )?;
+            for (i, count) in e.encoded_rate_distribution.iter().enumerate() {
+                let rate = i as f64 / 10f64;
+                writeln!(
+                    &mut wtr,
+                    "encoded_reads.encoded_rate.{:.1}\t{}",
+                    rate, count
+                )?;
+            }
+            writeln!(&mut wtr, "encoded_reads.mean_nodes\t{:.2}", e.mean_nodes)?;
+        }
+        for u in self.units.iter() {
+            writeln!(&mut wtr, "units.{}.coverage\t{}", u.unit, u.coverage)?;
+            writeln!(&mut wtr, "units.{}.cluster_num\t{}", u.unit, u.cluster_num)?;
+            for (cl, size) in u.cluster_sizes.iter().enumerate() {
+                writeln!(&mut wtr, "units.{}.cluster.{}\t{}", u.unit, cl, size)?;
+            }
+            writeln!(&mut wtr, "units.{}.balance\t{:.4}", u.unit, u.balance)?;
+        }
+        if let Some(a) = self.assignments.as_ref() {
+            writeln!(&mut wtr, "assignments.reads\t{}", a.reads)?;
+            writeln!(&mut wtr, "assignments.clusters\t{}", a.clusters)?;
+            for (cl, size) in a.cluster_sizes.iter() {
+                writeln!(&mut wtr, "assignments.cluster.{}\t{}", cl, size)?;
+            }
+            writeln!(&mut wtr, "assignments.balance\t{:.4}", a.balance)?;
+        }
+        Ok(())
+    }
+}
+
+impl Stats for DataSet {
     fn stats<W: std::io::Write>(&self, mut wtr: W) -> std::io::Result<()> {
+        // Stages applied so far.
+        if !self.provenance.is_empty() {
+            writeln!(&mut wtr, "Provenance")?;
+            writeln!(&mut wtr, "Timestamp\tStage\tVersion\tThreads\tSeed\tConfig")?;
+            for provenance in self.provenance.iter() {
+                writeln!(&mut wtr, "{}", provenance)?;
+            }
+        }
         // raw reads.
         if !self.raw_reads.is_empty() {
             let lens = self.raw_reads.iter().map(|r| r.seq().len());
@@ -30,8 +203,8 @@ impl Stats for definitions::DataSet {
                 .iter()
                 .map(|r| r.seq1().len() + r.seq2().len());
             let sum = lens.clone().sum::<usize>();
-            let len = self.raw_reads.len() * 2;
-            let ave = sum / len;
+            let len = self.hic_pairs.len();
+            let ave = sum / (2 * len);
             writeln!(&mut wtr, "HiC Reads")?;
             writeln!(
                 &mut wtr,
@@ -54,7 +227,6 @@ impl Stats for definitions::DataSet {
         }
         // Encoded Reads
         if !self.encoded_reads.is_empty() {
-            use std::collections::HashSet;
             let reads: HashSet<_> = self.encoded_reads.iter().map(|r| r.id).collect();
             let gap_read = self
                 .raw_reads
@@ -68,7 +240,8 @@ impl Stats for definitions::DataSet {
                 .filter(|e| !reads.contains(&e.id))
                 .map(|e| e.seq().len())
                 .sum::<usize>()
-                / gap_read;
+                .checked_div(gap_read)
+                .unwrap_or(0);
             let covered_length = self
                 .encoded_reads
                 .iter()
@@ -90,7 +263,6 @@ impl Stats for definitions::DataSet {
         // Unit statistics
         if !self.encoded_reads.is_empty() {
             let mut units: Vec<(u64, usize)> = {
-                use std::collections::HashMap;
                 let mut count: HashMap<u64, usize> = HashMap::new();
                 for read in self.encoded_reads.iter() {
                     for node in read.nodes.iter() {
@@ -121,4 +293,167 @@ impl Stats for definitions::DataSet {
         }
         Ok(())
     }
+    fn metrics(&self) -> Metrics {
+        let raw_reads = (!self.raw_reads.is_empty()).then(|| {
+            let mut lens: Vec<_> = self.raw_reads.iter().map(|r| r.seq().len()).collect();
+            let total_length: usize = lens.iter().sum();
+            ReadMetrics {
+                reads: lens.len(),
+                total_length,
+                mean_length: total_length / lens.len(),
+                max_length: lens.iter().max().copied().unwrap_or(0),
+                min_length: lens.iter().min().copied().unwrap_or(0),
+                n50: n50(&mut lens, total_length),
+            }
+        });
+        let hic_pairs = (!self.hic_pairs.is_empty()).then(|| {
+            let lens = self
+                .hic_pairs
+                .iter()
+                .map(|r| r.seq1().len() + r.seq2().len());
+            let total_length: usize = lens.sum();
+            let pairs = self.hic_pairs.len();
+            HiCMetrics {
+                pairs,
+                total_length,
+                mean_length: total_length / (2 * pairs),
+            }
+        });
+        let chunks = (!self.selected_chunks.is_empty()).then(|| {
+            let total_length: usize = self.selected_chunks.iter().map(|u| u.seq().len()).sum();
+            let units = self.selected_chunks.len();
+            ChunkMetrics {
+                units,
+                total_length,
+                mean_length: total_length / units,
+            }
+        });
+        let encoded_reads = (!self.encoded_reads.is_empty()).then(|| {
+            let reads: HashSet<_> = self.encoded_reads.iter().map(|r| r.id).collect();
+            let gaps: Vec<_> = self
+                .raw_reads
+                .iter()
+                .filter(|r| !reads.contains(&r.id))
+                .map(|r| r.seq().len())
+                .collect();
+            let gap_mean = gaps.iter().sum::<usize>().checked_div(gaps.len());
+            let covered: usize = self.encoded_reads.iter().map(|r| r.encoded_length()).sum();
+            let total: usize = self.encoded_reads.iter().map(|r| r.original_length).sum();
+            let mut encoded_rate_distribution = vec![0; 10];
+            for read in self.encoded_reads.iter() {
+                let rate = read.encoded_length() as f64 / read.original_length.max(1) as f64;
+                encoded_rate_distribution[((rate * 10f64) as usize).min(9)] += 1;
+            }
+            let nodes: usize = self.encoded_reads.iter().map(|r| r.nodes.len()).sum();
+            EncodingMetrics {
+                reads: self.encoded_reads.len(),
+                gappy_reads: gaps.len(),
+                gap_mean: gap_mean.unwrap_or(0),
+                encoded_rate: covered as f64 / total.max(1) as f64,
+                encoded_rate_distribution,
+                mean_nodes: nodes as f64 / self.encoded_reads.len() as f64,
+            }
+        });
+        let mut cluster_sizes: HashMap<u64, Vec<usize>> = HashMap::new();
+        for node in self.encoded_reads.iter().flat_map(|r| r.nodes.iter()) {
+            let sizes = cluster_sizes.entry(node.unit).or_default();
+            let cluster = node.cluster as usize;
+            if sizes.len() <= cluster {
+                sizes.resize(cluster + 1, 0);
+            }
+            sizes[cluster] += 1;
+        }
+        let units: Vec<_> = self
+            .selected_chunks
+            .iter()
+            .map(|unit| {
+                let mut cluster_sizes = cluster_sizes.get(&unit.id).cloned().unwrap_or_default();
+                if cluster_sizes.len() < unit.cluster_num {
+                    cluster_sizes.resize(unit.cluster_num, 0);
+                }
+                UnitMetrics {
+                    unit: unit.id,
+                    coverage: cluster_sizes.iter().sum(),
+                    cluster_num: unit.cluster_num,
+                    balance: balance(cluster_sizes.iter().copied()),
+                    cluster_sizes,
+                }
+            })
+            .collect();
+        let assignments = (!self.assignments.is_empty()).then(|| {
+            let mut sizes: HashMap<usize, usize> = HashMap::new();
+            for asn in self.assignments.iter() {
+                *sizes.entry(asn.cluster).or_default() += 1;
+            }
+            let mut cluster_sizes: Vec<_> = sizes.into_iter().collect();
+            cluster_sizes.sort_unstable();
+            AssignmentMetrics {
+                reads: self.assignments.len(),
+                clusters: cluster_sizes.len(),
+                balance: balance(cluster_sizes.iter().map(|x| x.1)),
+                cluster_sizes,
+            }
+        });
+        Metrics {
+            stages: self.provenance.iter().map(|p| p.stage.clone()).collect(),
+            coverage: self.coverage,
+            raw_reads,
+            hic_pairs,
+            chunks,
+            encoded_reads,
+            units,
+            assignments,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::*;
+    use definitions::*;
+    #[test]
+    fn metrics_test() {
+        let reads: Vec<_> = (0..3)
+            .map(|id| {
+                let len = 1000 * (id as usize + 1);
+                raw_read(id, &format!("read{}", id), &"A".repeat(len))
+            })
+            .collect();
+        let mut ds = DataSet::with_minimum_data("test", reads, "CLR");
+        ds.selected_chunks = vec![
+            Unit::new(0, "A".repeat(100), 2),
+            Unit::new(1, "C".repeat(100), 2),
+        ];
+        ds.encoded_reads = (0..3)
+            .map(|id| {
+                let node = node(0, 0, 0, &"A".repeat(100));
+                encoded_read(id, 1000 * (id as usize + 1), vec![node])
+            })
+            .collect();
+        ds.assignments = vec![Assignment::new(0, 0), Assignment::new(1, 1)];
+        // No read is gappy.
+        let mut text = vec![];
+        ds.stats(&mut text).unwrap();
+        let metrics = ds.metrics();
+        assert_eq!(metrics.raw_reads.as_ref().unwrap().n50, 3000);
+        let encoded = metrics.encoded_reads.as_ref().unwrap();
+        assert_eq!(encoded.gappy_reads, 0);
+        assert_eq!(encoded.gap_mean, 0);
+        assert_eq!(encoded.encoded_rate_distribution[..2], [2, 1]);
+        assert_eq!(metrics.units[0].cluster_sizes, vec![3, 0]);
+        assert_eq!(metrics.units[0].balance, 0f64);
+        // Units without any node are reported, too.
+        assert_eq!(metrics.units[1].coverage, 0);
+        assert_eq!(metrics.units[1].cluster_sizes, vec![0, 0]);
+        let assignments = metrics.assignments.as_ref().unwrap();
+        assert_eq!(assignments.cluster_sizes, vec![(0, 1), (1, 1)]);
+        assert_eq!(assignments.balance, 1f64);
+    }
+    #[test]
+    fn n50_test() {
+        assert_eq!(n50(&mut [2, 5, 3], 10), 5);
+        assert_eq!(n50(&mut [2, 5, 3], 12), 3);
+        assert_eq!(n50(&mut [1], 10), 0);
+    }
 }
@@ -0,0 +1,73 @@
+//! Reading possibly compressed input streams.
+use definitions::{DataSet, BINARY_HEADER};
+use std::io::{BufRead, BufReader, Read};
+
+/// Read the first (at most) `len` bytes, and return them with a reader yielding the entire stream again.
+pub fn peek<'a, R: Read + 'a>(
+    mut reader: R,
+    len: usize,
+) -> std::io::Result<(Vec<u8>, impl Read + 'a)> {
+    let mut buf = vec![0; len];
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    buf.truncate(filled);
+    let reader = std::io::Cursor::new(buf.clone()).chain(reader);
+    Ok((buf, reader))
+}
+
+/// Wrap the reader by a decoder if the stream is compressed.
+/// Gzip(including bgzip) and zstd are detected by their magic bytes.
+pub fn decompress<'a, R: Read + 'a>(reader: R) -> std::io::Result<Box<dyn BufRead + 'a>> {
+    const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+    const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+    let (magic, reader) = peek(reader, ZSTD_MAGIC.len())?;
+    if magic.starts_with(&GZIP_MAGIC) {
+        debug!("Input is gzip-compressed.");
+        let decoder = flate2::read::MultiGzDecoder::new(reader);
+        Ok(Box::new(BufReader::new(decoder)))
+    } else if magic.starts_with(&ZSTD_MAGIC) {
+        debug!("Input is zstd-compressed.");
+        let decoder = zstd::stream::read::Decoder::new(reader)?;
+        Ok(Box::new(BufReader::new(decoder)))
+    } else {
+        Ok(Box::new(BufReader::new(reader)))
+    }
+}
+
+/// Read a DataSet in JSON or binary format, possibly compressed.
+pub fn read_dataset<R: Read>(reader: R) -> std::io::Result<DataSet> {
+    let reader = decompress(reader)?;
+    let (header, reader) = peek(reader, BINARY_HEADER.len())?;
+    if header == BINARY_HEADER {
+        debug!("Input is a binary DataSet.");
+        DataSet::from_binary(BufReader::new(reader))
+    } else {
+        DataSet::from_json(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    #[test]
+    fn read_dataset_test() {
+        let ds = DataSet::with_minimum_data("test.fa", vec![], "CCS");
+        let json = serde_json::to_vec(&ds).unwrap();
+        let mut gzip = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        gzip.write_all(&json).unwrap();
+        let gzip = gzip.finish().unwrap();
+        let mut binary = vec![];
+        ds.to_binary(&mut binary).unwrap();
+        let zstd = zstd::stream::encode_all(binary.as_slice(), 0).unwrap();
+        for input in [json, gzip, binary, zstd] {
+            let read = read_dataset(input.as_slice()).unwrap();
+            assert_eq!(read.input_file, "test.fa");
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Taking a subset of a DataSet, e.g., reads touching a few units, for debugging.
+use definitions::DataSet;
+use std::collections::HashSet;
+
+/// Which reads to keep. A read is kept if it satisfies all the specified conditions.
+#[derive(Debug, Clone, Default)]
+pub struct SubsetConfig {
+    /// Names of the reads.
+    names: Option<HashSet<String>>,
+    /// Units. Reads encoded by at least one of them are kept.
+    units: Option<HashSet<u64>>,
+    /// The cluster of the assignments.
+    cluster: Option<usize>,
+}
+
+impl SubsetConfig {
+    pub fn new(
+        names: Option<Vec<String>>,
+        units: Option<Vec<u64>>,
+        cluster: Option<usize>,
+    ) -> Self {
+        Self {
+            names: names.map(|xs| xs.into_iter().collect()),
+            units: units.map(|xs| xs.into_iter().collect()),
+            cluster,
+        }
+    }
+}
+
+pub trait Subset {
+    /// Keep the reads specified by `c`, and drop the encoded reads, assignments and units
+    /// not referred by the kept reads anymore. Hi-C pairs are kept as they are,
+    /// while Hi-C edges on the dropped units are removed.
+    fn subset(self, c: &SubsetConfig) -> Self;
+}
+
+impl Subset for DataSet {
+    fn subset(mut self, c: &SubsetConfig) -> Self {
+        let mut kept: HashSet<_> = self.raw_reads.iter().map(|r| r.id).collect();
+        if let Some(names) = c.names.as_ref() {
+            let ids = self.raw_reads.iter().filter(|r| names.contains(&r.name));
+            let ids: HashSet<_> = ids.map(|r| r.id).collect();
+            kept.retain(|id| ids.contains(id));
+        }
+        if let Some(units) = c.units.as_ref() {
+            let ids = self
+                .encoded_reads
+                .iter()
+                .filter(|r| r.nodes.iter().any(|n| units.contains(&n.unit)));
+            let ids: HashSet<_> = ids.map(|r| r.id).collect();
+            kept.retain(|id| ids.contains(id));
+        }
+        if let Some(cluster) = c.cluster {
+            let ids = self.assignments.iter().filter(|a| a.cluster == cluster);
+            let ids: HashSet<_> = ids.map(|a| a.id).collect();
+            kept.retain(|id| ids.contains(id));
+        }
+        debug!("Keep {} reads out of {}.", kept.len(), self.raw_reads.len());
+        self.raw_reads.retain(|r| kept.contains(&r.id));
+        self.assignments.retain(|a| kept.contains(&a.id));
+        // Units are dangling only when the reads have been encoded.
+        if !self.encoded_reads.is_empty() {
+            self.encoded_reads.retain(|r| kept.contains(&r.id));
+            let units: HashSet<_> = self
+                .encoded_reads
+                .iter()
+                .flat_map(|r| r.nodes.iter().map(|n| n.unit))
+                .collect();
+            self.selected_chunks.retain(|u| units.contains(&u.id));
+            self.hic_edges
+                .retain(|e| units.contains(&e.pair1) && units.contains(&e.pair2));
+            debug!("Keep {} units.", self.selected_chunks.len());
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::*;
+    use definitions::*;
+    #[test]
+    fn subset_test() {
+        let raw_reads: Vec<_> = (0..3)
+            .map(|id| raw_read(id, &format!("read{}", id), "ACGT"))
+            .collect();
+        let input = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+        let mut ds = DataSet::with_minimum_data(input, raw_reads, "CLR");
+        ds.selected_chunks = (0..3)
+            .map(|id| Unit::new(id, "ACGT".to_string(), 2))
+            .collect();
+        ds.encoded_reads = (0..3)
+            .map(|id| encoded_read(id, 4, vec![node(0, id, 0, "ACGT")]))
+            .collect();
+        ds.assignments = (0..3)
+            .map(|id| Assignment::new(id, id as usize % 2))
+            .collect();
+        let c = SubsetConfig::new(None, None, Some(0));
+        let subset = ds.clone().subset(&c);
+        let ids: Vec<_> = subset.raw_reads.iter().map(|r| r.id).collect();
+        assert_eq!(ids, vec![0, 2]);
+        let units: Vec<_> = subset.selected_chunks.iter().map(|u| u.id).collect();
+        assert_eq!(units, vec![0, 2]);
+        assert!(subset.sanity_check());
+        let names = vec!["read0".to_string(), "read1".to_string()];
+        let c = SubsetConfig::new(Some(names), Some(vec![1, 2]), None);
+        let subset = ds.subset(&c);
+        let ids: Vec<_> = subset.encoded_reads.iter().map(|r| r.id).collect();
+        assert_eq!(ids, vec![1]);
+        assert_eq!(subset.assignments.len(), 1);
+        assert_eq!(subset.selected_chunks.len(), 1);
+        assert!(subset.sanity_check());
+    }
+}
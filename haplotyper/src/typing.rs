@@ -0,0 +1,346 @@
+//! HLA allele typing of contigs against a local allele database, such as `hla_gen.fasta` of IMGT/HLA.
+//! Each contig is aligned to the alleles sharing enough k-mers with it, and the alleles with
+//! the minimum edit distance are reported for each gene. Allele names are truncated into
+//! 1 to 4 fields(i.e., 2/4/6/8-digit resolution), and the set of the distinct truncated names
+//! among the best alleles is the ambiguity set at that resolution.
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// An allele in the database. The name is like `A*01:01:01:01`.
+#[derive(Debug, Clone)]
+pub struct Allele {
+    pub name: String,
+    pub seq: Vec<u8>,
+}
+
+impl Allele {
+    pub fn new(name: &str, seq: &[u8]) -> Self {
+        Self {
+            name: name.trim_start_matches("HLA-").to_string(),
+            seq: seq.to_ascii_uppercase(),
+        }
+    }
+    /// The gene of this allele, e.g., `A` for `A*01:01:01:01`.
+    pub fn gene(&self) -> &str {
+        self.name.split('*').next().unwrap()
+    }
+    /// The name truncated into the first `fields` fields, e.g., `A*01:01` for 2 fields.
+    pub fn truncate(&self, fields: usize) -> &str {
+        match self.name.match_indices(':').nth(fields.max(1) - 1) {
+            Some((idx, _)) => &self.name[..idx],
+            None => &self.name,
+        }
+    }
+}
+
+impl std::convert::From<bio_utils::fasta::Record> for Allele {
+    // IMGT/HLA records are like `>HLA:HLA00001 A*01:01:01:01 3503 bp`.
+    // The first token with `*` is the name of the allele. If there is no such token, the ID is.
+    fn from(record: bio_utils::fasta::Record) -> Self {
+        let desc = record.desc().cloned().unwrap_or_default();
+        let name = std::iter::once(record.id())
+            .chain(desc.split_whitespace())
+            .find(|token| token.contains('*'))
+            .unwrap_or_else(|| record.id());
+        Self::new(name, record.seq())
+    }
+}
+
+/// Parse the alleles from a FASTA file.
+pub fn parse_alleles<R: std::io::BufRead>(reader: R) -> std::io::Result<Vec<Allele>> {
+    let records = bio_utils::fasta::parse_into_vec_from(reader)?;
+    Ok(records.into_iter().map(Allele::from).collect())
+}
+
+/// A sequence to be typed. `group` is the haplotype the contig belongs to, if any.
+#[derive(Debug, Clone)]
+pub struct Contig {
+    pub name: String,
+    pub group: Option<String>,
+    pub seq: Vec<u8>,
+}
+
+/// The segments with sequences in the `gfa`, the output of `jtk assemble`.
+/// The group of a segment is the set containing it, i.e., `group-{cluster}`.
+pub fn contigs_from_gfa(gfa: &gfa::GFA) -> Vec<Contig> {
+    let mut groups: HashMap<&str, &str> = HashMap::new();
+    for record in gfa.iter() {
+        if let gfa::Content::Group(gfa::Group::Set(group)) = &record.content {
+            if let Some(uid) = group.uid.as_ref() {
+                groups.extend(group.ids.iter().map(|id| (id.as_str(), uid.as_str())));
+            }
+        }
+    }
+    gfa.iter()
+        .filter_map(|record| match &record.content {
+            gfa::Content::Seg(seg) => seg.sequence.as_ref().map(|seq| Contig {
+                name: seg.sid.clone(),
+                group: groups.get(seg.sid.as_str()).map(|g| g.to_string()),
+                seq: seq.as_bytes().to_ascii_uppercase(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TypingConfig {
+    /// The length of the k-mers to select the alleles to be aligned. At most 32.
+    k: usize,
+    /// A gene is typed only if some of its alleles share this fraction of k-mers with the contig.
+    min_shared: f64,
+}
+
+impl TypingConfig {
+    pub fn new(k: usize, min_shared: f64) -> Self {
+        Self { k, min_shared }
+    }
+}
+
+impl std::default::Default for TypingConfig {
+    fn default() -> Self {
+        Self {
+            k: 15,
+            min_shared: 0.5,
+        }
+    }
+}
+
+/// The call at a resolution.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Call {
+    /// The number of fields.
+    pub fields: usize,
+    /// The best allele, truncated into `fields` fields.
+    pub allele: String,
+    /// All the truncated names of the alleles with the minimum distance, including `allele`.
+    pub ambiguity: Vec<String>,
+}
+
+/// The result of a gene on a contig.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TypingResult {
+    pub contig: String,
+    pub group: Option<String>,
+    pub gene: String,
+    pub is_forward: bool,
+    /// The edit distance between the contig and the best alleles.
+    pub distance: u32,
+    /// The length of the aligned region. It is shorter than the alleles if the contig is.
+    pub aligned_length: usize,
+    /// The calls from 1 to 4 fields.
+    pub calls: Vec<Call>,
+}
+
+fn to_idx(base: u8) -> Option<u64> {
+    match base {
+        b'A' => Some(0),
+        b'C' => Some(1),
+        b'G' => Some(2),
+        b'T' => Some(3),
+        _ => None,
+    }
+}
+
+// The k-mers of `seq` in the 2-bit encoding. The k-mers with N are skipped.
+fn kmers(seq: &[u8], k: usize) -> Vec<u64> {
+    seq.windows(k)
+        .filter_map(|kmer| {
+            kmer.iter()
+                .try_fold(0, |acc, &b| Some((acc << 2) | to_idx(b)?))
+        })
+        .collect()
+}
+
+// The fraction of the k-mers of `allele` in `kmers`.
+fn shared_fraction(allele: &[u8], kmers_of_contig: &HashSet<u64>, k: usize) -> f64 {
+    let kmers = kmers(allele, k);
+    let shared = kmers.iter().filter(|x| kmers_of_contig.contains(x)).count();
+    shared as f64 / kmers.len().max(1) as f64
+}
+
+// Return the edit distance and the length of the aligned region.
+// The shorter sequence is aligned to a part of the longer one.
+fn align(contig: &[u8], allele: &[u8]) -> (u32, usize) {
+    let (query, target) = if allele.len() <= contig.len() {
+        (allele, contig)
+    } else {
+        (contig, allele)
+    };
+    let mode = edlib_sys::AlignMode::Infix;
+    let task = edlib_sys::AlignTask::Alignment;
+    let aln = edlib_sys::edlib_align(query, target, mode, task);
+    (aln.dist, query.len())
+}
+
+/// Type the `contigs` by the `alleles`. The results are in the order of the contigs, then the genes.
+pub fn typing(contigs: &[Contig], alleles: &[Allele], c: &TypingConfig) -> Vec<TypingResult> {
+    let mut genes: HashMap<&str, Vec<&Allele>> = HashMap::new();
+    for allele in alleles.iter() {
+        genes.entry(allele.gene()).or_default().push(allele);
+    }
+    let mut genes: Vec<_> = genes.into_iter().collect();
+    genes.sort_by_key(|x| x.0);
+    let mut results = vec![];
+    for contig in contigs.iter() {
+        let forward: HashSet<_> = kmers(&contig.seq, c.k).into_iter().collect();
+        let revcmp = bio_utils::revcmp(&contig.seq);
+        let reverse: HashSet<_> = kmers(&revcmp, c.k).into_iter().collect();
+        for (gene, alleles) in genes.iter() {
+            if let Some(result) = type_gene(contig, &revcmp, (&forward, &reverse), alleles, c) {
+                debug!(
+                    "TYPING\t{}\t{}\t{}",
+                    contig.name, gene, result.calls[3].allele
+                );
+                results.push(result);
+            }
+        }
+    }
+    results
+}
+
+fn type_gene(
+    contig: &Contig,
+    revcmp: &[u8],
+    (forward, reverse): (&HashSet<u64>, &HashSet<u64>),
+    alleles: &[&Allele],
+    c: &TypingConfig,
+) -> Option<TypingResult> {
+    let shared: Vec<_> = alleles
+        .par_iter()
+        .map(|allele| {
+            let forward = shared_fraction(&allele.seq, forward, c.k);
+            let reverse = shared_fraction(&allele.seq, reverse, c.k);
+            (forward, reverse)
+        })
+        .collect();
+    let fwd_max = shared.iter().map(|x| x.0).fold(0f64, f64::max);
+    let rev_max = shared.iter().map(|x| x.1).fold(0f64, f64::max);
+    if fwd_max.max(rev_max) < c.min_shared {
+        return None;
+    }
+    let is_forward = rev_max <= fwd_max;
+    let seq = if is_forward { &contig.seq[..] } else { revcmp };
+    let mut distances: Vec<_> = alleles
+        .par_iter()
+        .zip(shared.par_iter())
+        .filter(|(_, shared)| c.min_shared <= if is_forward { shared.0 } else { shared.1 })
+        .map(|(allele, _)| (allele, align(seq, &allele.seq)))
+        .collect();
+    distances.sort_by(|x, y| (x.1).0.cmp(&(y.1).0).then(x.0.name.cmp(&y.0.name)));
+    let &(best, (distance, aligned_length)) = distances.first()?;
+    let calls = (1..=4)
+        .map(|fields| {
+            let mut ambiguity: Vec<_> = distances
+                .iter()
+                .take_while(|x| (x.1).0 == distance)
+                .map(|x| x.0.truncate(fields).to_string())
+                .collect();
+            ambiguity.sort();
+            ambiguity.dedup();
+            let allele = best.truncate(fields).to_string();
+            Call {
+                fields,
+                allele,
+                ambiguity,
+            }
+        })
+        .collect();
+    Some(TypingResult {
+        contig: contig.name.clone(),
+        group: contig.group.clone(),
+        gene: best.gene().to_string(),
+        is_forward,
+        distance,
+        aligned_length,
+        calls,
+    })
+}
+
+/// Write the results in TSV, one line for each resolution.
+pub fn write_tsv<W: std::io::Write>(results: &[TypingResult], mut wtr: W) -> std::io::Result<()> {
+    writeln!(
+        &mut wtr,
+        "Contig\tGroup\tGene\tStrand\tFields\tAllele\tDistance\tAlignedLength\tAmbiguity"
+    )?;
+    for result in results.iter() {
+        let group = result.group.as_deref().unwrap_or("-");
+        let strand = if result.is_forward { '+' } else { '-' };
+        for call in result.calls.iter() {
+            writeln!(
+                &mut wtr,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                result.contig,
+                group,
+                result.gene,
+                strand,
+                call.fields,
+                call.allele,
+                result.distance,
+                result.aligned_length,
+                call.ambiguity.join(",")
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_xoshiro::Xoshiro256PlusPlus;
+    fn mutate(seq: &[u8], positions: &[usize]) -> Vec<u8> {
+        let mut seq = seq.to_vec();
+        for &pos in positions {
+            seq[pos] = match seq[pos] {
+                b'A' => b'C',
+                b'C' => b'G',
+                b'G' => b'T',
+                _ => b'A',
+            };
+        }
+        seq
+    }
+    #[test]
+    fn truncate_test() {
+        let allele = Allele::new("HLA-A*01:01:01:02N", b"acgt");
+        assert_eq!(allele.gene(), "A");
+        assert_eq!(allele.truncate(1), "A*01");
+        assert_eq!(allele.truncate(2), "A*01:01");
+        assert_eq!(allele.truncate(4), "A*01:01:01:02N");
+        assert_eq!(allele.seq, b"ACGT");
+    }
+    #[test]
+    fn typing_test() {
+        let mut rng: Xoshiro256PlusPlus = SeedableRng::seed_from_u64(4234);
+        let mut gen =
+            |len: usize| -> Vec<u8> { (0..len).map(|_| b"ACGT"[rng.gen_range(0..4)]).collect() };
+        let a = gen(300);
+        let alleles = vec![
+            Allele::new("A*01:01:01:01", &a),
+            Allele::new("A*01:01:01:02", &mutate(&a, &[10])),
+            Allele::new("A*01:02:01:01", &a),
+            Allele::new("A*02:01:01:01", &mutate(&a, &[50, 100, 150, 200])),
+            Allele::new("B*07:02:01:01", &gen(300)),
+        ];
+        let seq: Vec<_> = gen(100).into_iter().chain(a).chain(gen(100)).collect();
+        let contig = Contig {
+            name: "tig".to_string(),
+            group: Some("group-0".to_string()),
+            seq: bio_utils::revcmp(&seq),
+        };
+        let results = typing(&[contig], &alleles, &TypingConfig::default());
+        assert_eq!(results.len(), 1);
+        let result = &results[0];
+        assert_eq!(result.gene, "A");
+        assert!(!result.is_forward);
+        assert_eq!(result.distance, 0);
+        assert_eq!(result.aligned_length, 300);
+        assert_eq!(result.calls[0].ambiguity, vec!["A*01"]);
+        assert_eq!(result.calls[1].allele, "A*01:01");
+        assert_eq!(result.calls[1].ambiguity, vec!["A*01:01", "A*01:02"]);
+        assert_eq!(result.calls[3].allele, "A*01:01:01:01");
+    }
+}
@@ -0,0 +1,410 @@
+//! Phased variants between the haplotypes of each unit, in VCF.
+//! The reference sequences are the selected chunks, and the haplotypes of a unit are its clusters.
+//! The allele of a haplotype is the majority of the nodes in the cluster at each column of the unit,
+//! and the haplotypes are ordered by the global cluster, i.e., the majority of the assignments of their reads.
+use definitions::{DataSet, Node, Op};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::io::Write;
+
+/// The minimum number of reads to call the allele of a haplotype.
+const MIN_DEPTH: usize = 3;
+
+/// A variant on a unit. Positions are 0-origin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variant {
+    pub unit: u64,
+    pub position: usize,
+    pub reference: Vec<u8>,
+    pub alternatives: Vec<Vec<u8>>,
+    /// The allele of each haplotype, an index into [reference, alternatives..]. None if unknown.
+    pub genotype: Vec<Option<usize>>,
+    /// The global cluster of each haplotype. None if the reads have no assignment.
+    pub clusters: Vec<Option<usize>>,
+    /// The number of reads supporting each allele.
+    pub depths: Vec<usize>,
+    /// The phase set, the ID of the phase block of the haplotypes by [crate::assemble::phase_blocks].
+    /// None if some haplotype has no assignment.
+    pub phase_set: Option<usize>,
+}
+
+// The allele of the read at each column of the unit: the base and the bases inserted after it.
+// A deleted column is empty, and a column not covered by the read is None.
+fn tokens(node: &Node, len: usize) -> Option<Vec<Option<Vec<u8>>>> {
+    let seq = node.seq();
+    let mut tokens: Vec<Option<Vec<u8>>> = vec![None; len];
+    let (mut qpos, mut rpos) = (0, 0);
+    let (mut start, mut end) = (len, 0);
+    for op in node.cigar.iter() {
+        match *op {
+            Op::Match(l) => {
+                if len < rpos + l || seq.len() < qpos + l {
+                    return None;
+                }
+                for (token, base) in tokens[rpos..rpos + l].iter_mut().zip(&seq[qpos..qpos + l]) {
+                    *token = Some(vec![base.to_ascii_uppercase()]);
+                }
+                start = start.min(rpos);
+                end = rpos + l;
+                qpos += l;
+                rpos += l;
+            }
+            Op::Del(l) => {
+                if len < rpos + l {
+                    return None;
+                }
+                tokens[rpos..rpos + l]
+                    .iter_mut()
+                    .for_each(|t| *t = Some(vec![]));
+                rpos += l;
+            }
+            Op::Ins(l) => {
+                if seq.len() < qpos + l {
+                    return None;
+                }
+                if let Some(Some(token)) = rpos.checked_sub(1).map(|p| tokens[p].as_mut()) {
+                    token.extend(seq[qpos..qpos + l].iter().map(u8::to_ascii_uppercase));
+                }
+                qpos += l;
+            }
+        }
+    }
+    // Leading and trailing deletions are the regions the read does not reach.
+    tokens[..start.min(len)].iter_mut().for_each(|t| *t = None);
+    tokens[end.max(start).min(len)..]
+        .iter_mut()
+        .for_each(|t| *t = None);
+    Some(tokens)
+}
+
+// Concatenate the tokens in the range. None if some column is not covered.
+fn allele(tokens: &[Option<Vec<u8>>], range: std::ops::Range<usize>) -> Option<Vec<u8>> {
+    let mut allele = vec![];
+    for token in tokens[range].iter() {
+        allele.extend(token.as_ref()?);
+    }
+    Some(allele)
+}
+
+// The majority of the tokens at each column. None if there are fewer than MIN_DEPTH tokens.
+fn consensus(reads: &[&Vec<Option<Vec<u8>>>], len: usize) -> Vec<Option<Vec<u8>>> {
+    (0..len)
+        .map(|pos| {
+            let mut counts: HashMap<&[u8], usize> = HashMap::new();
+            for token in reads.iter().filter_map(|r| r[pos].as_ref()) {
+                *counts.entry(token.as_slice()).or_default() += 1;
+            }
+            if counts.values().sum::<usize>() < MIN_DEPTH {
+                return None;
+            }
+            let (token, _) = counts.into_iter().max_by_key(|&(t, c)| (c, t))?;
+            Some(token.to_vec())
+        })
+        .collect()
+}
+
+/// Call the variants between the haplotypes on each unit.
+pub fn call_variants(ds: &DataSet) -> Vec<Variant> {
+    let assignments: HashMap<_, _> = ds.assignments.iter().map(|a| (a.id, a.cluster)).collect();
+    let mut pileups: HashMap<u64, Vec<(u64, &Node)>> = HashMap::new();
+    for read in ds.encoded_reads.iter() {
+        for node in read.nodes.iter() {
+            pileups.entry(node.unit).or_default().push((read.id, node));
+        }
+    }
+    let mut variants: Vec<_> = ds
+        .selected_chunks
+        .par_iter()
+        .flat_map(|unit| match pileups.get(&unit.id) {
+            Some(nodes) => call_variants_on(unit, nodes, &assignments),
+            None => vec![],
+        })
+        .collect();
+    // The clusters of a unit share the unit, thus they are in the same phase block.
+    let blocks = crate::assemble::phase_blocks(ds);
+    for variant in variants.iter_mut() {
+        let block = |cl: &Option<usize>| cl.and_then(|cl| blocks.get(&cl)).map(|b| b.0);
+        let blocks: Option<Vec<_>> = variant.clusters.iter().map(block).collect();
+        variant.phase_set = blocks.and_then(|blocks| blocks.first().copied());
+    }
+    variants
+}
+
+fn call_variants_on(
+    unit: &definitions::Unit,
+    nodes: &[(u64, &Node)],
+    assignments: &HashMap<u64, usize>,
+) -> Vec<Variant> {
+    let reference = unit.seq().to_ascii_uppercase();
+    let len = reference.len();
+    let reads: Vec<_> = nodes
+        .iter()
+        .filter_map(|&(id, node)| Some((id, node.cluster, tokens(node, len)?)))
+        .collect();
+    // Haplotypes: (global cluster, local cluster, consensus).
+    let mut clusters: HashMap<u64, Vec<&(u64, u64, _)>> = HashMap::new();
+    for read in reads.iter() {
+        clusters.entry(read.1).or_default().push(read);
+    }
+    let mut haplotypes: Vec<_> = clusters
+        .into_iter()
+        .map(|(cluster, reads)| {
+            let mut votes: HashMap<usize, usize> = HashMap::new();
+            for (id, _, _) in reads.iter() {
+                if let Some(&asn) = assignments.get(id) {
+                    *votes.entry(asn).or_default() += 1;
+                }
+            }
+            let global = votes
+                .into_iter()
+                .max_by_key(|&(cl, c)| (c, cl))
+                .map(|x| x.0);
+            let tokens: Vec<_> = reads.iter().map(|r| &r.2).collect();
+            (global, cluster, consensus(&tokens, len))
+        })
+        .collect();
+    haplotypes.sort_by_key(|x| (x.0, x.1));
+    let is_variant: Vec<_> = (0..len)
+        .map(|pos| {
+            haplotypes
+                .iter()
+                .any(|(_, _, cons)| match cons[pos].as_ref() {
+                    Some(token) => token.as_slice() != &reference[pos..pos + 1],
+                    None => false,
+                })
+        })
+        .collect();
+    let mut variants = vec![];
+    let mut pos = 0;
+    while pos < len {
+        if !is_variant[pos] {
+            pos += 1;
+            continue;
+        }
+        let start = pos;
+        while pos < len && is_variant[pos] {
+            pos += 1;
+        }
+        let variant = to_variant(unit.id, &reference, (start, pos), &haplotypes, &reads);
+        variants.extend(variant);
+    }
+    variants
+}
+
+type Haplotype = (Option<usize>, u64, Vec<Option<Vec<u8>>>);
+type Read = (u64, u64, Vec<Option<Vec<u8>>>);
+fn to_variant(
+    unit: u64,
+    reference: &[u8],
+    (start, end): (usize, usize),
+    haplotypes: &[Haplotype],
+    reads: &[Read],
+) -> Option<Variant> {
+    let alleles = |range: std::ops::Range<usize>| -> Vec<Option<Vec<u8>>> {
+        let haps = haplotypes.iter();
+        haps.map(|(_, _, cons)| allele(cons, range.clone()))
+            .collect()
+    };
+    let mut range = start..end;
+    let is_indel = alleles(range.clone())
+        .iter()
+        .flatten()
+        .any(|a| a.len() != end - start);
+    // Indels need a base preceding them, or following them at the start of the unit.
+    if is_indel && 0 < start {
+        range = start - 1..end;
+    } else if is_indel && end < reference.len() {
+        range = start..end + 1;
+    } else if is_indel {
+        return None;
+    }
+    let ref_allele = reference[range.clone()].to_vec();
+    let mut alternatives: Vec<Vec<u8>> = vec![];
+    let genotype: Vec<_> = alleles(range.clone())
+        .into_iter()
+        .map(|allele| {
+            let allele = allele?;
+            if allele == ref_allele {
+                return Some(0);
+            }
+            match alternatives.iter().position(|alt| alt == &allele) {
+                Some(idx) => Some(idx + 1),
+                None => {
+                    alternatives.push(allele);
+                    Some(alternatives.len())
+                }
+            }
+        })
+        .collect();
+    if alternatives.is_empty() {
+        return None;
+    }
+    let mut depths = vec![0; alternatives.len() + 1];
+    for (_, _, tokens) in reads.iter() {
+        if let Some(allele) = allele(tokens, range.clone()) {
+            if allele == ref_allele {
+                depths[0] += 1;
+            } else if let Some(idx) = alternatives.iter().position(|alt| alt == &allele) {
+                depths[idx + 1] += 1;
+            }
+        }
+    }
+    Some(Variant {
+        unit,
+        position: range.start,
+        reference: ref_allele,
+        alternatives,
+        genotype,
+        clusters: haplotypes.iter().map(|h| h.0).collect(),
+        depths,
+        phase_set: None,
+    })
+}
+
+/// Write the variants in VCF. `references` are (name, length) pairs of the units.
+pub fn write_vcf<W: Write>(
+    mut wtr: W,
+    sample: &str,
+    references: &[(String, usize)],
+    variants: &[Variant],
+) -> std::io::Result<()> {
+    writeln!(&mut wtr, "##fileformat=VCFv4.2")?;
+    writeln!(&mut wtr, "##source=jtk")?;
+    for (name, len) in references.iter() {
+        writeln!(&mut wtr, "##contig=<ID={},length={}>", name, len)?;
+    }
+    let formats = [
+        (
+            "GT",
+            "1",
+            "String",
+            "Genotype, one allele for each haplotype of the unit",
+        ),
+        (
+            "PS",
+            "1",
+            "Integer",
+            "Phase set, 1 + the smallest global cluster of the haplotypes",
+        ),
+        ("HC", ".", "String", "Global cluster of each haplotype"),
+        (
+            "AD",
+            "R",
+            "Integer",
+            "Number of reads supporting each allele",
+        ),
+        (
+            "DP",
+            "1",
+            "Integer",
+            "Number of reads supporting any allele",
+        ),
+    ];
+    for (id, number, ty, desc) in formats.iter() {
+        writeln!(
+            &mut wtr,
+            "##FORMAT=<ID={},Number={},Type={},Description=\"{}\">",
+            id, number, ty, desc
+        )?;
+    }
+    writeln!(
+        &mut wtr,
+        "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\t{}",
+        sample
+    )?;
+    let to_str = |xs: &[u8]| String::from_utf8_lossy(xs).to_string();
+    for variant in variants.iter() {
+        let alts: Vec<_> = variant.alternatives.iter().map(|a| to_str(a)).collect();
+        let phase_set = variant.phase_set;
+        let sep = if phase_set.is_some() { "|" } else { "/" };
+        let or_dot = |x: &Option<usize>| x.map_or(".".to_string(), |x| format!("{}", x));
+        let gt: Vec<_> = variant.genotype.iter().map(or_dot).collect();
+        let hc: Vec<_> = variant.clusters.iter().map(or_dot).collect();
+        let ad: Vec<_> = variant.depths.iter().map(|d| format!("{}", d)).collect();
+        let phase_set = or_dot(&phase_set);
+        let depth: usize = variant.depths.iter().sum();
+        writeln!(
+            &mut wtr,
+            "{}\t{}\t.\t{}\t{}\t.\tPASS\t.\tGT:PS:HC:AD:DP\t{}:{}:{}:{}:{}",
+            variant.unit,
+            variant.position + 1,
+            to_str(&variant.reference),
+            alts.join(","),
+            gt.join(sep),
+            phase_set,
+            hc.join(","),
+            ad.join(","),
+            depth
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use definitions::*;
+    #[test]
+    fn call_variants_test() {
+        let mut ds = DataSet::with_minimum_data("reads.fa", vec![], "CLR");
+        ds.selected_chunks = vec![Unit::new(0, "ACGTACGTAC".to_string(), 2)];
+        let node = |cluster: u64, seq: &str, cigar: Vec<Op>| Node {
+            position_from_start: 0,
+            unit: 0,
+            cluster,
+            seq: seq.to_string(),
+            qual: None,
+            is_forward: true,
+            cigar,
+        };
+        // Cluster 1 has a SNV at 3 and a deletion at 6.
+        let hap0 = || node(0, "ACGTACGTAC", vec![Op::Match(10)]);
+        let hap1 = || {
+            let cigar = vec![Op::Match(6), Op::Del(1), Op::Match(3)];
+            node(1, "ACGAACTAC", cigar)
+        };
+        // A read not reaching the end of the unit.
+        let partial = node(0, "ACGTAC", vec![Op::Match(6), Op::Del(4)]);
+        let nodes = vec![hap0(), hap0(), hap0(), hap1(), hap1(), hap1(), partial];
+        ds.encoded_reads = nodes
+            .into_iter()
+            .enumerate()
+            .map(|(id, node)| EncodedRead {
+                id: id as u64,
+                original_length: 0,
+                leading_gap: vec![],
+                trailing_gap: vec![],
+                nodes: vec![node],
+                edges: vec![],
+            })
+            .collect();
+        ds.assignments = (0..7)
+            .map(|id| Assignment::new(id, if (3..6).contains(&id) { 4 } else { 2 }))
+            .collect();
+        let variants = call_variants(&ds);
+        assert_eq!(variants.len(), 2);
+        let snv = &variants[0];
+        assert_eq!((snv.position, snv.reference.as_slice()), (3, b"T".as_ref()));
+        assert_eq!(snv.alternatives, vec![b"A".to_vec()]);
+        assert_eq!(snv.genotype, vec![Some(0), Some(1)]);
+        assert_eq!(snv.clusters, vec![Some(2), Some(4)]);
+        assert_eq!(snv.depths, vec![4, 3]);
+        assert_eq!(snv.phase_set, Some(3));
+        let del = &variants[1];
+        assert_eq!(
+            (del.position, del.reference.as_slice()),
+            (5, b"CG".as_ref())
+        );
+        assert_eq!(del.alternatives, vec![b"C".to_vec()]);
+        assert_eq!(del.depths, vec![3, 3]);
+        let mut vcf = vec![];
+        let references = vec![("0".to_string(), 10)];
+        write_vcf(&mut vcf, "sample", &references, &variants).unwrap();
+        let vcf = String::from_utf8(vcf).unwrap();
+        let last = vcf.lines().last().unwrap();
+        assert_eq!(
+            last,
+            "0\t6\t.\tCG\tC\t.\tPASS\t.\tGT:PS:HC:AD:DP\t0|1:3:2,4:3,3:6"
+        );
+    }
+}
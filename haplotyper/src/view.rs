@@ -1,59 +1,180 @@
+use definitions::{DataSet, EncodedRead, RawRead, Unit};
+use std::collections::HashMap;
+use std::io::{BufWriter, Write};
 pub trait View {
-    // Show reads with specified name. (name, ID, length, and alignemnts informations.)
-    fn view(&self, name: &str) -> Option<()>;
-    // Show unit (unit itself and the segments of the reads aligned to it.)
-    fn view_unit(&self, name: &str) -> Option<()>;
+    /// Show reads with specified names. (name, ID, length, and alignemnts informations.)
+    /// If `cluster` is Some(cl), only the nodes assigned to the cl-th cluster are shown.
+    fn view(&self, names: &[&str], cluster: Option<u64>) -> std::io::Result<()>;
+    /// Show units (unit itself and the segments of the reads aligned to it.)
+    /// If `cluster` is Some(cl), only the segments assigned to the cl-th cluster are shown.
+    fn view_unit(&self, units: &[u64], cluster: Option<u64>) -> std::io::Result<()>;
 }
 
-impl View for definitions::DataSet {
-    fn view(&self, name: &str) -> Option<()> {
-        let read = self.raw_reads.iter().find(|r| r.name == name)?;
-        let encoded = self.encoded_reads.iter().find(|e| e.id == read.id)?;
-        println!("Name:{}", name);
-        println!("ID:{}", encoded.id,);
-        println!("Length:{}", encoded.original_length);
-        println!(
+/// Index from names/IDs to reads and units, so that we do not scan the entire dataset for each query.
+pub struct ViewIndex<'a> {
+    raw_reads: HashMap<&'a str, &'a RawRead>,
+    encoded_reads: HashMap<u64, &'a EncodedRead>,
+    units: HashMap<u64, &'a Unit>,
+    // Unit ID -> (read ID, the index of the node)
+    occurrences: HashMap<u64, Vec<(u64, usize)>>,
+    id_to_name: HashMap<u64, &'a str>,
+}
+
+impl<'a> ViewIndex<'a> {
+    pub fn new(ds: &'a DataSet) -> Self {
+        let raw_reads = ds.raw_reads.iter().map(|r| (r.name.as_str(), r)).collect();
+        let encoded_reads = ds.encoded_reads.iter().map(|r| (r.id, r)).collect();
+        let units = ds.selected_chunks.iter().map(|u| (u.id, u)).collect();
+        let id_to_name = ds
+            .raw_reads
+            .iter()
+            .map(|r| (r.id, r.name.as_str()))
+            .collect();
+        let mut occurrences: HashMap<_, Vec<_>> = HashMap::new();
+        for read in ds.encoded_reads.iter() {
+            for (idx, node) in read.nodes.iter().enumerate() {
+                occurrences
+                    .entry(node.unit)
+                    .or_default()
+                    .push((read.id, idx));
+            }
+        }
+        Self {
+            raw_reads,
+            encoded_reads,
+            units,
+            occurrences,
+            id_to_name,
+        }
+    }
+    /// Write the read named `name`. Return false if there is no such encoded read.
+    pub fn view_read<W: Write>(
+        &self,
+        wtr: &mut W,
+        name: &str,
+        cluster: Option<u64>,
+    ) -> std::io::Result<bool> {
+        let read = match self.raw_reads.get(name) {
+            Some(res) => res,
+            None => return Ok(false),
+        };
+        let encoded = match self.encoded_reads.get(&read.id) {
+            Some(res) => res,
+            None => return Ok(false),
+        };
+        writeln!(wtr, "Name:{}", name)?;
+        writeln!(wtr, "ID:{}", encoded.id)?;
+        writeln!(wtr, "Length:{}", encoded.original_length)?;
+        writeln!(
+            wtr,
             "{}-(Align)-{}",
             encoded.leading_gap.len(),
             encoded.trailing_gap.len()
-        );
-        println!("Encoded Rate:{:.3}", encoded.encoded_rate());
-        for (idx, node) in encoded.nodes.iter().enumerate() {
-            println!("{}-th unit, unit id is {}", idx, node.unit);
-            println!("Start At {}", node.position_from_start);
+        )?;
+        writeln!(wtr, "Encoded Rate:{:.3}", encoded.encoded_rate())?;
+        let nodes = encoded
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| cluster.map(|cl| n.cluster == cl).unwrap_or(true));
+        for (idx, node) in nodes {
+            writeln!(wtr, "{}-th unit, unit id is {}", idx, node.unit)?;
+            writeln!(wtr, "Cluster:{}", node.cluster)?;
+            writeln!(wtr, "Start At {}", node.position_from_start)?;
             let d = if node.is_forward {
                 "Forward"
             } else {
                 "Reverse"
             };
-            println!("Direction:{}", d);
-            let unit = self.selected_chunks.iter().find(|u| u.id == node.unit)?;
+            writeln!(wtr, "Direction:{}", d)?;
+            let unit = match self.units.get(&node.unit) {
+                Some(res) => res,
+                None => {
+                    writeln!(wtr, "Unit {} is not in the selected chunks.", node.unit)?;
+                    continue;
+                }
+            };
             let (q, al, r) = node.recover(unit);
             for ((q, al), r) in q.chunks(50).zip(al.chunks(50)).zip(r.chunks(50)) {
                 let q = String::from_utf8_lossy(q);
                 let al = String::from_utf8_lossy(al);
                 let r = String::from_utf8_lossy(r);
-                println!("{}\n{}\n{}\n", q, al, r);
+                writeln!(wtr, "{}\n{}\n{}\n", q, al, r)?;
             }
         }
         for edge in encoded.edges.iter() {
-            println!("{}-//{}//-{}", edge.from, edge.offset, edge.to);
+            writeln!(wtr, "{}-//{}//-{}", edge.from, edge.offset, edge.to)?;
         }
-        println!("{}", read);
-        Some(())
+        writeln!(wtr, "{}", read)?;
+        Ok(true)
     }
-    fn view_unit(&self, name: &str) -> Option<()> {
-        let unit_id: u64 = match name.parse() {
-            Ok(res) => res,
-            Err(why) => panic!("{:?}-{} is not a valid Unit ID.", why, name),
+    /// Write the unit and the read segments on it in FASTA format. Return false if there is no such unit.
+    pub fn view_unit<W: Write>(
+        &self,
+        wtr: &mut W,
+        unit_id: u64,
+        cluster: Option<u64>,
+    ) -> std::io::Result<bool> {
+        let unit = match self.units.get(&unit_id) {
+            Some(res) => res,
+            None => return Ok(false),
         };
-        use std::collections::HashMap;
-        let id_to_name: HashMap<_, _> = self.raw_reads.iter().map(|r| (r.id, &r.name)).collect();
-        for read in self.encoded_reads.iter() {
-            if let Some(c) = read.nodes.iter().find(|n| n.unit == unit_id) {
-                println!(">{}\n{}", id_to_name[&read.id], c.seq);
+        writeln!(
+            wtr,
+            ">unit_{} cluster_num:{}\n{}",
+            unit.id, unit.cluster_num, unit.seq
+        )?;
+        let occurrences = self.occurrences.get(&unit_id).into_iter().flatten();
+        for &(id, idx) in occurrences {
+            let node = &self.encoded_reads[&id].nodes[idx];
+            if cluster.map(|cl| node.cluster == cl).unwrap_or(true) {
+                let name = self.id_to_name.get(&id).copied().unwrap_or("-");
+                writeln!(wtr, ">{} cluster:{}\n{}", name, node.cluster, node.seq)?;
             }
         }
-        Some(())
+        Ok(true)
+    }
+}
+
+fn not_found<T: std::fmt::Display>(missing: &[T], what: &str) -> std::io::Error {
+    let missing: Vec<_> = missing.iter().map(|x| format!("{}", x)).collect();
+    let msg = format!("No {} found:{}", what, missing.join(","));
+    std::io::Error::new(std::io::ErrorKind::NotFound, msg)
+}
+
+impl View for DataSet {
+    fn view(&self, names: &[&str], cluster: Option<u64>) -> std::io::Result<()> {
+        let index = ViewIndex::new(self);
+        let stdout = std::io::stdout();
+        let mut wtr = BufWriter::new(stdout.lock());
+        let mut missing = vec![];
+        for name in names {
+            if !index.view_read(&mut wtr, name, cluster)? {
+                missing.push(name);
+            }
+        }
+        wtr.flush()?;
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(not_found(&missing, "encoded read"))
+        }
+    }
+    fn view_unit(&self, units: &[u64], cluster: Option<u64>) -> std::io::Result<()> {
+        let index = ViewIndex::new(self);
+        let stdout = std::io::stdout();
+        let mut wtr = BufWriter::new(stdout.lock());
+        let mut missing = vec![];
+        for &unit in units {
+            if !index.view_unit(&mut wtr, unit, cluster)? {
+                missing.push(unit);
+            }
+        }
+        wtr.flush()?;
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(not_found(&missing, "unit"))
+        }
     }
 }
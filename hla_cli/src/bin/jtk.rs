@@ -1,6 +1,7 @@
-use clap::{App, Arg, SubCommand};
+use clap::{App, Arg, ArgGroup, SubCommand};
 use definitions::*;
 use haplotyper::*;
+use std::io::BufRead;
 use std::io::BufReader;
 use std::io::{BufWriter, Write};
 #[macro_use]
@@ -9,7 +10,7 @@ fn subcommand_entry() -> App<'static, 'static> {
     SubCommand::with_name("entry")
         .version("0.1")
         .author("Bansho Masutani")
-        .about("Entry point. It encodes a fasta file into HLA-class file.")
+        .about("Entry point. It encodes a fasta/fastq file into HLA-class file.")
         .arg(
             Arg::with_name("verbose")
                 .short("v")
@@ -23,7 +24,7 @@ fn subcommand_entry() -> App<'static, 'static> {
                 .value_name("READS")
                 .takes_value(true)
                 .required(true)
-                .help("Input FASTA file."),
+                .help("Input FASTA/FASTQ file. The format is detected automatically."),
         )
         .arg(
             Arg::with_name("read_type")
@@ -36,7 +37,14 @@ fn subcommand_entry() -> App<'static, 'static> {
 }
 
 fn subcommand_extract() -> App<'static, 'static> {
-    let targets = ["raw_reads", "hic_reads", "units", "assignments"];
+    let targets = [
+        "raw_reads",
+        "hic_reads",
+        "units",
+        "assignments",
+        "alignments",
+        "vcf",
+    ];
     SubCommand::with_name("extract")
         .version("0.1")
         .author("Bansho Masutani")
@@ -62,7 +70,8 @@ fn subcommand_extract() -> App<'static, 'static> {
                 .long("output")
                 .takes_value(true)
                 .value_name("PATH")
-                .required(true),
+                .required(true)
+                .help("Output file. For alignments, BAM if it ends with .bam, otherwise SAM."),
         )
 }
 
@@ -85,6 +94,179 @@ fn subcommand_stats() -> App<'static, 'static> {
                 .required(true)
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("stats_format")
+                .long("stats_format")
+                .value_name("FORMAT")
+                .takes_value(true)
+                .default_value(&"text")
+                .possible_values(&["text", "json", "tsv"])
+                .help("Format of the stats. `--format` is the format of the output DataSet."),
+        )
+}
+
+fn subcommand_evaluate() -> App<'static, 'static> {
+    SubCommand::with_name("evaluate")
+        .version("0.1")
+        .author("Bansho Masutani")
+        .about("Evaluate the clustering against the true haplotypes of the reads. It passes through the stdin to the stdout")
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .multiple(true)
+                .help("Debug mode"),
+        )
+        .arg(
+            Arg::with_name("truth")
+                .long("truth")
+                .required(false)
+                .value_name("TSV")
+                .help("Read name, haplotype, and optionally start, end, and strand. If not given, the descriptions of the reads by `jtk simulate` are used.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .required(true)
+                .value_name("PATH")
+                .help("Output file name of the evaluation of each unit in TSV.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .required(false)
+                .value_name("PATH")
+                .help("Write the summary in JSON into PATH.")
+                .takes_value(true),
+        )
+}
+
+fn subcommand_report() -> App<'static, 'static> {
+    SubCommand::with_name("report")
+        .version("0.1")
+        .author("Bansho Masutani")
+        .about("Write a report of the DataSet into a self-contained HTML file. It passes through the stdin to the stdout")
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .multiple(true)
+                .help("Debug mode"),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .short("t")
+                .long("threads")
+                .required(false)
+                .value_name("THREADS")
+                .help("Number of Threads")
+                .default_value(&"1")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .required(true)
+                .value_name("PATH")
+                .help("Output HTML file.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("bins")
+                .long("bins")
+                .required(false)
+                .value_name("BINS")
+                .help("Number of the bins of the histograms.")
+                .default_value(&"50")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("min_edge_count")
+                .long("min_edge_count")
+                .required(false)
+                .value_name("COUNT")
+                .help("Edges of the unit graph supported by fewer reads are not drawn.")
+                .default_value(&"2")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("contigs")
+                .short("c")
+                .long("contigs")
+                .required(false)
+                .value_name("GFA")
+                .help("The output of `jtk assemble` on the DataSet, to summarize the contigs.")
+                .takes_value(true),
+        )
+}
+
+fn subcommand_evaluate_assembly() -> App<'static, 'static> {
+    let app = SubCommand::with_name("evaluate_assembly")
+        .version("0.1")
+        .author("Bansho Masutani")
+        .about("Evaluate the contigs of `jtk assemble` against the true haplotypes. It passes through the stdin to the stdout")
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .multiple(true)
+                .help("Debug mode"),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .short("t")
+                .long("threads")
+                .required(false)
+                .value_name("THREADS")
+                .help("Number of Threads")
+                .default_value(&"1")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("contigs")
+                .short("c")
+                .long("contigs")
+                .required(true)
+                .value_name("GFA")
+                .help("The output of `jtk assemble` on the DataSet.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("haplotypes")
+                .long("haplotypes")
+                .required(true)
+                .value_name("FASTA")
+                .help("The true haplotype sequences.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .required(true)
+                .value_name("PATH")
+                .help("Output file name of the evaluation of each contig in TSV.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .required(false)
+                .value_name("PATH")
+                .help("Write the summary and the misjoins in JSON into PATH.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("window")
+                .long("window")
+                .required(false)
+                .value_name("LENGTH")
+                .help("Contigs are cut into windows of LENGTH, and each window is aligned to the haplotypes.")
+                .default_value(&"5000")
+                .takes_value(true),
+        );
+    aligner_args(app, "edlib")
 }
 
 fn subcommand_select_unit() -> App<'static, 'static> {
@@ -243,10 +425,92 @@ fn subcommand_repeatmasking() -> App<'static, 'static> {
 }
 
 fn subcommand_encode() -> App<'static, 'static> {
-    SubCommand::with_name("encode")
+    let app = SubCommand::with_name("encode")
+        .version("0.1")
+        .author("Bansho Masutani")
+        .about("Encode reads by alignments (Internally invoke `minimap2` tools by default).")
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .multiple(true)
+                .help("Debug mode"),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .long("threads")
+                .short("t")
+                .help("Number of threads")
+                .takes_value(true)
+                .default_value(&"1"),
+        )
+        .arg(
+            Arg::with_name("paf")
+                .long("paf")
+                .help(
+                    "Encode reads by the alignments from the reads to the units in this PAF file, \
+                     instead of aligning them.",
+                )
+                .takes_value(true)
+                .value_name("PATH"),
+        )
+        .arg(
+            Arg::with_name("dump_paf")
+                .long("dump-paf")
+                .help("Write the alignments from the reads to the units into this PAF file.")
+                .takes_value(true)
+                .value_name("PATH")
+                .conflicts_with("paf"),
+        );
+    aligner_args(app, "minimap2")
+}
+
+/// The arguments to select the aligner, read by `aligner_from_matches`.
+fn aligner_args(app: App<'static, 'static>, default: &'static str) -> App<'static, 'static> {
+    let app = app.arg(
+        Arg::with_name("aligner")
+            .long("aligner")
+            .help("Aligner. `edlib` runs in this process, without minimap2.")
+            .takes_value(true)
+            .default_value(default)
+            .possible_values(&["minimap2", "edlib"]),
+    );
+    minimap2_args(app)
+}
+
+/// The arguments to configure minimap2, read by `minimap2_from_matches`.
+fn minimap2_args(app: App<'static, 'static>) -> App<'static, 'static> {
+    app.arg(
+        Arg::with_name("minimap2")
+            .long("minimap2")
+            .help("Path to the minimap2 binary.")
+            .takes_value(true)
+            .value_name("PATH"),
+    )
+    .arg(
+        Arg::with_name("preset")
+            .long("preset")
+            .help("Preset of minimap2(-x), such as map-pb.")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("tmpdir")
+            .long("tmpdir")
+            .help("Directory for the temporary files of minimap2[default: $TMPDIR].")
+            .takes_value(true)
+            .value_name("DIR"),
+    )
+    .arg(
+        Arg::with_name("keep_tmp")
+            .long("keep_tmp")
+            .help("Keep the temporary files if minimap2 fails."),
+    )
+}
+
+fn subcommand_hic() -> App<'static, 'static> {
+    let app = SubCommand::with_name("hic")
         .version("0.1")
         .author("Bansho Masutani")
-        .about("Encode reads by alignments (Internally invoke `minimap2` tools).")
+        .about("Load Hi-C read pairs, and map them onto the units to compute Hi-C edges (Internally invoke `minimap2` tools).")
         .arg(
             Arg::with_name("verbose")
                 .short("v")
@@ -261,6 +525,32 @@ fn subcommand_encode() -> App<'static, 'static> {
                 .takes_value(true)
                 .default_value(&"1"),
         )
+        .arg(
+            Arg::with_name("read1")
+                .short("1")
+                .long("read1")
+                .takes_value(true)
+                .value_name("READS")
+                .requires("read2")
+                .help("The first mates in FASTA/FASTQ. If not given, only the Hi-C edges are re-computed."),
+        )
+        .arg(
+            Arg::with_name("read2")
+                .short("2")
+                .long("read2")
+                .takes_value(true)
+                .value_name("READS")
+                .requires("read1")
+                .help("The second mates in FASTA/FASTQ."),
+        )
+        .arg(
+            Arg::with_name("max_diff")
+                .long("max_diff")
+                .takes_value(true)
+                .default_value(&"0.2")
+                .help("Maximum edit distance per base between a mate and the nodes of its cluster."),
+        );
+    minimap2_args(app)
 }
 
 fn subcommand_pick_components() -> App<'static, 'static> {
@@ -461,6 +751,30 @@ fn subcommand_global_clustering() -> App<'static, 'static> {
                 .long("graph")
                 .help("Invoke graph-WhatsHap instead of de Bruijn."),
         )
+        .arg(
+            Arg::with_name("hic")
+                .long("hic")
+                .conflicts_with("graph")
+                .help("Join the clusters by Hi-C edges after de Bruijn. Run `hic` in advance."),
+        )
+        .arg(
+            Arg::with_name("min_contacts")
+                .long("min_contacts")
+                .required(false)
+                .value_name("MIN_CONTACTS")
+                .help("The minimum number of Hi-C edges to join two clusters.")
+                .default_value(&"5")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("min_fraction")
+                .long("min_fraction")
+                .required(false)
+                .value_name("MIN_FRACTION")
+                .help("The minimum fraction of the Hi-C edges between two clusters in the Hi-C edges from each cluster.")
+                .default_value(&"0.5")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("threads")
                 .short("t")
@@ -577,7 +891,7 @@ fn subcommand_clustering_correction() -> App<'static, 'static> {
 }
 
 fn subcommand_assembly() -> App<'static, 'static> {
-    SubCommand::with_name("assemble")
+    let app = SubCommand::with_name("assemble")
         .version("0.1")
         .author("BanshoMasutani")
         .about("Assemble reads.")
@@ -622,72 +936,905 @@ fn subcommand_assembly() -> App<'static, 'static> {
                 .help("Output file name")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("bam")
+                .long("bam")
+                .required(false)
+                .value_name("PATH")
+                .help("Write the reads used in polishing, aligned to the contigs, sorted and tagged by their cluster(ac) and group(gi). SAM unless it ends with .bam.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("haplotypes")
+                .long("haplotypes")
+                .required(false)
+                .value_name("DIR")
+                .help("Write the contigs of each haplotype into DIR/hap1.fa, DIR/hap2.fa, ..., with the group(gi), phase block(ps), coverage(cv) and copy number(cp).")
+                .takes_value(true),
+        );
+    minimap2_args(app)
 }
 
-fn entry(matches: &clap::ArgMatches) -> std::io::Result<DataSet> {
-    debug!("Entry");
-    let file = matches.value_of("input").unwrap();
-    let reader = std::fs::File::open(file).map(BufReader::new)?;
-    let seqs = bio_utils::fasta::parse_into_vec_from(reader)?;
-    debug!("Encoding {} reads", seqs.len());
-    let read_type = matches.value_of("read_type").unwrap();
-    Ok(DataSet::new(file, &seqs, read_type))
-}
-
-fn extract(matches: &clap::ArgMatches, dataset: DataSet) -> std::io::Result<DataSet> {
-    debug!("Extract");
-    debug!("Target is {}", matches.value_of("target").unwrap());
-    let file = std::fs::File::create(matches.value_of("output").unwrap())?;
-    match matches.value_of("target").unwrap() {
-        "raw_reads" => {
-            let mut wtr = bio_utils::fasta::Writer::new(file);
-            for seq in dataset.extract_fasta(ExtractTarget::RawReads) {
-                wtr.write_record(&seq)?;
-            }
-        }
-        "hic_reads" => {
-            let mut wtr = bio_utils::fasta::Writer::new(file);
-            for seq in dataset.extract_fasta(ExtractTarget::HiCReads) {
-                wtr.write_record(&seq)?;
-            }
-        }
-        "units" => {
-            let mut wtr = bio_utils::fasta::Writer::new(file);
-            for seq in dataset.extract_fasta(ExtractTarget::Units) {
-                wtr.write_record(&seq)?;
-            }
-        }
-        "assignments" => {
-            let asn_name_desc = dataset.extract_assignments();
-            let mut wtr = BufWriter::new(file);
-            for (asn, name, desc) in asn_name_desc {
-                writeln!(&mut wtr, "{}\t{}\t{}", asn, name, desc)?;
-            }
-        }
-        &_ => unreachable!(),
-    };
-    Ok(dataset)
+fn subcommand_convert() -> App<'static, 'static> {
+    SubCommand::with_name("convert")
+        .version("0.1")
+        .author("Bansho Masutani")
+        .about("Convert the DataSet from the stdin into the format specified by --format.")
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .multiple(true)
+                .help("Debug mode"),
+        )
 }
 
-fn stats(matches: &clap::ArgMatches, dataset: DataSet) -> std::io::Result<DataSet> {
-    debug!("Start Stats step");
-    let wtr = std::io::BufWriter::new(std::fs::File::create(matches.value_of("file").unwrap())?);
-    dataset.stats(wtr)?;
-    Ok(dataset)
+fn subcommand_merge() -> App<'static, 'static> {
+    SubCommand::with_name("merge")
+        .version("0.1")
+        .author("Bansho Masutani")
+        .about("Merge the DataSet from the stdin with other DataSets. IDs of reads and units are renumbered.")
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .multiple(true)
+                .help("Debug mode"),
+        )
+        .arg(
+            Arg::with_name("input")
+                .short("i")
+                .long("input")
+                .takes_value(true)
+                .multiple(true)
+                .required(true)
+                .value_name("PATH")
+                .help("DataSets to be merged."),
+        )
 }
 
-fn select_unit(matches: &clap::ArgMatches, dataset: DataSet) -> std::io::Result<DataSet> {
-    debug!("Start Selecting Units");
-    let chunk_len: usize = matches
-        .value_of("chunk_len")
-        .and_then(|e| e.parse().ok())
-        .expect("Chunk len");
-    let margin: usize = matches
-        .value_of("margin")
-        .and_then(|e| e.parse().ok())
-        .expect("Margin");
-    let skip_len: usize = matches
-        .value_of("skip_len")
+fn subcommand_subset() -> App<'static, 'static> {
+    SubCommand::with_name("subset")
+        .version("0.1")
+        .author("Bansho Masutani")
+        .about("Keep the reads satisfying all the given conditions, and drop dangling units and assignments.")
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .multiple(true)
+                .help("Debug mode"),
+        )
+        .arg(
+            Arg::with_name("names")
+                .short("n")
+                .long("names")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("File with a read name per line."),
+        )
+        .arg(
+            Arg::with_name("unit")
+                .short("u")
+                .long("unit")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("UNIT")
+                .help("Keep reads encoded by this unit."),
+        )
+        .arg(
+            Arg::with_name("cluster")
+                .short("c")
+                .long("cluster")
+                .takes_value(true)
+                .value_name("CLUSTER")
+                .help("Keep reads assigned to this cluster."),
+        )
+        .group(
+            ArgGroup::with_name("condition")
+                .args(&["names", "unit", "cluster"])
+                .multiple(true)
+                .required(true),
+        )
+}
+
+fn subcommand_validate() -> App<'static, 'static> {
+    SubCommand::with_name("validate")
+        .version("0.1")
+        .author("Bansho Masutani")
+        .about("Check the invariants of the DataSet from the stdin, and report all the violations.")
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .multiple(true)
+                .help("Debug mode"),
+        )
+}
+
+fn subcommand_view() -> App<'static, 'static> {
+    SubCommand::with_name("view")
+        .version("0.1")
+        .author("Bansho Masutani")
+        .about("Show the alignments of reads, or the read segments on units. It does not output the DataSet.")
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .multiple(true)
+                .help("Debug mode"),
+        )
+        .arg(
+            Arg::with_name("read")
+                .long("read")
+                .short("r")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("NAME")
+                .help("Show the read with NAME."),
+        )
+        .arg(
+            Arg::with_name("unit")
+                .long("unit")
+                .short("u")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("ID")
+                .help("Show the unit with ID and the read segments on it."),
+        )
+        .group(
+            ArgGroup::with_name("target")
+                .args(&["read", "unit"])
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("cluster")
+                .long("cluster")
+                .short("c")
+                .takes_value(true)
+                .value_name("CLUSTER")
+                .help("Show only the nodes in CLUSTER."),
+        )
+}
+
+fn subcommand_typing() -> App<'static, 'static> {
+    SubCommand::with_name("typing")
+        .version("0.1")
+        .author("Bansho Masutani")
+        .about("Call HLA alleles of contigs by a local allele database. It does not read nor output the DataSet.")
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .multiple(true)
+                .help("Debug mode"),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .short("t")
+                .long("threads")
+                .required(false)
+                .value_name("THREADS")
+                .help("Number of Threads")
+                .default_value(&"1")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("contigs")
+                .short("c")
+                .long("contigs")
+                .required(true)
+                .value_name("PATH")
+                .help("Contigs in GFA(the output of `jtk assemble`) or FASTA.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("alleles")
+                .short("a")
+                .long("alleles")
+                .required(true)
+                .value_name("FASTA")
+                .help("Allele sequences, such as hla_gen.fasta of IMGT/HLA.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .required(true)
+                .value_name("PATH")
+                .help("Output file name of the calls in TSV.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .required(false)
+                .value_name("PATH")
+                .help("Write the calls in JSON into PATH, too.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("k")
+                .short("k")
+                .long("kmer_size")
+                .required(false)
+                .value_name("K")
+                .help("The size of k-mers to select the alleles to be aligned(<=32).")
+                .default_value(&"15")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("min_shared")
+                .long("min_shared")
+                .required(false)
+                .value_name("FRACTION")
+                .help("A gene is typed only if one of its alleles shares FRACTION of the k-mers with the contig.")
+                .default_value(&"0.5")
+                .takes_value(true),
+        )
+}
+
+fn subcommand_simulate() -> App<'static, 'static> {
+    SubCommand::with_name("simulate")
+        .version("0.1")
+        .author("Bansho Masutani")
+        .about("Simulate reads from template haplotypes or random ones. It does not read nor output the DataSet.")
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .multiple(true)
+                .help("Debug mode"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .required(true)
+                .value_name("PATH")
+                .help("Output FASTA file of the reads. The description of a read is its origin.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("templates")
+                .long("templates")
+                .required(false)
+                .multiple(true)
+                .value_name("FASTA")
+                .help("Each record is a haplotype. If not given, haplotypes are generated at random.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("read_type")
+                .long("read_type")
+                .takes_value(true)
+                .default_value(&"CLR")
+                .possible_values(&["CCS", "CLR", "ONT"])
+                .help("Length and error profile of the reads. CCS, CLR, or ONT."),
+        )
+        .arg(
+            Arg::with_name("coverage")
+                .long("coverage")
+                .required(false)
+                .value_name("COVERAGE")
+                .help("Coverage per haplotype.")
+                .default_value(&"20")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .short("s")
+                .required(false)
+                .value_name("SEED")
+                .help("Seed for pseudorandom number generators.")
+                .default_value(&"42")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("length")
+                .long("length")
+                .required(false)
+                .value_name("LENGTH")
+                .help("Length of the random haplotypes.")
+                .default_value(&"100000")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("ploidy")
+                .long("ploidy")
+                .required(false)
+                .value_name("PLOIDY")
+                .help("Number of the random haplotypes.")
+                .default_value(&"2")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("divergence")
+                .long("divergence")
+                .required(false)
+                .value_name("RATE")
+                .help("Rate of SNVs and short indels in each random haplotype.")
+                .default_value(&"0.001")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("sv_num")
+                .long("sv_num")
+                .required(false)
+                .value_name("NUM")
+                .help("Number of structural variations in each random haplotype.")
+                .default_value(&"0")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("sv_length")
+                .long("sv_length")
+                .required(false)
+                .value_name("LENGTH")
+                .help("Length of the structural variations.")
+                .default_value(&"5000")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("repeat_num")
+                .long("repeat_num")
+                .required(false)
+                .value_name("NUM")
+                .help("Number of the copies of a repeat shared by the random haplotypes.")
+                .default_value(&"0")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("repeat_length")
+                .long("repeat_length")
+                .required(false)
+                .value_name("LENGTH")
+                .help("Length of the repeat.")
+                .default_value(&"2000")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("repeat_divergence")
+                .long("repeat_divergence")
+                .required(false)
+                .value_name("RATE")
+                .help("Divergence between the copies of the repeat.")
+                .default_value(&"0.01")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("haplotypes")
+                .long("haplotypes")
+                .required(false)
+                .value_name("PATH")
+                .help("Write the haplotypes into PATH in FASTA.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("truth")
+                .long("truth")
+                .required(false)
+                .value_name("PATH")
+                .help("Write the origins of the reads into PATH in TSV.")
+                .takes_value(true),
+        )
+}
+
+fn subcommand_pipeline() -> App<'static, 'static> {
+    SubCommand::with_name("pipeline")
+        .version("0.1")
+        .author("Bansho Masutani")
+        .about("Run the stages listed in a TOML file in one process. If the first stage is not `entry`, the DataSet is read from the stdin.")
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .multiple(true)
+                .help("Debug mode"),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .short("c")
+                .takes_value(true)
+                .required(true)
+                .value_name("TOML")
+                .help("Each [[stage]] table has the `name` of the subcommand and its options, such as `threads = 23`. See script/pipeline.toml."),
+        )
+        .arg(
+            Arg::with_name("workdir")
+                .long("workdir")
+                .takes_value(true)
+                .value_name("DIR")
+                .help("Save a checkpoint after each stage into DIR. On restart, the stages with valid checkpoints are skipped."),
+        )
+}
+
+/// All the subcommands except `pipeline`.
+fn subcommands() -> Vec<App<'static, 'static>> {
+    vec![
+        subcommand_entry(),
+        subcommand_extract(),
+        subcommand_stats(),
+        subcommand_evaluate(),
+        subcommand_evaluate_assembly(),
+        subcommand_report(),
+        subcommand_select_unit(),
+        subcommand_polish_unit(),
+        subcommand_encode(),
+        subcommand_hic(),
+        subcommand_multiplicity_estimation(),
+        subcommand_local_clustering(),
+        subcommand_global_clustering(),
+        subcommand_clustering_correction(),
+        subcommand_assembly(),
+        subcommand_pick_components(),
+        subcommand_repeatmasking(),
+        subcommand_convert(),
+        subcommand_view(),
+        subcommand_validate(),
+        subcommand_merge(),
+        subcommand_subset(),
+        subcommand_typing(),
+        subcommand_simulate(),
+    ]
+}
+
+/// Append a record of the stage to the provenance of the dataset.
+fn record(
+    mut dataset: DataSet,
+    stage: &str,
+    config: serde_json::Value,
+    threads: usize,
+    seed: Option<u64>,
+) -> DataSet {
+    let version = env!("CARGO_PKG_VERSION");
+    let provenance = Provenance::new(stage, version, &config, threads, seed);
+    dataset.provenance.push(provenance);
+    dataset
+}
+
+fn entry(matches: &clap::ArgMatches) -> std::io::Result<DataSet> {
+    debug!("Entry");
+    let file = matches.value_of("input").unwrap();
+    let reader = std::fs::File::open(file).and_then(stream::decompress)?;
+    let seqs = haplotyper::parse_reads(reader)?;
+    debug!("Encoding {} reads", seqs.len());
+    let read_type = matches.value_of("read_type").unwrap();
+    let dataset = DataSet::new(file, &seqs, read_type);
+    let config = serde_json::json!({"input": file, "read_type": read_type});
+    Ok(record(dataset, "entry", config, 1, None))
+}
+
+fn extract(matches: &clap::ArgMatches, dataset: DataSet) -> std::io::Result<DataSet> {
+    debug!("Extract");
+    debug!("Target is {}", matches.value_of("target").unwrap());
+    let file = std::fs::File::create(matches.value_of("output").unwrap())?;
+    match matches.value_of("target").unwrap() {
+        "raw_reads" => {
+            let mut wtr = bio_utils::fasta::Writer::new(file);
+            for seq in dataset.extract_fasta(ExtractTarget::RawReads) {
+                wtr.write_record(&seq)?;
+            }
+        }
+        "hic_reads" => {
+            let mut wtr = bio_utils::fasta::Writer::new(file);
+            for seq in dataset.extract_fasta(ExtractTarget::HiCReads) {
+                wtr.write_record(&seq)?;
+            }
+        }
+        "units" => {
+            let mut wtr = bio_utils::fasta::Writer::new(file);
+            for seq in dataset.extract_fasta(ExtractTarget::Units) {
+                wtr.write_record(&seq)?;
+            }
+        }
+        "assignments" => {
+            let asn_name_desc = dataset.extract_assignments();
+            let mut wtr = BufWriter::new(file);
+            for (asn, name, desc) in asn_name_desc {
+                writeln!(&mut wtr, "{}\t{}\t{}", asn, name, desc)?;
+            }
+        }
+        "alignments" => {
+            let (header, records) = dataset.extract_alignments();
+            let output = matches.value_of("output").unwrap();
+            haplotyper::sam::write_alignments(output, &header, &records)?;
+        }
+        "vcf" => {
+            let (references, variants) = dataset.extract_variants();
+            debug!("{} variants", variants.len());
+            let sample = std::path::Path::new(&dataset.input_file)
+                .file_stem()
+                .map_or("sample".to_string(), |stem| {
+                    stem.to_string_lossy().to_string()
+                });
+            let wtr = BufWriter::new(file);
+            haplotyper::vcf::write_vcf(wtr, &sample, &references, &variants)?;
+        }
+        &_ => unreachable!(),
+    };
+    let config = serde_json::json!({
+        "target": matches.value_of("target"),
+        "output": matches.value_of("output"),
+    });
+    Ok(record(dataset, "extract", config, 1, None))
+}
+
+fn merge(matches: &clap::ArgMatches, mut dataset: DataSet) -> std::io::Result<DataSet> {
+    debug!("Start Merge");
+    let inputs: Vec<_> = matches.values_of("input").unwrap().collect();
+    for path in inputs.iter() {
+        debug!("Merging {}", path);
+        let other = std::fs::File::open(path).and_then(stream::read_dataset)?;
+        dataset = dataset.merge(other)?;
+    }
+    let config = serde_json::json!({"input": inputs});
+    Ok(record(dataset, "merge", config, 1, None))
+}
+
+fn subset(matches: &clap::ArgMatches, dataset: DataSet) -> std::io::Result<DataSet> {
+    debug!("Start Subset");
+    let names = match matches.value_of("names") {
+        Some(path) => {
+            let names = std::fs::File::open(path).map(BufReader::new)?.lines();
+            let names: Vec<_> = names.collect::<std::io::Result<_>>()?;
+            let names = names.iter().map(|x| x.trim().to_string());
+            Some(names.filter(|x| !x.is_empty()).collect())
+        }
+        None => None,
+    };
+    let units: Option<Vec<u64>> = matches
+        .values_of("unit")
+        .map(|units| units.map(|u| parse_value("unit", u)).collect())
+        .transpose()?;
+    let cluster: Option<usize> = matches
+        .value_of("cluster")
+        .map(|cl| parse_value("cluster", cl))
+        .transpose()?;
+    // The names are recorded by the path of the file, not by themselves.
+    let summary = serde_json::json!({
+        "names": matches.value_of("names"),
+        "units": units,
+        "cluster": cluster,
+    });
+    let config = SubsetConfig::new(names, units, cluster);
+    Ok(record(dataset.subset(&config), "subset", summary, 1, None))
+}
+
+fn validate(dataset: &DataSet) -> std::io::Result<()> {
+    debug!("Start Validate");
+    let violations = dataset.validate();
+    let stdout = std::io::stdout();
+    let mut wtr = BufWriter::new(stdout.lock());
+    for violation in violations.iter() {
+        writeln!(&mut wtr, "{}", violation)?;
+    }
+    writeln!(&mut wtr, "{} violations found.", violations.len())?;
+    wtr.flush()?;
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        let msg = format!("{} violations found.", violations.len());
+        Err(std::io::Error::new(std::io::ErrorKind::InvalidData, msg))
+    }
+}
+
+fn typing(matches: &clap::ArgMatches) -> std::io::Result<()> {
+    debug!("Start Typing");
+    use haplotyper::typing;
+    let threads: usize = matches
+        .value_of("threads")
+        .and_then(|num| num.parse().ok())
+        .unwrap();
+    // The k-mers are encoded into u64.
+    let k: usize = parse_arg(matches, "k")?;
+    if !(1..=32).contains(&k) {
+        let msg = format!("Invalid value of --kmer_size:{}. It must be 1..=32.", k);
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, msg));
+    }
+    let min_shared: f64 = matches
+        .value_of("min_shared")
+        .and_then(|num| num.parse().ok())
+        .unwrap();
+    if let Err(why) = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+    {
+        debug!("{:?} If you run `pipeline` module, this is Harmless.", why);
+    }
+    let path = matches.value_of("contigs").unwrap();
+    let content = std::fs::read(path)?;
+    let contigs: Vec<_> = if content.first() == Some(&b'>') {
+        let records = bio_utils::fasta::parse_into_vec_from(content.as_slice())?;
+        let contigs = records.into_iter().map(|record| typing::Contig {
+            name: record.id().to_string(),
+            group: None,
+            seq: record.seq().to_ascii_uppercase(),
+        });
+        contigs.collect()
+    } else {
+        typing::contigs_from_gfa(&gfa::GFA::from_reader(content.as_slice()))
+    };
+    let alleles = std::fs::File::open(matches.value_of("alleles").unwrap())
+        .and_then(stream::decompress)
+        .and_then(typing::parse_alleles)?;
+    debug!(
+        "Typing {} contigs by {} alleles",
+        contigs.len(),
+        alleles.len()
+    );
+    let config = typing::TypingConfig::new(k, min_shared);
+    let results = typing::typing(&contigs, &alleles, &config);
+    let wtr = std::fs::File::create(matches.value_of("output").unwrap()).map(BufWriter::new)?;
+    typing::write_tsv(&results, wtr)?;
+    if let Some(path) = matches.value_of("json") {
+        let wtr = std::fs::File::create(path).map(BufWriter::new)?;
+        serde_json::ser::to_writer_pretty(wtr, &results)?;
+    }
+    Ok(())
+}
+
+// Parse the value of the argument `name`. Return an error if it is invalid, e.g., "1.5" for an integer.
+fn parse_arg<T: std::str::FromStr>(matches: &clap::ArgMatches, name: &str) -> std::io::Result<T> {
+    parse_value(name, matches.value_of(name).unwrap_or_default())
+}
+
+fn parse_value<T: std::str::FromStr>(name: &str, value: &str) -> std::io::Result<T> {
+    value.parse().map_err(|_| {
+        let msg = format!("Invalid value of --{}:{}", name, value);
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, msg)
+    })
+}
+
+fn simulate(matches: &clap::ArgMatches) -> std::io::Result<()> {
+    debug!("Start Simulation");
+    use haplotyper::simulate::*;
+    use rand::SeedableRng;
+    let seed: u64 = parse_arg(matches, "seed")?;
+    let mut rng: rand_xoshiro::Xoshiro256PlusPlus = SeedableRng::seed_from_u64(seed);
+    let haplotypes: Vec<_> = match matches.values_of("templates") {
+        Some(paths) => {
+            let mut haplotypes = vec![];
+            for path in paths {
+                let records = std::fs::File::open(path)
+                    .map(BufReader::new)
+                    .and_then(bio_utils::fasta::parse_into_vec_from)?;
+                haplotypes.extend(records.into_iter().map(|record| {
+                    let seq = record.seq().to_ascii_uppercase();
+                    (record.id().to_string(), seq)
+                }));
+            }
+            haplotypes
+        }
+        None => {
+            let config = HaplotypeConfig {
+                length: parse_arg(matches, "length")?,
+                ploidy: parse_arg(matches, "ploidy")?,
+                divergence: parse_arg(matches, "divergence")?,
+                sv_num: parse_arg(matches, "sv_num")?,
+                sv_length: parse_arg(matches, "sv_length")?,
+                repeat_num: parse_arg(matches, "repeat_num")?,
+                repeat_length: parse_arg(matches, "repeat_length")?,
+                repeat_divergence: parse_arg(matches, "repeat_divergence")?,
+            };
+            debug!("{:?}", config);
+            random_haplotypes(&config, &mut rng)
+        }
+    };
+    let read_type = match matches.value_of("read_type").unwrap() {
+        "CCS" => ReadType::CCS,
+        "ONT" => ReadType::ONT,
+        _ => ReadType::CLR,
+    };
+    let profile = ReadProfile::new(read_type);
+    debug!("{:?}", profile);
+    let coverage: f64 = parse_arg(matches, "coverage")?;
+    let reads = simulate_reads(&haplotypes, coverage, &profile, &mut rng);
+    debug!(
+        "Simulated {} reads from {} haplotypes",
+        reads.len(),
+        haplotypes.len()
+    );
+    let file = std::fs::File::create(matches.value_of("output").unwrap())?;
+    let mut wtr = bio_utils::fasta::Writer::new(BufWriter::new(file));
+    for read in reads.iter() {
+        wtr.write_record(&bio_utils::fasta::Record::from(read))?;
+    }
+    if let Some(path) = matches.value_of("haplotypes") {
+        let file = std::fs::File::create(path)?;
+        let mut wtr = bio_utils::fasta::Writer::new(BufWriter::new(file));
+        for (name, seq) in haplotypes.iter() {
+            wtr.write_record(&bio_utils::fasta::Record::with_data(name, &None, seq))?;
+        }
+    }
+    if let Some(path) = matches.value_of("truth") {
+        let wtr = std::fs::File::create(path).map(BufWriter::new)?;
+        write_truth(&reads, wtr)?;
+    }
+    Ok(())
+}
+
+/// Validate the dataset between stages if `--check` is given.
+fn check(dataset: &DataSet, when: &str) -> std::io::Result<()> {
+    let violations = dataset.validate();
+    for violation in violations.iter() {
+        eprintln!("{}", violation);
+    }
+    if violations.is_empty() {
+        debug!("The {} passed the check.", when);
+        Ok(())
+    } else {
+        let msg = format!("{} violations in the {}.", violations.len(), when);
+        Err(std::io::Error::new(std::io::ErrorKind::InvalidData, msg))
+    }
+}
+
+fn view(matches: &clap::ArgMatches, dataset: &DataSet) -> std::io::Result<()> {
+    debug!("Start View");
+    let cluster: Option<u64> = matches
+        .value_of("cluster")
+        .map(|cl| parse_value("cluster", cl))
+        .transpose()?;
+    if let Some(names) = matches.values_of("read") {
+        let names: Vec<_> = names.collect();
+        dataset.view(&names, cluster)?;
+    }
+    if let Some(units) = matches.values_of("unit") {
+        let units: Vec<u64> = units
+            .map(|u| parse_value("unit", u))
+            .collect::<std::io::Result<_>>()?;
+        dataset.view_unit(&units, cluster)?;
+    }
+    Ok(())
+}
+
+fn stats(matches: &clap::ArgMatches, dataset: DataSet) -> std::io::Result<DataSet> {
+    debug!("Start Stats step");
+    let wtr = std::io::BufWriter::new(std::fs::File::create(matches.value_of("file").unwrap())?);
+    let format = matches.value_of("stats_format").unwrap();
+    match format {
+        "json" => serde_json::ser::to_writer_pretty(wtr, &dataset.metrics())?,
+        "tsv" => dataset.metrics().write_tsv(wtr)?,
+        _ => dataset.stats(wtr)?,
+    }
+    let config = serde_json::json!({"file": matches.value_of("file"), "format": format});
+    Ok(record(dataset, "stats", config, 1, None))
+}
+
+fn evaluate(matches: &clap::ArgMatches, dataset: DataSet) -> std::io::Result<DataSet> {
+    debug!("Start Evaluation");
+    let truth = match matches.value_of("truth") {
+        Some(path) => std::fs::File::open(path)
+            .map(BufReader::new)
+            .and_then(haplotyper::evaluate::parse_truth)?,
+        None => haplotyper::evaluate::truth_from_reads(&dataset),
+    };
+    if truth.is_empty() {
+        let msg = "No read has the true haplotype.";
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, msg));
+    }
+    let (units, summary) = dataset.evaluate(&truth);
+    let output = matches.value_of("output").unwrap();
+    let wtr = std::fs::File::create(output).map(BufWriter::new)?;
+    haplotyper::evaluate::write_tsv(&units, wtr)?;
+    if let Some(path) = matches.value_of("json") {
+        let wtr = std::fs::File::create(path).map(BufWriter::new)?;
+        serde_json::ser::to_writer_pretty(wtr, &summary)?;
+    }
+    let config = serde_json::json!({"truth": matches.value_of("truth"), "output": output});
+    Ok(record(dataset, "evaluate", config, 1, None))
+}
+
+fn evaluate_assembly(matches: &clap::ArgMatches, dataset: DataSet) -> std::io::Result<DataSet> {
+    debug!("Start Assembly Evaluation");
+    use haplotyper::evaluate::assembly;
+    let threads: usize = matches
+        .value_of("threads")
+        .and_then(|num| num.parse().ok())
+        .unwrap();
+    let window: usize = matches
+        .value_of("window")
+        .and_then(|num| num.parse().ok())
+        .unwrap();
+    if window == 0 {
+        let msg = "--window should be positive.";
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, msg));
+    }
+    if let Err(why) = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+    {
+        debug!("{:?} If you run `pipeline` module, this is Harmless.", why);
+    }
+    let path = matches.value_of("contigs").unwrap();
+    let gfa = std::fs::read(path).map(|content| gfa::GFA::from_reader(content.as_slice()))?;
+    let contigs = haplotyper::typing::contigs_from_gfa(&gfa);
+    let haplotypes: Vec<_> = std::fs::File::open(matches.value_of("haplotypes").unwrap())
+        .map(BufReader::new)
+        .and_then(bio_utils::fasta::parse_into_vec_from)?
+        .into_iter()
+        .map(|record| (record.id().to_string(), record.seq().to_ascii_uppercase()))
+        .collect();
+    // The tiles of the contigs, recorded in the GFA by `jtk assemble`.
+    let graphs = assemble::graphs_from_gfa(&gfa);
+    let units: Vec<_> = graphs.into_iter().flat_map(|g| g.nodes).collect();
+    debug!(
+        "Evaluating {} contigs by {} haplotypes",
+        contigs.len(),
+        haplotypes.len()
+    );
+    let edlib = haplotyper::minimap2::EdlibAligner::new(15, 10, 0.1);
+    let minimap2 = haplotyper::minimap2::Minimap2::new(threads).preset("asm20");
+    let (aligner, aligner_config) = aligner_from_matches(matches, edlib, minimap2);
+    let config = assembly::AssemblyEvaluationConfig::new(window);
+    let (evaluations, summary) =
+        assembly::evaluate_assembly(&contigs, &haplotypes, &units, aligner.as_ref(), &config)?;
+    let output = matches.value_of("output").unwrap();
+    let wtr = std::fs::File::create(output).map(BufWriter::new)?;
+    assembly::write_tsv(&evaluations, wtr)?;
+    if let Some(path) = matches.value_of("json") {
+        let wtr = std::fs::File::create(path).map(BufWriter::new)?;
+        let json = serde_json::json!({"summary": summary, "contigs": evaluations});
+        serde_json::ser::to_writer_pretty(wtr, &json)?;
+    }
+    let config = serde_json::json!({
+        "contigs": path,
+        "evaluation": config,
+        "aligner": aligner_config,
+    });
+    Ok(record(dataset, "evaluate_assembly", config, threads, None))
+}
+
+fn report(matches: &clap::ArgMatches, dataset: DataSet) -> std::io::Result<DataSet> {
+    debug!("Start Report step");
+    use haplotyper::report;
+    let threads: usize = matches
+        .value_of("threads")
+        .and_then(|num| num.parse().ok())
+        .unwrap();
+    let bins: usize = matches
+        .value_of("bins")
+        .and_then(|num| num.parse().ok())
+        .unwrap();
+    let min_edge_count: usize = matches
+        .value_of("min_edge_count")
+        .and_then(|num| num.parse().ok())
+        .unwrap();
+    if let Err(why) = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+    {
+        debug!("{:?} If you run `pipeline` module, this is Harmless.", why);
+    }
+    let graphs = match matches.value_of("contigs") {
+        Some(path) => std::fs::read(path)
+            .map(|content| gfa::GFA::from_reader(content.as_slice()))
+            .map(|gfa| assemble::graphs_from_gfa(&gfa))?,
+        None => vec![],
+    };
+    let config = report::ReportConfig::new(bins, min_edge_count);
+    let data = dataset.report(&graphs, &config);
+    let output = matches.value_of("output").unwrap();
+    let wtr = std::fs::File::create(output).map(BufWriter::new)?;
+    report::write_html(&data, wtr)?;
+    let config = serde_json::json!({
+        "output": output,
+        "contigs": matches.value_of("contigs"),
+        "report": config,
+    });
+    Ok(record(dataset, "report", config, threads, None))
+}
+
+fn select_unit(matches: &clap::ArgMatches, dataset: DataSet) -> std::io::Result<DataSet> {
+    debug!("Start Selecting Units");
+    let chunk_len: usize = matches
+        .value_of("chunk_len")
+        .and_then(|e| e.parse().ok())
+        .expect("Chunk len");
+    let margin: usize = matches
+        .value_of("margin")
+        .and_then(|e| e.parse().ok())
+        .expect("Margin");
+    let skip_len: usize = matches
+        .value_of("skip_len")
         .and_then(|e| e.parse().ok())
         .expect("Skip Len");
     let take_num: usize = matches
@@ -723,7 +1870,9 @@ fn select_unit(matches: &clap::ArgMatches, dataset: DataSet) -> std::io::Result<
         CLR => UnitConfig::new_clr(cl, tn, skip_len, margin, thrds, filter, upper, lower),
         _ => UnitConfig::new_ont(cl, tn, skip_len, margin, thrds, filter, upper, lower),
     };
-    Ok(dataset.select_chunks(&config))
+    let dataset = dataset.select_chunks(&config)?;
+    let config = serde_json::to_value(&config)?;
+    Ok(record(dataset, "select_unit", config, thrds, None))
 }
 
 fn repeat_masking(matches: &clap::ArgMatches, dataset: DataSet) -> std::io::Result<DataSet> {
@@ -751,7 +1900,9 @@ fn repeat_masking(matches: &clap::ArgMatches, dataset: DataSet) -> std::io::Resu
         debug!("{:?} If you run `pipeline` module, this is Harmless.", why);
     }
     let config = haplotyper::RepeatMaskConfig::new(k, freq, min);
-    Ok(dataset.mask_repeat(&config))
+    let dataset = dataset.mask_repeat(&config);
+    let config = serde_json::to_value(&config)?;
+    Ok(record(dataset, "repeat_masking", config, threads, None))
 }
 
 fn encode(matches: &clap::ArgMatches, dataset: DataSet) -> std::io::Result<DataSet> {
@@ -766,7 +1917,94 @@ fn encode(matches: &clap::ArgMatches, dataset: DataSet) -> std::io::Result<DataS
     {
         debug!("{:?} If you run `pipeline` module, this is Harmless.", why);
     }
-    Ok(dataset.encode(threads))
+    if let Some(path) = matches.value_of("paf") {
+        use std::io::Read;
+        debug!("Encoding by the alignments in {}", path);
+        let mut paf = vec![];
+        std::fs::File::open(path)
+            .and_then(stream::decompress)?
+            .read_to_end(&mut paf)?;
+        let dataset = dataset.encode_by_paf(&paf);
+        let config = serde_json::json!({"paf": path});
+        return Ok(record(dataset, "encode", config, threads, None));
+    }
+    let edlib = haplotyper::minimap2::EdlibAligner::default();
+    let minimap2 = haplotyper::encode::default_minimap2(dataset.read_type, threads);
+    let (aligner, config) = aligner_from_matches(matches, edlib, minimap2);
+    let paf = haplotyper::encode::unit_alignment(&dataset, aligner.as_ref())?;
+    if let Some(path) = matches.value_of("dump_paf") {
+        debug!("Writing the alignments into {}", path);
+        std::fs::write(path, &paf)?;
+    }
+    let dataset = dataset.encode_by_paf(&paf);
+    Ok(record(dataset, "encode", config, threads, None))
+}
+
+/// The aligner selected by the arguments of `aligner_args`.
+/// `edlib` and `minimap2` are the defaults of the subcommand, and the options override them.
+fn aligner_from_matches(
+    matches: &clap::ArgMatches,
+    edlib: haplotyper::minimap2::EdlibAligner,
+    minimap2: haplotyper::minimap2::Minimap2,
+) -> (Box<dyn haplotyper::minimap2::Aligner>, serde_json::Value) {
+    if matches.value_of("aligner") == Some("edlib") {
+        let config = serde_json::json!({"edlib": edlib});
+        (Box::new(edlib), config)
+    } else {
+        let aligner = minimap2_from_matches(matches, minimap2);
+        let config = serde_json::json!({"minimap2": aligner});
+        (Box::new(aligner), config)
+    }
+}
+
+/// `minimap2` configured by the arguments of `minimap2_args`.
+fn minimap2_from_matches(
+    matches: &clap::ArgMatches,
+    minimap2: haplotyper::minimap2::Minimap2,
+) -> haplotyper::minimap2::Minimap2 {
+    let mut aligner = minimap2.cleanup_on_error(!matches.is_present("keep_tmp"));
+    if let Some(binary) = matches.value_of("minimap2") {
+        aligner = aligner.binary(binary);
+    }
+    if let Some(preset) = matches.value_of("preset") {
+        aligner = aligner.preset(preset);
+    }
+    if let Some(tmpdir) = matches.value_of("tmpdir") {
+        aligner = aligner.tmpdir(tmpdir);
+    }
+    aligner
+}
+
+fn hic(matches: &clap::ArgMatches, mut dataset: DataSet) -> std::io::Result<DataSet> {
+    debug!("Start Hi-C step");
+    let threads: usize = matches
+        .value_of("threads")
+        .and_then(|e| e.parse::<usize>().ok())
+        .unwrap();
+    let max_diff: f64 = matches
+        .value_of("max_diff")
+        .and_then(|e| e.parse().ok())
+        .unwrap();
+    if let Err(why) = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+    {
+        debug!("{:?} If you run `pipeline` module, this is Harmless.", why);
+    }
+    if let (Some(read1), Some(read2)) = (matches.value_of("read1"), matches.value_of("read2")) {
+        let mates1 = std::fs::File::open(read1)
+            .and_then(stream::decompress)
+            .and_then(haplotyper::parse_reads)?;
+        let mates2 = std::fs::File::open(read2)
+            .and_then(stream::decompress)
+            .and_then(haplotyper::parse_reads)?;
+        dataset.add_hic_pairs(&mates1, &mates2)?;
+    }
+    let aligner = minimap2_from_matches(matches, haplotyper::hic::default_minimap2(threads));
+    let config = HiCConfig::new(threads, max_diff).aligner(aligner);
+    dataset.compute_hic_edges(&config)?;
+    let config = serde_json::to_value(&config)?;
+    Ok(record(dataset, "hic", config, threads, None))
 }
 
 fn pick_components(matches: &clap::ArgMatches, dataset: DataSet) -> std::io::Result<DataSet> {
@@ -786,7 +2024,9 @@ fn pick_components(matches: &clap::ArgMatches, dataset: DataSet) -> std::io::Res
         .and_then(|num| num.parse().ok())
         .unwrap();
     let config = ComponentPickingConfig::new(component_num);
-    Ok(dataset.pick_top_n_component(&config))
+    let dataset = dataset.pick_top_n_component(&config);
+    let config = serde_json::to_value(&config)?;
+    Ok(record(dataset, "pick_components", config, threads, None))
 }
 
 // fn filter_unit(matches: &clap::ArgMatches, dataset: DataSet) -> std::io::Result<DataSet> {
@@ -832,8 +2072,11 @@ fn polish_unit(matches: &clap::ArgMatches, dataset: DataSet) -> std::io::Result<
     {
         debug!("{:?} If you run `pipeline` module, this is Harmless.", why);
     }
-    let config = PolishUnitConfig::new(dataset.read_type, consensus_size, iteration, 2309);
-    Ok(dataset.polish_unit(&config))
+    let seed = 2309;
+    let config = PolishUnitConfig::new(dataset.read_type, consensus_size, iteration, seed);
+    let dataset = dataset.polish_unit(&config);
+    let config = serde_json::to_value(&config)?;
+    Ok(record(dataset, "polish_unit", config, threads, Some(seed)))
 }
 fn multiplicity_estimation(
     matches: &clap::ArgMatches,
@@ -860,7 +2103,10 @@ fn multiplicity_estimation(
         debug!("{:?} If you run `pipeline` module, this is Harmless.", why);
     }
     let config = MultiplicityEstimationConfig::new(threads, max_cluster_size, seed, path);
-    Ok(dataset.estimate_multiplicity(&config))
+    let dataset = dataset.estimate_multiplicity(&config);
+    let config = serde_json::to_value(&config)?;
+    let stage = "multiplicity_estimation";
+    Ok(record(dataset, stage, config, threads, Some(seed)))
 }
 
 fn local_clustering(matches: &clap::ArgMatches, dataset: DataSet) -> std::io::Result<DataSet> {
@@ -895,7 +2141,9 @@ fn local_clustering(matches: &clap::ArgMatches, dataset: DataSet) -> std::io::Re
     }
     let config =
         ClusteringConfig::with_default(&dataset, cluster_num, length, limit, retry, retain);
-    Ok(dataset.local_clustering(&config))
+    let dataset = dataset.local_clustering(&config);
+    let config = serde_json::to_value(&config)?;
+    Ok(record(dataset, "local_clustering", config, threads, None))
 }
 
 fn global_clustering(matches: &clap::ArgMatches, dataset: DataSet) -> std::io::Result<DataSet> {
@@ -938,10 +2186,28 @@ fn global_clustering(matches: &clap::ArgMatches, dataset: DataSet) -> std::io::R
         mismat_score,
         gap_score,
     );
+    let stage = "global_clustering";
     if matches.is_present("graph") {
-        Ok(dataset.global_clustering_graph(&config))
+        let dataset = dataset.global_clustering_graph(&config);
+        let config = serde_json::json!({"graph": config});
+        Ok(record(dataset, stage, config, threads, None))
+    } else if matches.is_present("hic") {
+        let min_contacts: usize = matches
+            .value_of("min_contacts")
+            .and_then(|num| num.parse().ok())
+            .unwrap();
+        let min_fraction: f64 = matches
+            .value_of("min_fraction")
+            .and_then(|num| num.parse().ok())
+            .unwrap();
+        let config = haplotyper::HiCPhasingConfig::new(config, min_contacts, min_fraction);
+        let dataset = dataset.global_clustering_hic(&config);
+        let config = serde_json::to_value(&config)?;
+        Ok(record(dataset, stage, config, threads, None))
     } else {
-        Ok(dataset.global_clustering(&config))
+        let dataset = dataset.global_clustering(&config);
+        let config = serde_json::to_value(&config)?;
+        Ok(record(dataset, stage, config, threads, None))
     }
 }
 
@@ -969,7 +2235,14 @@ fn clustering_correction(matches: &clap::ArgMatches, dataset: DataSet) -> std::i
     {
         debug!("{:?} If you run `pipeline` module, this is Harmless.", why);
     }
-    Ok(dataset.correct_clustering_em(repeat_num, threshold, len_thr))
+    let dataset = dataset.correct_clustering_em(repeat_num, threshold, len_thr);
+    let config = serde_json::json!({
+        "repeat_num": repeat_num,
+        "coverage_threshold": threshold,
+        "length_threshold": len_thr,
+    });
+    let stage = "clustering_correction";
+    Ok(record(dataset, stage, config, threads, None))
 }
 
 fn assembly(matches: &clap::ArgMatches, mut dataset: DataSet) -> std::io::Result<DataSet> {
@@ -991,7 +2264,9 @@ fn assembly(matches: &clap::ArgMatches, mut dataset: DataSet) -> std::io::Result
     let skip_polish = matches.is_present("no_polish");
     let file = matches.value_of("output").unwrap();
     let mut file = std::fs::File::create(file).map(BufWriter::new)?;
-    let config = AssembleConfig::new(threads, window_size, !skip_polish);
+    let aligner = haplotyper::assemble::default_minimap2(threads);
+    let aligner = minimap2_from_matches(matches, aligner);
+    let config = AssembleConfig::new(threads, window_size, !skip_polish).aligner(aligner);
     if dataset.assignments.is_empty() {
         dataset.assignments = dataset
             .encoded_reads
@@ -999,28 +2274,388 @@ fn assembly(matches: &clap::ArgMatches, mut dataset: DataSet) -> std::io::Result
             .map(|r| Assignment::new(r.id, 0))
             .collect();
     }
-    let gfa = dataset.assemble_as_gfa(&config);
+    let gfa = match matches.value_of("bam") {
+        Some(path) => {
+            let (gfa, header, records) = dataset.assemble_with_alignments(&config)?;
+            haplotyper::sam::write_alignments(path, &header, &records)?;
+            gfa
+        }
+        None => dataset.assemble_as_gfa(&config),
+    };
     writeln!(&mut file, "{}", gfa)?;
-    Ok(dataset)
+    if let Some(dir) = matches.value_of("haplotypes") {
+        debug!("Writing haplotypes into {}", dir);
+        std::fs::create_dir_all(dir)?;
+        let haplotypes = dataset.split_into_haplotypes(&gfa);
+        for (i, contigs) in haplotypes.iter().enumerate() {
+            let path = std::path::Path::new(dir).join(format!("hap{}.fa", i + 1));
+            let mut wtr = bio_utils::fasta::Writer::new(std::fs::File::create(path)?);
+            for contig in contigs.iter() {
+                wtr.write_record(contig)?;
+            }
+        }
+    }
+    let config = serde_json::to_value(&config)?;
+    Ok(record(dataset, "assemble", config, threads, None))
+}
+
+/// Run the subcommand `name`, which takes a DataSet and returns a new one.
+fn run_stage(name: &str, sub_m: &clap::ArgMatches, ds: DataSet) -> std::io::Result<DataSet> {
+    match name {
+        "extract" => extract(sub_m, ds),
+        "stats" => stats(sub_m, ds),
+        "evaluate" => evaluate(sub_m, ds),
+        "evaluate_assembly" => evaluate_assembly(sub_m, ds),
+        "report" => report(sub_m, ds),
+        "select_unit" => select_unit(sub_m, ds),
+        "polish_unit" => polish_unit(sub_m, ds),
+        "encode" => encode(sub_m, ds),
+        "hic" => hic(sub_m, ds),
+        "local_clustering" => local_clustering(sub_m, ds),
+        "multiplicity_estimation" => multiplicity_estimation(sub_m, ds),
+        "global_clustering" => global_clustering(sub_m, ds),
+        "clustering_correction" => clustering_correction(sub_m, ds),
+        "assemble" => assembly(sub_m, ds),
+        "repeat_masking" => repeat_masking(sub_m, ds),
+        "pick_components" => pick_components(sub_m, ds),
+        "convert" => Ok(record(ds, "convert", serde_json::json!({}), 1, None)),
+        "merge" => merge(sub_m, ds),
+        "subset" => subset(sub_m, ds),
+        _ => {
+            let msg = format!("{} can not be a stage of the pipeline.", name);
+            Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, msg))
+        }
+    }
+}
+
+fn pipeline(matches: &clap::ArgMatches) -> std::io::Result<DataSet> {
+    use std::hash::{Hash, Hasher};
+    use std::io::{Error, ErrorKind};
+    let invalid = |msg: String| Error::new(ErrorKind::InvalidInput, msg);
+    let path = matches.value_of("config").unwrap();
+    debug!("Start pipeline from {}", path);
+    let config: toml::Value = std::fs::read_to_string(path)?
+        .parse()
+        .map_err(|why| invalid(format!("{}:{}", path, why)))?;
+    let stages = match config.get("stage").and_then(|stages| stages.as_array()) {
+        Some(stages) => stages,
+        None => return Err(invalid(format!("{} has no [[stage]].", path))),
+    };
+    // Parse all the stages in advance, so that typos are reported before running anything.
+    let stages: Vec<_> = stages
+        .iter()
+        .enumerate()
+        .map(|(idx, stage)| {
+            let stage = stage.as_table();
+            let name = stage.and_then(|stage| stage.get("name")?.as_str());
+            let (stage, name) = match (stage, name) {
+                (Some(stage), Some(name)) => (stage, name),
+                _ => return Err(invalid(format!("The {}-th stage has no name.", idx))),
+            };
+            let app = subcommands().into_iter().find(|app| app.get_name() == name);
+            let app = app.ok_or_else(|| invalid(format!("Unknown stage:{}", name)))?;
+            let args = stage_args(name, stage)?;
+            let sub_m = app
+                .get_matches_from_safe(&args)
+                .map_err(|why| invalid(format!("The {}-th stage({}):{}", idx, name, why)))?;
+            let dump = stage.get("dump").and_then(|x| x.as_str());
+            Ok((name, sub_m, dump, args))
+        })
+        .collect::<std::io::Result<_>>()?;
+    let mut dataset = None;
+    // The key of a stage is the hash of its arguments, its input files, and the key of the previous stage.
+    // The first key comes from the version of jtk, and the input DataSet if any.
+    let mut key = Fnv::new();
+    env!("CARGO_PKG_VERSION").hash(&mut key);
+    if stages.first().map(|stage| stage.0) != Some("entry") {
+        let ds = get_input_file()?;
+        if matches.is_present("workdir") {
+            ds.to_binary(&mut key)?;
+        }
+        dataset = Some(ds);
+    }
+    let use_checkpoints = matches.is_present("workdir");
+    let mut keys = vec![];
+    for (name, sub_m, _, args) in stages.iter() {
+        let mut hasher = Fnv::new();
+        key.finish().hash(&mut hasher);
+        args.hash(&mut hasher);
+        // Reading the input files takes time, so they are hashed only if the keys are used.
+        let inputs = INPUT_FILES.iter().filter(|(stage, _)| stage == name);
+        for (_, option) in inputs.filter(|_| use_checkpoints) {
+            for path in sub_m.values_of(option).into_iter().flatten() {
+                let mut input = std::fs::File::open(path)?;
+                std::io::copy(&mut input, &mut hasher)?;
+            }
+        }
+        keys.push(hasher.finish());
+        key = hasher;
+    }
+    let checkpoints = matches.value_of("workdir").map(Checkpoints::new);
+    let mut start = 0;
+    if let Some(checkpoints) = checkpoints.as_ref() {
+        std::fs::create_dir_all(&checkpoints.dir)?;
+        while start < stages.len() && checkpoints.is_valid(start, stages[start].0, keys[start]) {
+            start += 1;
+        }
+        if start > 0 {
+            let name = stages[start - 1].0;
+            info!(
+                "Resume from the checkpoint of the {}-th stage({}).",
+                start - 1,
+                name
+            );
+            dataset = Some(checkpoints.load(start - 1, name)?);
+        }
+    }
+    for (idx, (name, sub_m, dump, _)) in stages.into_iter().enumerate().skip(start) {
+        debug!("Stage:{}", name);
+        let run = || match (name, dataset.take()) {
+            ("entry", _) => entry(&sub_m),
+            (_, Some(ds)) => run_stage(name, &sub_m, ds),
+            (_, None) => run_stage(name, &sub_m, get_input_file()?),
+        };
+        // The global thread pool can be built only once, so each stage runs in its own pool.
+        let ds = match sub_m.value_of("threads") {
+            Some(_) => {
+                let threads: usize = parse_arg(&sub_m, "threads")?;
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()
+                    .map_err(Error::other)?
+                    .install(run)?
+            }
+            None => run()?,
+        };
+        if matches.is_present("check") {
+            check(&ds, &format!("output of {}", name))?;
+        }
+        if let Some(dump) = dump {
+            debug!("Writing the DataSet into {}", dump);
+            dump_file(&ds, dump)?;
+        }
+        if let Some(checkpoints) = checkpoints.as_ref() {
+            checkpoints.save(idx, name, keys[idx], &ds)?;
+        }
+        dataset = Some(ds);
+    }
+    match dataset {
+        Some(ds) => Ok(ds),
+        None => get_input_file(),
+    }
+}
+
+/// The (stage, option) pairs whose values are input files. The contents of the files are hashed into
+/// the key of the stage, as well as the arguments.
+const INPUT_FILES: &[(&str, &str)] = &[
+    ("entry", "input"),
+    ("evaluate", "truth"),
+    ("report", "contigs"),
+    ("evaluate_assembly", "contigs"),
+    ("evaluate_assembly", "haplotypes"),
+    ("encode", "paf"),
+    ("hic", "read1"),
+    ("hic", "read2"),
+    ("merge", "input"),
+    ("subset", "names"),
+];
+
+/// Checkpoints of the pipeline. The DataSet after the i-th stage is saved with the key of the stage,
+/// which is valid only if the key matches.
+struct Checkpoints {
+    dir: std::path::PathBuf,
+}
+
+impl Checkpoints {
+    fn new(dir: &str) -> Self {
+        Self { dir: dir.into() }
+    }
+    fn paths(&self, idx: usize, name: &str) -> (std::path::PathBuf, std::path::PathBuf) {
+        let prefix = format!("{:02}_{}", idx, name);
+        let dataset = self.dir.join(format!("{}.bin.zst", prefix));
+        let key = self.dir.join(format!("{}.key", prefix));
+        (dataset, key)
+    }
+    fn is_valid(&self, idx: usize, name: &str, key: u64) -> bool {
+        let (dataset, key_file) = self.paths(idx, name);
+        let saved = std::fs::read_to_string(key_file).unwrap_or_default();
+        dataset.exists() && saved.trim() == format!("{:016x}", key)
+    }
+    fn load(&self, idx: usize, name: &str) -> std::io::Result<DataSet> {
+        let (dataset, _) = self.paths(idx, name);
+        std::fs::File::open(dataset).and_then(stream::read_dataset)
+    }
+    // The key is written after the DataSet, so that a crash while saving leaves no valid checkpoint.
+    fn save(&self, idx: usize, name: &str, key: u64, ds: &DataSet) -> std::io::Result<()> {
+        let (dataset, key_file) = self.paths(idx, name);
+        debug!("Saving a checkpoint to {:?}", dataset);
+        if key_file.exists() {
+            std::fs::remove_file(&key_file)?;
+        }
+        let wtr = std::fs::File::create(dataset).map(BufWriter::new)?;
+        write_compressed(wtr, Compression::Zstd, |wtr| ds.to_binary(wtr))?;
+        std::fs::write(key_file, format!("{:016x}\n", key))
+    }
+}
+
+/// FNV-1a hash. Unlike the default hasher of std, it is stable across builds.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl std::hash::Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x100_0000_01b3);
+        }
+    }
+}
+
+impl Write for Fnv {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        std::hash::Hasher::write(self, buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Convert a [[stage]] table into the arguments of the subcommand.
+/// Keys other than `name` and `dump` are options: `true` is a flag, and an array is a repeated option.
+fn stage_args(name: &str, stage: &toml::value::Table) -> std::io::Result<Vec<String>> {
+    let mut args = vec![name.to_string()];
+    for (key, value) in stage.iter() {
+        if key == "name" || key == "dump" {
+            continue;
+        }
+        let option = match key.len() {
+            1 => format!("-{}", key),
+            _ => format!("--{}", key),
+        };
+        let values = match value {
+            toml::Value::Array(values) => values.iter().collect(),
+            _ => vec![value],
+        };
+        for value in values {
+            let value = match value {
+                toml::Value::Boolean(true) => None,
+                toml::Value::Boolean(false) => continue,
+                toml::Value::String(value) => Some(value.clone()),
+                toml::Value::Integer(value) => Some(value.to_string()),
+                toml::Value::Float(value) => Some(value.to_string()),
+                _ => {
+                    let msg = format!("Invalid value of {} in {}:{}", key, name, value);
+                    return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, msg));
+                }
+            };
+            args.push(option.clone());
+            args.extend(value);
+        }
+    }
+    Ok(args)
+}
+
+/// Write the DataSet in JSON, compressed if the path ends with `.gz` or `.zst`.
+fn dump_file(dataset: &DataSet, path: &str) -> std::io::Result<()> {
+    let compression = if path.ends_with(".gz") {
+        Compression::Gzip
+    } else if path.ends_with(".zst") {
+        Compression::Zstd
+    } else {
+        Compression::None
+    };
+    let wtr = std::fs::File::create(path).map(BufWriter::new)?;
+    write_compressed(wtr, compression, |wtr| {
+        serde_json::ser::to_writer(wtr, dataset).map_err(std::io::Error::from)
+    })
+}
+
+/// Compression of the output stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    fn from_matches(matches: &clap::ArgMatches) -> Self {
+        match matches.value_of("compress") {
+            Some("gzip") => Compression::Gzip,
+            Some("zstd") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+}
+
+/// Serialization format of the output DataSet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Json,
+    Binary,
+}
+
+impl Format {
+    fn from_matches(matches: &clap::ArgMatches) -> Self {
+        match matches.value_of("format") {
+            Some("binary") => Format::Binary,
+            _ => Format::Json,
+        }
+    }
+}
+
+/// Write to `wtr` by `write`, compressing the stream as specified.
+fn write_compressed<W, F>(wtr: W, compression: Compression, write: F) -> std::io::Result<()>
+where
+    W: Write,
+    F: FnOnce(&mut dyn Write) -> std::io::Result<()>,
+{
+    match compression {
+        Compression::None => {
+            let mut wtr = wtr;
+            write(&mut wtr)?;
+            wtr.flush()
+        }
+        Compression::Gzip => {
+            let mut wtr = flate2::write::GzEncoder::new(wtr, flate2::Compression::default());
+            write(&mut wtr)?;
+            wtr.finish()?.flush()
+        }
+        Compression::Zstd => {
+            let mut wtr = zstd::stream::write::Encoder::new(wtr, 0)?;
+            write(&mut wtr)?;
+            wtr.finish()?.flush()
+        }
+    }
 }
 
 fn get_input_file() -> std::io::Result<DataSet> {
     let stdin = std::io::stdin();
-    let reader = BufReader::new(stdin.lock());
-    match serde_json::de::from_reader(reader) {
-        Err(why) => {
-            eprintln!("{:?}", why);
-            eprintln!("Invalid Input from STDIN.");
-            Err(std::io::Error::from(std::io::ErrorKind::Other))
-        }
-        Ok(res) => Ok(res),
+    let dataset = stream::read_dataset(stdin.lock());
+    if let Err(why) = dataset.as_ref() {
+        eprintln!("{}", why);
+        eprintln!("Invalid Input from STDIN.");
     }
+    dataset
 }
 
-fn flush_file(dataset: &DataSet) -> std::io::Result<()> {
+fn flush_file(dataset: &DataSet, format: Format, compression: Compression) -> std::io::Result<()> {
     let stdout = std::io::stdout();
-    let mut wtr = std::io::BufWriter::new(stdout.lock());
-    match serde_json::ser::to_writer(&mut wtr, dataset) {
+    let wtr = std::io::BufWriter::new(stdout.lock());
+    let result = write_compressed(wtr, compression, |wtr| match format {
+        Format::Json => serde_json::ser::to_writer(wtr, dataset).map_err(std::io::Error::from),
+        Format::Binary => dataset.to_binary(wtr),
+    });
+    match result {
         Err(why) => {
             eprintln!("{:?}", why);
             eprintln!("Invalid output to the STDOUT.");
@@ -1036,19 +2671,32 @@ fn main() -> std::io::Result<()> {
         .author("Bansho Masutani")
         .about("HLA toolchain")
         .setting(clap::AppSettings::ArgRequiredElseHelp)
-        .subcommand(subcommand_entry())
-        .subcommand(subcommand_extract())
-        .subcommand(subcommand_stats())
-        .subcommand(subcommand_select_unit())
-        .subcommand(subcommand_polish_unit())
-        .subcommand(subcommand_encode())
-        .subcommand(subcommand_multiplicity_estimation())
-        .subcommand(subcommand_local_clustering())
-        .subcommand(subcommand_global_clustering())
-        .subcommand(subcommand_clustering_correction())
-        .subcommand(subcommand_assembly())
-        .subcommand(subcommand_pick_components())
-        .subcommand(subcommand_repeatmasking())
+        .arg(
+            Arg::with_name("compress")
+                .long("compress")
+                .global(true)
+                .takes_value(true)
+                .value_name("COMPRESSION")
+                .possible_values(&["none", "gzip", "zstd"])
+                .help("Compression of the output. The input is decompressed automatically."),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .global(true)
+                .takes_value(true)
+                .value_name("FORMAT")
+                .possible_values(&["json", "binary"])
+                .help("Format of the output DataSet[default: json]. The input format is detected automatically."),
+        )
+        .arg(
+            Arg::with_name("check")
+                .long("check")
+                .global(true)
+                .help("Validate the input and the output DataSet. Stop if there is any violation."),
+        )
+        .subcommands(subcommands())
+        .subcommand(subcommand_pipeline())
         .get_matches();
     if let Some(sub_m) = matches.subcommand().1 {
         let level = match sub_m.occurrences_of("verbose") {
@@ -1059,27 +2707,49 @@ fn main() -> std::io::Result<()> {
         };
         env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(level)).init();
     }
+    let compression = matches
+        .subcommand()
+        .1
+        .map(Compression::from_matches)
+        .unwrap_or(Compression::None);
+    let format = matches
+        .subcommand()
+        .1
+        .map(Format::from_matches)
+        .unwrap_or(Format::Json);
+    let to_check = matches
+        .subcommand()
+        .1
+        .map(|sub_m| sub_m.is_present("check"))
+        .unwrap_or(false);
+    let flush = |ds: DataSet| {
+        if to_check {
+            check(&ds, "output")?;
+        }
+        flush_file(&ds, format, compression)
+    };
     if let ("entry", Some(sub_m)) = matches.subcommand() {
-        return entry(sub_m).and_then(|x| flush_file(&x));
-    }//  else if let ("pipeline", Some(sub_m)) = matches.subcommand() {
-    //     return pipeline(sub_m).and_then(|x| flush_file(&x));
-    // }
-    ;
+        return entry(sub_m).and_then(flush);
+    } else if let ("pipeline", Some(sub_m)) = matches.subcommand() {
+        return pipeline(sub_m).and_then(flush);
+    } else if let ("typing", Some(sub_m)) = matches.subcommand() {
+        return typing(sub_m);
+    } else if let ("simulate", Some(sub_m)) = matches.subcommand() {
+        return simulate(sub_m);
+    }
     let ds = get_input_file()?;
+    if let ("validate", Some(_)) = matches.subcommand() {
+        return validate(&ds);
+    }
+    if to_check {
+        check(&ds, "input")?;
+    }
+    if let ("view", Some(sub_m)) = matches.subcommand() {
+        return view(sub_m, &ds);
+    }
     let result = match matches.subcommand() {
-        ("extract", Some(sub_m)) => extract(sub_m, ds),
-        ("stats", Some(sub_m)) => stats(sub_m, ds),
-        ("select_unit", Some(sub_m)) => select_unit(sub_m, ds),
-        ("polish_unit", Some(sub_m)) => polish_unit(sub_m, ds),
-        ("encode", Some(sub_m)) => encode(sub_m, ds),
-        ("local_clustering", Some(sub_m)) => local_clustering(sub_m, ds),
-        ("multiplicity_estimation", Some(sub_m)) => multiplicity_estimation(sub_m, ds),
-        ("global_clustering", Some(sub_m)) => global_clustering(sub_m, ds),
-        ("clustering_correction", Some(sub_m)) => clustering_correction(sub_m, ds),
-        ("assemble", Some(sub_m)) => assembly(sub_m, ds),
-        ("repeat_masking", Some(sub_m)) => repeat_masking(sub_m, ds),
-        ("pick_components", Some(sub_m)) => pick_components(sub_m, ds),
+        (name, Some(sub_m)) => run_stage(name, sub_m, ds),
         _ => unreachable!(),
     };
-    result.and_then(|x| flush_file(&x))
+    result.and_then(flush)
 }
@@ -9,7 +9,7 @@ use std::io::BufReader;
 fn main() -> std::io::Result<()> {
     let args: Vec<_> = std::env::args().collect();
     let rdr = std::fs::File::open(&args[1]).map(BufReader::new)?;
-    let ds: DataSet = serde_json::de::from_reader(rdr).unwrap();
+    let ds = DataSet::from_json(rdr)?;
     let output_dir = std::path::PathBuf::from(&args[2]);
     std::fs::create_dir_all(&output_dir)?;
     let id2cluster: HashMap<_, usize> = ds
@@ -0,0 +1,619 @@
+//! Python bindings of the DataSet and the core stages of the pipeline.
+//! Elements of a DataSet, such as reads and nodes, are exposed as views into the dataset on the Rust side.
+//! No element is converted into a Python object until its field is accessed.
+//! Sequences and qualities are [Buffer]s supporting the buffer protocol, so `memoryview(read.seq)`
+//! or `numpy.frombuffer(read.seq, dtype = numpy.uint8)` does not copy them, while `bytes(read.seq)` does.
+//! ```python
+//! import jtk_py
+//! ds = jtk_py.load("dataset.bin.zst")
+//! ds.encode(threads = 24)
+//! for read in ds.encoded_reads:
+//!     print(read.id, [(node.unit, node.cluster) for node in read.nodes])
+//! ```
+use definitions::{DataSet, Provenance};
+use haplotyper::{Assemble, AssembleConfig, ClusteringConfig, Encode, LocalClustering};
+use pyo3::exceptions::{PyBufferError, PyIndexError, PyRuntimeError};
+use pyo3::prelude::*;
+use pyo3::pyclass::CompareOp;
+use pyo3::types::{PyBytes, PyDict};
+use serde_json::Value;
+use std::os::raw::{c_int, c_void};
+use std::sync::Arc;
+
+/// Load a DataSet from `path`. JSON and binary formats, optionally gzip or zstd compressed, are accepted.
+#[pyfunction]
+fn load(py: Python, path: &str) -> PyResult<PyDataSet> {
+    let read = || std::fs::File::open(path).and_then(haplotyper::stream::read_dataset);
+    let dataset = py.allow_threads(read)?;
+    Ok(PyDataSet::from(dataset))
+}
+
+/// Return the index into a sequence of length `len`, allowing negative indices as Python does.
+fn index(idx: isize, len: usize) -> PyResult<usize> {
+    let idx = if idx < 0 { idx + len as isize } else { idx };
+    if 0 <= idx && (idx as usize) < len {
+        Ok(idx as usize)
+    } else {
+        Err(PyIndexError::new_err(format!("index {} out of range", idx)))
+    }
+}
+
+/// Run `stage` on a thread pool with `threads` threads, without holding the GIL.
+fn run<T: Send, F: FnOnce() -> T + Send>(py: Python, threads: usize, stage: F) -> PyResult<T> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+    Ok(py.allow_threads(|| pool.install(stage)))
+}
+
+#[pyclass(name = "DataSet")]
+pub struct PyDataSet {
+    data: Arc<DataSet>,
+}
+
+impl From<DataSet> for PyDataSet {
+    fn from(dataset: DataSet) -> Self {
+        Self {
+            data: Arc::new(dataset),
+        }
+    }
+}
+
+impl PyDataSet {
+    // Take the dataset out to pass it to a stage. The dataset is copied only if
+    // some views still refer to it.
+    fn take(&mut self) -> DataSet {
+        let empty = Arc::new(DataSet::with_minimum_data("", vec![], "CLR"));
+        let data = std::mem::replace(&mut self.data, empty);
+        Arc::try_unwrap(data).unwrap_or_else(|data| (*data).clone())
+    }
+    fn put(&mut self, mut dataset: DataSet, stage: &str, config: Value, threads: usize) {
+        let version = env!("CARGO_PKG_VERSION");
+        let provenance = Provenance::new(stage, version, &config, threads, None);
+        dataset.provenance.push(provenance);
+        self.data = Arc::new(dataset);
+    }
+}
+
+#[pymethods]
+impl PyDataSet {
+    /// Write this dataset into `path`, in the binary format if `binary` is true, or JSON otherwise.
+    #[pyo3(signature = (path, binary = true))]
+    fn save(&self, py: Python, path: &str, binary: bool) -> PyResult<()> {
+        py.allow_threads(|| {
+            let mut wtr = std::io::BufWriter::new(std::fs::File::create(path)?);
+            if binary {
+                self.data.to_binary(&mut wtr)
+            } else {
+                serde_json::to_writer(&mut wtr, self.data.as_ref()).map_err(std::io::Error::from)
+            }
+        })?;
+        Ok(())
+    }
+    #[getter]
+    fn input_file(&self) -> &str {
+        &self.data.input_file
+    }
+    #[getter]
+    fn coverage(&self) -> Option<f64> {
+        self.data.coverage
+    }
+    #[getter]
+    fn read_type(&self) -> String {
+        format!("{:?}", self.data.read_type)
+    }
+    #[getter]
+    fn raw_reads(&self) -> RawReads {
+        RawReads {
+            data: self.data.clone(),
+        }
+    }
+    #[getter]
+    fn selected_chunks(&self) -> Units {
+        Units {
+            data: self.data.clone(),
+        }
+    }
+    #[getter]
+    fn encoded_reads(&self) -> EncodedReads {
+        EncodedReads {
+            data: self.data.clone(),
+        }
+    }
+    /// The assignments as a list of (read ID, cluster).
+    #[getter]
+    fn assignments(&self) -> Vec<(u64, usize)> {
+        self.data
+            .assignments
+            .iter()
+            .map(|a| (a.id, a.cluster))
+            .collect()
+    }
+    /// The provenance as a list of (stage, version, config, timestamp, threads, seed), where config is in JSON.
+    #[getter]
+    fn provenance(&self) -> Vec<(String, String, String, u64, usize, Option<u64>)> {
+        self.data
+            .provenance
+            .iter()
+            .map(|p| {
+                let (stage, version, config) = (&p.stage, &p.version, &p.config);
+                let (stage, version, config) = (stage.clone(), version.clone(), config.clone());
+                (stage, version, config, p.timestamp, p.threads, p.seed)
+            })
+            .collect()
+    }
+    /// Encode the raw reads by the selected units, as `jtk encode`.
+    #[pyo3(signature = (threads = 1))]
+    fn encode(&mut self, py: Python, threads: usize) -> PyResult<()> {
+        use haplotyper::encode::{default_minimap2, unit_alignment};
+        let aligner = default_minimap2(self.data.read_type, threads);
+        let config = serde_json::json!({"minimap2": aligner});
+        // Align first, so that the dataset is kept intact if the aligner fails.
+        let paf = run(py, threads, || unit_alignment(&self.data, &aligner))??;
+        let dataset = self.take();
+        let dataset = run(py, threads, || dataset.encode_by_paf(&paf))?;
+        self.put(dataset, "encode", config, threads);
+        Ok(())
+    }
+    /// Cluster the nodes on each unit, as `jtk local_clustering`.
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (threads = 1, cluster_num = 2, subchunk_len = 100, limit = 600, retry = 2, retain = false))]
+    fn local_clustering(
+        &mut self,
+        py: Python,
+        threads: usize,
+        cluster_num: usize,
+        subchunk_len: usize,
+        limit: u64,
+        retry: u64,
+        retain: bool,
+    ) -> PyResult<()> {
+        let dataset = self.take();
+        let retry = if retain { 1 } else { retry };
+        let config = ClusteringConfig::with_default(
+            &dataset,
+            cluster_num,
+            subchunk_len,
+            limit,
+            retry,
+            retain,
+        );
+        let summary = serde_json::json!(config);
+        let dataset = run(py, threads, || dataset.local_clustering(&config))?;
+        self.put(dataset, "local_clustering", summary, threads);
+        Ok(())
+    }
+    /// Assemble the reads in each cluster into graphs, as `jtk assemble`.
+    /// Each graph is a dictionary with `nodes` and `edges`, where the segments of a node
+    /// are (unit, cluster, strand) tuples. If there is no assignment, all the reads are assembled together.
+    #[pyo3(signature = (threads = 1, window_size = 2000, polish = true))]
+    fn assemble_as_graph(
+        &self,
+        py: Python,
+        threads: usize,
+        window_size: usize,
+        polish: bool,
+    ) -> PyResult<Vec<PyObject>> {
+        let config = AssembleConfig::new(threads, window_size, polish);
+        let data = self.data.clone();
+        let graphs = run(py, threads, || {
+            if data.assignments.is_empty() {
+                let mut dataset = (*data).clone();
+                dataset.assignments = dataset
+                    .encoded_reads
+                    .iter()
+                    .map(|r| definitions::Assignment::new(r.id, 0))
+                    .collect();
+                dataset.assemble_as_graph(&config)
+            } else {
+                data.assemble_as_graph(&config)
+            }
+        })?;
+        graphs.iter().map(|g| graph_to_dict(py, g)).collect()
+    }
+    fn __repr__(&self) -> String {
+        format!(
+            "DataSet(input_file={:?}, raw_reads={}, selected_chunks={}, encoded_reads={})",
+            self.data.input_file,
+            self.data.raw_reads.len(),
+            self.data.selected_chunks.len(),
+            self.data.encoded_reads.len()
+        )
+    }
+}
+
+fn graph_to_dict(py: Python, graph: &haplotyper::assemble::Graph) -> PyResult<PyObject> {
+    let nodes = graph.nodes.iter().map(|node| -> PyResult<_> {
+        let dict = PyDict::new(py);
+        dict.set_item("id", &node.id)?;
+        let segments = node.segments.iter();
+        let segments: Vec<_> = segments.map(|t| (t.unit, t.cluster, t.strand)).collect();
+        dict.set_item("segments", segments)?;
+        Ok(dict.to_object(py))
+    });
+    let nodes = nodes.collect::<PyResult<Vec<_>>>()?;
+    let edges = graph.edges.iter().map(|edge| -> PyResult<_> {
+        let dict = PyDict::new(py);
+        dict.set_item("from", &edge.from)?;
+        dict.set_item("from_tail", edge.from_tail)?;
+        dict.set_item("to", &edge.to)?;
+        dict.set_item("to_tail", edge.to_tail)?;
+        Ok(dict.to_object(py))
+    });
+    let edges = edges.collect::<PyResult<Vec<_>>>()?;
+    let dict = PyDict::new(py);
+    dict.set_item("nodes", nodes)?;
+    dict.set_item("edges", edges)?;
+    Ok(dict.to_object(py))
+}
+
+/// The field of the DataSet a [Buffer] refers to, by the indices of the read and the node.
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    RawSeq(usize),
+    RawQual(usize),
+    UnitSeq(usize),
+    LeadingGap(usize),
+    TrailingGap(usize),
+    NodeSeq(usize, usize),
+    NodeQual(usize, usize),
+}
+
+/// A read-only byte sequence in the DataSet, exposed by the buffer protocol without copying.
+#[pyclass]
+pub struct Buffer {
+    data: Arc<DataSet>,
+    field: Field,
+}
+
+impl Buffer {
+    fn new(data: &Arc<DataSet>, field: Field) -> Self {
+        let data = data.clone();
+        Self { data, field }
+    }
+    fn as_slice(&self) -> &[u8] {
+        let ds = self.data.as_ref();
+        match self.field {
+            Field::RawSeq(i) => ds.raw_reads[i].seq(),
+            Field::RawQual(i) => ds.raw_reads[i].qual().unwrap_or(&[]),
+            Field::UnitSeq(i) => ds.selected_chunks[i].seq(),
+            Field::LeadingGap(i) => &ds.encoded_reads[i].leading_gap,
+            Field::TrailingGap(i) => &ds.encoded_reads[i].trailing_gap,
+            Field::NodeSeq(i, j) => ds.encoded_reads[i].nodes[j].seq(),
+            Field::NodeQual(i, j) => ds.encoded_reads[i].nodes[j].qual().unwrap_or(&[]),
+        }
+    }
+}
+
+#[pymethods]
+impl Buffer {
+    fn __len__(&self) -> usize {
+        self.as_slice().len()
+    }
+    /// Copy the sequence into `bytes`.
+    fn __bytes__<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, self.as_slice())
+    }
+    fn __richcmp__(&self, other: &PyAny, op: CompareOp, py: Python) -> PyObject {
+        let other = match other.extract::<PyRef<Buffer>>() {
+            Ok(other) => Some(other.as_slice() == self.as_slice()),
+            Err(_) => other.extract::<&[u8]>().ok().map(|x| x == self.as_slice()),
+        };
+        match (op, other) {
+            (CompareOp::Eq, Some(eq)) => eq.into_py(py),
+            (CompareOp::Ne, Some(eq)) => (!eq).into_py(py),
+            _ => py.NotImplemented(),
+        }
+    }
+    fn __repr__(&self) -> String {
+        format!("Buffer(len={})", self.as_slice().len())
+    }
+    unsafe fn __getbuffer__(
+        slf: PyRef<'_, Self>,
+        view: *mut pyo3::ffi::Py_buffer,
+        flags: c_int,
+    ) -> PyResult<()> {
+        if view.is_null() {
+            return Err(PyBufferError::new_err("View is null"));
+        }
+        // The view holds a reference to this buffer, thus to the DataSet, until it is released.
+        let bytes = slf.as_slice();
+        let (ptr, len) = (bytes.as_ptr() as *mut c_void, bytes.len() as isize);
+        match pyo3::ffi::PyBuffer_FillInfo(view, slf.as_ptr(), ptr, len, 1, flags) {
+            -1 => Err(PyErr::fetch(slf.py())),
+            _ => Ok(()),
+        }
+    }
+    unsafe fn __releasebuffer__(&self, _view: *mut pyo3::ffi::Py_buffer) {}
+}
+
+#[pyclass]
+pub struct RawReads {
+    data: Arc<DataSet>,
+}
+
+#[pymethods]
+impl RawReads {
+    fn __len__(&self) -> usize {
+        self.data.raw_reads.len()
+    }
+    fn __getitem__(&self, idx: isize) -> PyResult<RawRead> {
+        let index = index(idx, self.data.raw_reads.len())?;
+        let data = self.data.clone();
+        Ok(RawRead { data, index })
+    }
+}
+
+#[pyclass]
+pub struct RawRead {
+    data: Arc<DataSet>,
+    index: usize,
+}
+
+impl RawRead {
+    fn read(&self) -> &definitions::RawRead {
+        &self.data.raw_reads[self.index]
+    }
+}
+
+#[pymethods]
+impl RawRead {
+    #[getter]
+    fn id(&self) -> u64 {
+        self.read().id
+    }
+    #[getter]
+    fn name(&self) -> &str {
+        &self.read().name
+    }
+    #[getter]
+    fn desc(&self) -> &str {
+        &self.read().desc
+    }
+    #[getter]
+    fn seq(&self) -> Buffer {
+        Buffer::new(&self.data, Field::RawSeq(self.index))
+    }
+    #[getter]
+    fn qual(&self) -> Option<Buffer> {
+        let qual = self.read().qual.is_some();
+        qual.then(|| Buffer::new(&self.data, Field::RawQual(self.index)))
+    }
+}
+
+#[pyclass]
+pub struct Units {
+    data: Arc<DataSet>,
+}
+
+#[pymethods]
+impl Units {
+    fn __len__(&self) -> usize {
+        self.data.selected_chunks.len()
+    }
+    fn __getitem__(&self, idx: isize) -> PyResult<Unit> {
+        let index = index(idx, self.data.selected_chunks.len())?;
+        let data = self.data.clone();
+        Ok(Unit { data, index })
+    }
+}
+
+#[pyclass]
+pub struct Unit {
+    data: Arc<DataSet>,
+    index: usize,
+}
+
+impl Unit {
+    fn unit(&self) -> &definitions::Unit {
+        &self.data.selected_chunks[self.index]
+    }
+}
+
+#[pymethods]
+impl Unit {
+    #[getter]
+    fn id(&self) -> u64 {
+        self.unit().id
+    }
+    #[getter]
+    fn cluster_num(&self) -> usize {
+        self.unit().cluster_num
+    }
+    #[getter]
+    fn seq(&self) -> Buffer {
+        Buffer::new(&self.data, Field::UnitSeq(self.index))
+    }
+}
+
+#[pyclass]
+pub struct EncodedReads {
+    data: Arc<DataSet>,
+}
+
+#[pymethods]
+impl EncodedReads {
+    fn __len__(&self) -> usize {
+        self.data.encoded_reads.len()
+    }
+    fn __getitem__(&self, idx: isize) -> PyResult<EncodedRead> {
+        let index = index(idx, self.data.encoded_reads.len())?;
+        let data = self.data.clone();
+        Ok(EncodedRead { data, index })
+    }
+}
+
+#[pyclass]
+pub struct EncodedRead {
+    data: Arc<DataSet>,
+    index: usize,
+}
+
+impl EncodedRead {
+    fn read(&self) -> &definitions::EncodedRead {
+        &self.data.encoded_reads[self.index]
+    }
+}
+
+#[pymethods]
+impl EncodedRead {
+    #[getter]
+    fn id(&self) -> u64 {
+        self.read().id
+    }
+    #[getter]
+    fn original_length(&self) -> usize {
+        self.read().original_length
+    }
+    #[getter]
+    fn leading_gap(&self) -> Buffer {
+        Buffer::new(&self.data, Field::LeadingGap(self.index))
+    }
+    #[getter]
+    fn trailing_gap(&self) -> Buffer {
+        Buffer::new(&self.data, Field::TrailingGap(self.index))
+    }
+    #[getter]
+    fn nodes(&self) -> Nodes {
+        let (data, read) = (self.data.clone(), self.index);
+        Nodes { data, read }
+    }
+    /// The edges as a list of (from, to, offset, label).
+    #[getter]
+    fn edges(&self) -> Vec<(u64, u64, i64, String)> {
+        let edges = self.read().edges.iter();
+        edges
+            .map(|e| (e.from, e.to, e.offset, e.label.clone()))
+            .collect()
+    }
+}
+
+#[pyclass]
+pub struct Nodes {
+    data: Arc<DataSet>,
+    read: usize,
+}
+
+#[pymethods]
+impl Nodes {
+    fn __len__(&self) -> usize {
+        self.data.encoded_reads[self.read].nodes.len()
+    }
+    fn __getitem__(&self, idx: isize) -> PyResult<Node> {
+        let index = index(idx, self.__len__())?;
+        let (data, read) = (self.data.clone(), self.read);
+        Ok(Node { data, read, index })
+    }
+}
+
+#[pyclass]
+pub struct Node {
+    data: Arc<DataSet>,
+    read: usize,
+    index: usize,
+}
+
+impl Node {
+    fn node(&self) -> &definitions::Node {
+        &self.data.encoded_reads[self.read].nodes[self.index]
+    }
+}
+
+#[pymethods]
+impl Node {
+    #[getter]
+    fn position_from_start(&self) -> usize {
+        self.node().position_from_start
+    }
+    #[getter]
+    fn unit(&self) -> u64 {
+        self.node().unit
+    }
+    #[getter]
+    fn cluster(&self) -> u64 {
+        self.node().cluster
+    }
+    #[getter]
+    fn is_forward(&self) -> bool {
+        self.node().is_forward
+    }
+    #[getter]
+    fn seq(&self) -> Buffer {
+        Buffer::new(&self.data, Field::NodeSeq(self.read, self.index))
+    }
+    #[getter]
+    fn qual(&self) -> Option<Buffer> {
+        let qual = self.node().qual.is_some();
+        qual.then(|| Buffer::new(&self.data, Field::NodeQual(self.read, self.index)))
+    }
+    /// The alignment to the unit, as a CIGAR string.
+    #[getter]
+    fn cigar(&self) -> String {
+        use definitions::Op;
+        let cigar = self.node().cigar.iter();
+        cigar
+            .map(|op| match op {
+                Op::Match(l) => format!("{}M", l),
+                Op::Del(l) => format!("{}D", l),
+                Op::Ins(l) => format!("{}I", l),
+            })
+            .collect()
+    }
+}
+
+#[pymodule]
+fn jtk_py(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(load, m)?)?;
+    m.add_class::<PyDataSet>()?;
+    m.add_class::<Buffer>()?;
+    m.add_class::<RawReads>()?;
+    m.add_class::<RawRead>()?;
+    m.add_class::<Units>()?;
+    m.add_class::<Unit>()?;
+    m.add_class::<EncodedReads>()?;
+    m.add_class::<EncodedRead>()?;
+    m.add_class::<Nodes>()?;
+    m.add_class::<Node>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::buffer::PyBuffer;
+    #[test]
+    fn buffer_test() {
+        let read = definitions::RawRead {
+            name: "read".to_string(),
+            desc: String::new(),
+            id: 0,
+            seq: "ACGT".to_string(),
+            qual: Some("IIII".to_string()),
+        };
+        let ds = DataSet::with_minimum_data("test.fa", vec![read], "CCS");
+        let ds = PyDataSet::from(ds);
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let read = ds.raw_reads().__getitem__(-1).unwrap();
+            let seq = Py::new(py, read.seq()).unwrap();
+            let buffer = PyBuffer::<u8>::get(seq.as_ref(py)).unwrap();
+            assert!(buffer.readonly());
+            assert_eq!(
+                buffer.buf_ptr() as *const u8,
+                ds.data.raw_reads[0].seq().as_ptr()
+            );
+            assert_eq!(buffer.to_vec(py).unwrap(), b"ACGT");
+            let locals = PyDict::new(py);
+            locals.set_item("seq", seq).unwrap();
+            locals.set_item("qual", read.qual().into_py(py)).unwrap();
+            let test =
+                "bytes(memoryview(seq)) == b'ACGT' and seq == b'ACGT' and bytes(qual) == b'IIII'";
+            assert!(py
+                .eval(test, None, Some(locals))
+                .unwrap()
+                .is_true()
+                .unwrap());
+        });
+    }
+}
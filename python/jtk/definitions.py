@@ -7,15 +7,18 @@ class DataSet:
     Attributes 
     --------------------
     input_file: path to input file (relative or absolute).
+    coverage: the estimated coverage per haplotype, or None if not estimated yet.
     raw_reads: list of `RawRead`
     selected_chunks: list of `Unit`
     encoded_reads: list of `EncodedRead`
     assginments: List of `Assignments`
     hic_pairs: list of `HiCPair`
     hic_edges: list of `HiCEdge`
+    provenance: list of dictionaries, each of which records a stage applied to this dataset.
+        The `config` of a record is its configuration in JSON. Use `json.loads` to read it.
     """
     def __init__(self, input_file, raw_reads, selected_chunks,
-                 encoded_reads, assignments,hic_pairs, hic_edges, read_type):
+                 encoded_reads, assignments,hic_pairs, hic_edges, read_type, provenance = None, coverage = None):
         """
         The initialization method.
         Usually, users should not call this method directlly.
@@ -30,6 +33,8 @@ class DataSet:
         self.hic_pairs = hic_pairs
         self.hic_edges = hic_edges
         self.read_type = read_type
+        self.provenance = provenance if provenance is not None else []
+        self.coverage = coverage
 
 class RawRead:
     """
@@ -184,11 +189,16 @@ class HiCEdge:
     pair1: int
         The ID number of the unit which one of the hi_c pair hits.
     pair2: int
+    cluster1: int
+        The cluster of the node which one of the hi_c pair hits.
+    cluster2: int
     """
-    def __init__(self, pair_id, pair1, pair2):
+    def __init__(self, pair_id, pair1, pair2, cluster1 = 0, cluster2 = 0):
         self.pair_id = pair_id
         self.pair1 = pair1
         self.pair2 = pair2
+        self.cluster1 = cluster1
+        self.cluster2 = cluster2
 
 class Assignments:
     """
@@ -233,6 +243,8 @@ def as_dataset(dct):
                        hic_pairs = dct['hic_pairs'],
                        hic_edges = dct['hic_edges'],
                        read_type = dct['read_type'],
+                       provenance = dct.get('provenance', []),
+                       coverage = dct.get('coverage'),
         )
     elif 'name' in dct and \
          'desc' in dct and \
@@ -265,14 +277,16 @@ def as_dataset(dct):
                     label = dct['label'])
     elif 'pair_id' in dct and 'seq1' in dct and 'seq2' in dct:
         return HiCPair(pair1 = dct['pair1'],
-                       pair2 = dct['pari2'],
+                       pair2 = dct['pair2'],
                        pair_id = dct['pair_id'],
                        seq1 = dct['seq1'],
                        seq2 = dct['seq2'])
     elif 'pair_id' in dct and 'pair1' in dct and 'pair2' in dct:
         return HiCEdge(pair_id = dct['pair_id'],
                        pair1 = dct['pair1'],
-                       pair2 = dct['pair2'])
+                       pair2 = dct['pair2'],
+                       cluster1 = dct.get('cluster1', 0),
+                       cluster2 = dct.get('cluster2', 0))
     elif 'id' in dct and 'cluster'in dct:
         return Assignments(idn = dct['id'], cluster = dct['cluster'])
     else:
@@ -300,6 +314,8 @@ class DataSetEncoder(json.JSONEncoder):
                 'hic_pairs':list(map(self.default, obj.hic_pairs)),
                 'hic_edges':list(map(self.default, obj.hic_edges)),
                 'read_type': obj.read_type,
+                'provenance': obj.provenance,
+                'coverage': obj.coverage,
             }
         elif isinstance(obj, RawRead):
             return {
@@ -348,9 +364,11 @@ class DataSetEncoder(json.JSONEncoder):
             }
         elif isinstance(obj, HiCEdge):
             return {
-                'pair_id': obj.self_id,
+                'pair_id': obj.pair_id,
                 'pair1': obj.pair1,
                 'pair2': obj.pair2,
+                'cluster1': obj.cluster1,
+                'cluster2': obj.cluster2,
             }
         elif isinstance(obj, Assignments):
             return {
@@ -9,7 +9,7 @@
 set -ue
 PATH="${PATH}:${PWD}/target/release/"
 TARGET=$1
-CLUSTERED=${2}.entry.units.encode.clustered.json
+CLUSTERED=${2}.entry.units.encode.clustered.json.zst
 RESULT=${2}.json
 GFA=${2}.gfa
 DRAFT_GFA=${2}.draft.gfa
@@ -19,11 +19,11 @@ THREADS=23
 jtk entry --input ${TARGET} --read_type CCS |\
     jtk repeat_masking -k 15 -t ${THREADS} -vv |\
     jtk select_unit -vv -t ${THREADS} --take_num 10000 |\
-    jtk encode -vv --threads ${THREADS}|\
-    tee ${2}.entry.json |\
+    jtk encode -vv --threads ${THREADS} --compress zstd |\
+    tee ${2}.entry.json.zst |\
     jtk multiplicity_estimation -vv --threads ${THREADS} \
         --draft_assembly ${DRAFT_GFA} --max_cluster_size 6 |\
-    jtk local_clustering -vv --threads ${THREADS}|\
+    jtk local_clustering -vv --threads ${THREADS} --compress zstd |\
     tee ${CLUSTERED} |\
     jtk clustering_correction -vv --threads ${THREADS} |\
     jtk local_clustering -vv --threads ${THREADS}\